@@ -0,0 +1,23 @@
+//! Compares the hashes available for the local change detection cache. The cache hashes every
+//! file on every run, so the cheaper hash is the default; SHA256 is what the remote reports.
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use sha2::{Digest, Sha256};
+use std::hint::black_box;
+
+fn hashing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash");
+    for size in [4 * 1024, 256 * 1024, 8 * 1024 * 1024] {
+        let content: Vec<u8> = (0..size).map(|i| (i * 31 % 251) as u8).collect();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("sha256", size), &content, |b, content| {
+            b.iter(|| Sha256::digest(black_box(content)))
+        });
+        group.bench_with_input(BenchmarkId::new("blake3", size), &content, |b, content| {
+            b.iter(|| blake3::hash(black_box(content)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hashing);
+criterion_main!(benches);
@@ -0,0 +1,171 @@
+//! Compares building the remote file map from owned listing entries, as thumper used to, with
+//! parsing borrowed entries straight into the map, on synthetic listings of large zones. Also
+//! prints the peak memory of collecting the map, as `list_files` does for planning, against
+//! folding over the files of each listing, as `for_each_file` does for inventories.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/listing.rs"]
+mod listing;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use fxhash::FxHashMap;
+use listing::FileMeta;
+use serde::Deserialize;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the bytes allocated, to tell the peak memory of building a listing
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn allocated(bytes: usize) {
+    let now = ALLOCATED.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let moved = unsafe { System.realloc(ptr, layout, new_size) };
+        if !moved.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            allocated(new_size);
+        }
+        moved
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Most bytes held at once by `work` and what it returns, beyond what was allocated before
+fn peak_bytes<T>(work: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    black_box(work());
+    PEAK.load(Ordering::Relaxed) - before
+}
+
+const ZONE_PREFIX: &str = "/site/";
+const FILES_PER_DIRECTORY: usize = 1000;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FileInfo {
+    path: String,
+    object_name: String,
+    checksum: Option<String>,
+    is_directory: bool,
+    length: u64,
+    #[serde(default)]
+    replicated_zones: Option<String>,
+}
+
+/// One listing per directory, the way the storage API returns them
+fn listings(files: usize) -> Vec<Vec<u8>> {
+    (0..files.div_ceil(FILES_PER_DIRECTORY))
+        .map(|dir| {
+            let entries: Vec<_> = (0..FILES_PER_DIRECTORY)
+                .map(|file| {
+                    format!(
+                        r#"{{"Guid":"{dir}-{file}","StorageZoneName":"site","Path":"/site/assets/{dir}/","ObjectName":"file-{file}.js","Length":1024,"LastChanged":"2025-01-01T00:00:00","ServerId":1,"ArrayNumber":0,"IsDirectory":false,"UserId":"u","ContentType":"","DateCreated":"2025-01-01T00:00:00","StorageZoneId":1,"Checksum":"{:064X}","ReplicatedZones":"DE,NY"}}"#,
+                        dir * FILES_PER_DIRECTORY + file
+                    )
+                })
+                .collect();
+            format!("[{}]", entries.join(",")).into_bytes()
+        })
+        .collect()
+}
+
+fn owned(listings: &[Vec<u8>]) -> FxHashMap<String, FileMeta> {
+    let mut files = FxHashMap::default();
+    for listing in listings {
+        let entries: Vec<FileInfo> = serde_json::from_slice(listing).unwrap();
+        for fi in entries.into_iter().filter(|fi| !fi.is_directory) {
+            let checksum = fi.checksum.as_ref().map(|hex_checksum| {
+                let mut checksum = [0; 32];
+                hex::decode_to_slice(hex_checksum.as_bytes(), &mut checksum).unwrap();
+                checksum
+            });
+            black_box(&fi.replicated_zones);
+            let trim_prefix = format!("/{}/", "site");
+            let name = format!(
+                "{}{}",
+                fi.path.trim_start_matches(trim_prefix.as_str()),
+                fi.object_name
+            );
+            files.insert(name, FileMeta { checksum });
+        }
+    }
+    files
+}
+
+fn borrowed(listings: &[Vec<u8>]) -> FxHashMap<String, FileMeta> {
+    let mut files = FxHashMap::default();
+    for listing in listings {
+        listing::add_listing(listing, ZONE_PREFIX, |_| false, &mut files).unwrap();
+    }
+    files
+}
+
+/// Count the files and bytes one listing at a time, holding no more than a listing in memory
+fn streamed(listings: &[Vec<u8>]) -> (usize, u64) {
+    let mut totals = (0, 0);
+    for listing in listings {
+        let entries: Vec<FileInfo> = serde_json::from_slice(listing).unwrap();
+        for fi in entries.into_iter().filter(|fi| !fi.is_directory) {
+            black_box((&fi.path, &fi.object_name, &fi.checksum));
+            totals = (totals.0 + 1, totals.1 + fi.length);
+        }
+    }
+    totals
+}
+
+fn listing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("listing");
+    group.sample_size(10);
+    for files in [10_000, 100_000] {
+        let listings = listings(files);
+        let mebibytes = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+        println!(
+            "listing/{files}: peak memory {:.1} MiB collected, {:.1} MiB streamed",
+            mebibytes(peak_bytes(|| borrowed(&listings))),
+            mebibytes(peak_bytes(|| streamed(&listings)))
+        );
+        group.throughput(Throughput::Elements(files as u64));
+        group.bench_with_input(
+            BenchmarkId::new("owned", files),
+            &listings,
+            |b, listings| b.iter(|| owned(black_box(listings))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("borrowed", files),
+            &listings,
+            |b, listings| b.iter(|| borrowed(black_box(listings))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("streamed", files),
+            &listings,
+            |b, listings| b.iter(|| streamed(black_box(listings))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, listing);
+criterion_main!(benches);
@@ -0,0 +1,180 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where `--concurrency auto` starts
+const AUTO_START: usize = 2;
+/// The most requests `--concurrency auto` keeps in flight
+const AUTO_MAX: usize = 64;
+/// Back off once the smoothed latency is this many times the best seen so far
+const LATENCY_TOLERANCE: f64 = 2.0;
+/// Weight of the newest latency in the moving average
+const SMOOTHING: f64 = 0.2;
+
+/// The concurrency limit and the signals it is tuned from
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    in_flight: usize,
+    /// Requests completed since the limit last changed
+    completed: usize,
+    /// Whether a request failed since the limit last changed
+    failed: bool,
+    smoothed: Option<f64>,
+    /// The lowest smoothed latency seen, what latency is compared against
+    best: Option<f64>,
+    /// Whether the limit was lowered for latency in the last window, and the average is still
+    /// catching up with the new level
+    settling: bool,
+}
+
+impl State {
+    fn new(limit: usize) -> Self {
+        State {
+            limit,
+            in_flight: 0,
+            completed: 0,
+            failed: false,
+            smoothed: None,
+            best: None,
+            settling: false,
+        }
+    }
+
+    /// Additive increase after a full window of healthy requests, multiplicative decrease on
+    /// errors or when latency climbs, at most once per window
+    fn record(&mut self, latency: Duration, ok: bool) {
+        self.completed += 1;
+        self.failed |= !ok;
+        let seconds = latency.as_secs_f64();
+        let smoothed = match self.smoothed {
+            Some(previous) => previous + SMOOTHING * (seconds - previous),
+            None => seconds,
+        };
+        self.smoothed = Some(smoothed);
+        let best = self.best.map_or(smoothed, |best| best.min(smoothed));
+        self.best = Some(best);
+        if self.completed < self.limit {
+            return;
+        }
+        let slow = !self.settling && smoothed > best * LATENCY_TOLERANCE;
+        if self.failed || slow {
+            self.limit = (self.limit / 2).max(1);
+            // Let the average settle at the new level for a window before comparing again
+            self.settling = slow;
+            self.completed = 0;
+            self.failed = false;
+        } else if self.settling {
+            self.settling = false;
+            self.completed = 0;
+        } else if self.limit < AUTO_MAX {
+            self.limit += 1;
+            self.completed = 0;
+        }
+    }
+}
+
+/// Bounds the number of requests in flight, either at a fixed number or tuned AIMD-style
+/// from observed latency and errors
+pub struct Limiter {
+    state: Mutex<State>,
+    released: Condvar,
+    adaptive: bool,
+}
+
+impl Limiter {
+    pub fn fixed(limit: usize) -> Self {
+        Limiter {
+            state: Mutex::new(State::new(limit)),
+            released: Condvar::new(),
+            adaptive: false,
+        }
+    }
+
+    pub fn adaptive() -> Self {
+        Limiter {
+            state: Mutex::new(State::new(AUTO_START)),
+            released: Condvar::new(),
+            adaptive: true,
+        }
+    }
+
+    /// How many workers it takes to reach the highest limit
+    pub fn workers(&self) -> usize {
+        if self.adaptive {
+            AUTO_MAX
+        } else {
+            self.limit()
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().expect("limiter lock poisoned").limit
+    }
+
+    /// Run `op` once there is room for another request in flight
+    pub fn run<T, E>(&self, op: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let mut state = self.state.lock().expect("limiter lock poisoned");
+        while state.in_flight >= state.limit {
+            state = self.released.wait(state).expect("limiter lock poisoned");
+        }
+        state.in_flight += 1;
+        drop(state);
+
+        let started = Instant::now();
+        let result = op();
+        let mut state = self.state.lock().expect("limiter lock poisoned");
+        state.in_flight -= 1;
+        if self.adaptive {
+            state.record(started.elapsed(), result.is_ok());
+        }
+        drop(state);
+        self.released.notify_all();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_while_healthy_and_halves_on_trouble() {
+        let mut state = State::new(4);
+        let fast = Duration::from_millis(50);
+        for _ in 0..4 {
+            state.record(fast, true);
+        }
+        assert_eq!(state.limit, 5);
+
+        state.record(fast, false);
+        for _ in 0..4 {
+            state.record(fast, true);
+        }
+        assert_eq!(state.limit, 2);
+
+        for _ in 0..2 {
+            state.record(Duration::from_millis(500), true);
+        }
+        assert_eq!(state.limit, 1);
+    }
+
+    #[test]
+    fn compares_latency_against_the_best_seen() {
+        let mut state = State::new(8);
+        for _ in 0..8 {
+            state.record(Duration::from_millis(50), true);
+        }
+        assert_eq!(state.limit, 9);
+        let slow = Duration::from_millis(500);
+        for _ in 0..9 {
+            state.record(slow, true);
+        }
+        assert_eq!(state.limit, 4);
+        // The average settles at the slow level, which is still slow compared to the best
+        for _ in 0..8 {
+            state.record(slow, true);
+        }
+        assert_eq!(state.limit, 2);
+        assert_eq!(state.best, Some(0.05));
+    }
+}
@@ -1,9 +1,28 @@
-use anyhow::anyhow;
-use crossbeam::channel::unbounded;
+use crate::error::ThumperError;
+use crate::http::{HttpTrace, default_client, execute, execute_async};
+use crate::keys::ACCOUNT_KEY_VARS;
+pub use crate::listing::FileMeta;
+use crate::listing::{self, Page};
+use crate::pacing::{DEFAULT_RATE_LIMIT_WAIT, Throttle};
+use crate::planning::PathRules;
+use crate::retry::RetryPolicy;
+use crossbeam::channel::{RecvTimeoutError, bounded, unbounded};
 use fxhash::FxHashMap;
-use reqwest::blocking::Client;
+use reqwest::blocking::{Body, Client, RequestBuilder, Response};
+use reqwest::{Method, StatusCode, Url};
 use serde::Deserialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+
+/// Storage API endpoint of the Falkenstein region, zones in other regions have their own
+pub const DEFAULT_ENDPOINT: &str = "storage.bunnycdn.com";
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -12,71 +31,448 @@ pub struct FileInfo {
     pub object_name: String,
     pub checksum: Option<String>,
     pub is_directory: bool,
+    #[serde(default)]
+    pub replicated_zones: Option<String>,
+    #[serde(default)]
+    pub length: u64,
+    #[serde(default)]
+    pub last_changed: String,
+}
+
+/// An unsuccessful response from bunny.net
+#[derive(Debug)]
+pub struct HttpError {
+    pub method: Method,
+    pub path: String,
+    pub status: StatusCode,
+    /// How long bunny.net asked to wait before sending more requests, with 429 Too Many Requests
+    pub retry_after: Option<Duration>,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} failed: {}", self.method, self.path, self.status)
+    }
 }
 
+impl std::error::Error for HttpError {}
+
+/// An upload that sent none of its body and got no response for too long
 #[derive(Debug)]
-pub struct FileMeta {
-    pub checksum: Option<[u8; 32]>,
+pub struct Stalled {
+    pub path: String,
+    pub after: Duration,
+}
+
+impl fmt::Display for Stalled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PUT {} made no progress for {:?}", self.path, self.after)
+    }
+}
+
+impl std::error::Error for Stalled {}
+
+/// Hands the request body to reqwest, noting when it last took some of it
+struct ProgressReader<R> {
+    body: R,
+    progress: Arc<Mutex<Instant>>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.body.read(buf)?;
+        *self.progress.lock().expect("progress lock poisoned") = Instant::now();
+        Ok(read)
+    }
+}
+
+/// How many times a request rate limited with 429 is sent again before the error is returned
+const RATE_LIMIT_RETRIES: usize = 5;
+
+/// The failure `--chaos` reports in place of sending a request
+fn injected_failure(method: &Method, url: &Url) -> ThumperError {
+    HttpError {
+        method: method.clone(),
+        path: url.path().to_string(),
+        status: StatusCode::SERVICE_UNAVAILABLE,
+        retry_after: None,
+    }
+    .into()
 }
 
+/// Marks a [`StorageZoneClient`] that can only list and read the storage zone
+#[derive(Clone, Copy)]
+pub enum ReadOnly {}
+
+/// Marks a [`StorageZoneClient`] that can also upload and delete
+#[derive(Clone, Copy)]
+pub enum ReadWrite {}
+
+/// Client for the storage API of one zone. Only a `StorageZoneClient<ReadWrite>` has methods that
+/// upload or delete, so code holding a read-only client can't change the zone.
 #[derive(Clone)]
-pub struct StorageZoneClient {
+pub struct StorageZoneClient<Access = ReadWrite> {
     client: Client,
     access_key: String,
     endpoint: String,
     storage_zone: String,
+    default_content_type: String,
+    trace: HttpTrace,
+    key_origin: &'static str,
+    fallback_keys: Vec<String>,
+    /// Index of the key that last authenticated, 0 is `access_key` and the rest are fallbacks
+    active_key: Arc<AtomicUsize>,
+    chaos: Option<f64>,
+    /// How directory listings are retried when they fail on the way
+    retry: RetryPolicy,
+    /// Shared by clones, so one worker being rate limited holds back the others
+    throttle: Arc<Throttle>,
+    access: PhantomData<fn() -> Access>,
+}
+
+/// A directory listing that can't be trusted to hold every file, since planning from it would
+/// upload the missing ones again and never delete them
+fn incomplete(path: &str, reason: &str) -> ThumperError {
+    ThumperError::Integrity(format!(
+        "The listing of {path} is incomplete, {reason}. Refusing to plan from a partial listing"
+    ))
+}
+
+/// Account API keys are two GUIDs run together, storage zone passwords are shorter
+fn looks_like_account_key(key: &str) -> bool {
+    key.len() == 72 && key.chars().all(|ch| ch.is_ascii_hexdigit() || ch == '-')
+}
+
+/// Explain the usual reasons the storage API rejects a key
+fn auth_guidance(key: &str, origin: &str, storage_zone: &str, endpoint: &str) -> String {
+    let from_account_var = ACCOUNT_KEY_VARS
+        .iter()
+        .find(|var| std::env::var(var).is_ok_and(|api_key| api_key == key));
+    let kind = if let Some(var) = from_account_var {
+        format!("is the account API key from {var}")
+    } else if looks_like_account_key(key) {
+        "looks like an account API key".to_string()
+    } else {
+        return format!(
+            "bunny.net rejected the storage zone password from {origin}. Check that it is the \
+             password of storage zone {storage_zone} and not its read-only password, which can't \
+             upload or delete, and that {endpoint} is the endpoint of the zone's primary region"
+        );
+    };
+    format!(
+        "bunny.net rejected the key from {origin}, which {kind}. The storage API needs the \
+         password of storage zone {storage_zone}, found under FTP & API Access in the dashboard"
+    )
 }
 
 impl StorageZoneClient {
     pub fn new(access_key: String, endpoint: String, storage_zone: String) -> Self {
         StorageZoneClient {
-            client: Client::new(),
+            client: default_client(),
             access_key,
             endpoint,
             storage_zone,
+            default_content_type: "application/octet-stream".to_string(),
+            trace: HttpTrace::Off,
+            key_origin: "--access-key",
+            fallback_keys: vec![],
+            active_key: Arc::new(AtomicUsize::new(0)),
+            chaos: None,
+            retry: RetryPolicy::NONE,
+            throttle: Arc::default(),
+            access: PhantomData,
         }
     }
 
-    pub fn read_file(&self, path: &str) -> anyhow::Result<String> {
-        let response = self
-            .client
-            .get(self.url_for(path))
-            .header("AccessKey", self.access_key.as_str())
-            .send()?;
-        if response.status().is_success() {
-            Ok(response.text()?)
+    /// Give up uploading and deleting, for dry runs and commands that only look at the zone
+    pub fn read_only(self) -> StorageZoneClient<ReadOnly> {
+        StorageZoneClient {
+            client: self.client,
+            access_key: self.access_key,
+            endpoint: self.endpoint,
+            storage_zone: self.storage_zone,
+            default_content_type: self.default_content_type,
+            trace: self.trace,
+            key_origin: self.key_origin,
+            fallback_keys: self.fallback_keys,
+            active_key: self.active_key,
+            chaos: self.chaos,
+            retry: self.retry,
+            throttle: self.throttle,
+            access: PhantomData,
+        }
+    }
+}
+
+impl<Access> StorageZoneClient<Access> {
+    /// Keys to try in order when bunny.net answers 401 Unauthorized, like during key rotation
+    pub fn with_fallback_keys(mut self, keys: Vec<String>) -> Self {
+        self.fallback_keys = keys;
+        self
+    }
+
+    fn key(&self, index: usize) -> &str {
+        if index == 0 {
+            self.access_key.as_str()
         } else {
-            Err(anyhow!("Unable to read: {:?}", response.status()))
+            self.fallback_keys[index - 1].as_str()
         }
     }
 
+    /// Where the access key came from, like a flag or environment variable, for error messages
+    pub fn with_key_origin(mut self, origin: &'static str) -> Self {
+        self.key_origin = origin;
+        self
+    }
+
+    pub fn with_trace(mut self, trace: HttpTrace) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Fail this fraction of requests with 503 Service Unavailable without sending them
+    pub fn with_chaos(mut self, chaos: Option<f64>) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Send directory listings that fail with a 5xx response or on the way again, waiting
+    /// longer after each attempt
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Use a preconfigured HTTP client, for example one built with [`crate::http::build_client`]
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Content-Type to send for files where none could be detected
+    pub fn with_default_content_type(mut self, content_type: String) -> Self {
+        self.default_content_type = content_type;
+        self
+    }
+
+    /// Authenticate and send the request, turning unsuccessful status codes into errors. On 401
+    /// the request is sent again with the next fallback key, which is used from then on. On 429
+    /// every request waits out the Retry-After, and this one is sent again if its body allows.
+    fn send(&self, request: RequestBuilder) -> Result<Response, ThumperError> {
+        self.send_rebuilt(request, || None)
+    }
+
+    /// Like [`Self::send`], with `rebuild` making the request again when its body is streamed
+    /// and can't be cloned for another attempt
+    fn send_rebuilt(
+        &self,
+        mut request: RequestBuilder,
+        rebuild: impl Fn() -> Option<RequestBuilder>,
+    ) -> Result<Response, ThumperError> {
+        let mut index = self.active_key.load(Ordering::Relaxed);
+        let mut rate_limited = 0;
+        loop {
+            let retry = request.try_clone();
+            self.throttle.wait();
+            let built = request.header("AccessKey", self.key(index)).build()?;
+            let result = match self.chaos {
+                Some(chance) if rand::random_bool(chance) => {
+                    Err(injected_failure(built.method(), built.url()))
+                }
+                _ => execute(&self.client, self.trace, built),
+            };
+            let err = match result {
+                Ok(response) => {
+                    if self.active_key.fetch_max(index, Ordering::Relaxed) < index {
+                        eprintln!("Authenticated with fallback access key {index}");
+                    }
+                    return Ok(response);
+                }
+                Err(err) => err,
+            };
+            self.hold_off(&err);
+            let status = err.http().map(|err| err.status);
+            match (status, retry.or_else(&rebuild)) {
+                (Some(StatusCode::UNAUTHORIZED), Some(next))
+                    if index < self.fallback_keys.len() =>
+                {
+                    eprintln!(
+                        "WARNING: bunny.net rejected access key {index}, trying the next one"
+                    );
+                    index += 1;
+                    request = next;
+                }
+                (Some(StatusCode::TOO_MANY_REQUESTS), Some(next))
+                    if rate_limited < RATE_LIMIT_RETRIES =>
+                {
+                    rate_limited += 1;
+                    request = next;
+                }
+                _ => return Err(self.explain_rejection(index, err)),
+            }
+        }
+    }
+
+    /// Hold back every request of this client and its clones for as long as a 429 asks
+    fn hold_off(&self, err: &ThumperError) {
+        if let ThumperError::RateLimited(error) = err {
+            let wait = error.retry_after.unwrap_or(DEFAULT_RATE_LIMIT_WAIT);
+            if self.throttle.remaining().is_none() {
+                eprintln!("WARNING: bunny.net is rate limiting requests, holding off for {wait:?}");
+            }
+            self.throttle.pause(wait);
+        }
+    }
+
+    /// Note which key bunny.net rejected, and the usual reasons, on authentication errors
+    fn explain_rejection(&self, index: usize, err: ThumperError) -> ThumperError {
+        let ThumperError::Auth { error, .. } = err else {
+            return err;
+        };
+        let origin = if index == 0 {
+            self.key_origin
+        } else {
+            "--fallback-access-key"
+        };
+        let guidance = auth_guidance(self.key(index), origin, &self.storage_zone, &self.endpoint);
+        ThumperError::Auth {
+            error,
+            guidance: Some(guidance),
+        }
+    }
+
+    pub fn read_file(&self, path: &str) -> Result<String, ThumperError> {
+        let response = self.send(self.client.get(self.url_for(path)))?;
+        Ok(response.text()?)
+    }
+
+    pub fn read_bytes(&self, path: &str) -> Result<Vec<u8>, ThumperError> {
+        let response = self.send(self.client.get(self.url_for(path)))?;
+        Ok(response.bytes()?.to_vec())
+    }
+
     fn url_for(&self, path: &str) -> String {
         format!("https://{}/{}/{path}", self.endpoint, self.storage_zone)
     }
 
-    fn ls_dir(&self, path: &str) -> anyhow::Result<Vec<FileInfo>> {
-        let response = self
-            .client
-            .get(self.url_for(path))
-            .header("AccessKey", self.access_key.as_str())
-            .send()?;
-        Ok(response.json()?)
+    /// Name of a listed object relative to the root of the storage zone
+    pub fn remote_name(&self, fi: &FileInfo) -> String {
+        let trim_prefix = format!("/{}/", self.storage_zone);
+        format!(
+            "{}{}",
+            fi.path.trim_start_matches(trim_prefix.as_str()),
+            fi.object_name
+        )
     }
 
-    fn concurrent_discover_files(
-        &self,
-        path: &str,
-        skip: &[String],
-        concurrency: usize,
-    ) -> anyhow::Result<Vec<FileInfo>> {
+    /// One response to listing `path` from `url`, retried according to the retry policy, and how
+    /// many entries its headers say the directory has
+    fn fetch_page(&self, path: &str, url: &str) -> Result<(Vec<u8>, Option<u64>), ThumperError> {
+        self.retry.run(
+            || {
+                let response = self.send(self.client.get(url))?;
+                let header = |name| {
+                    let value = response.headers().get(name)?;
+                    value.to_str().ok()
+                };
+                if header("link").is_some_and(|link| link.contains("rel=\"next\"")) {
+                    return Err(incomplete(path, "the response links to a next page"));
+                }
+                let total = header("x-total-count").and_then(|total| total.parse().ok());
+                Ok((response.bytes()?.to_vec(), total))
+            },
+            |attempt, err| {
+                eprintln!(
+                    "WARNING: listing {path} failed on attempt {attempt}/{}, retrying: {err}",
+                    self.retry.retries + 1
+                )
+            },
+        )
+    }
+
+    /// The JSON array listing the directory `path`. A listing that comes in pages is collected
+    /// page by page, and one that says it is missing entries is an error rather than a smaller
+    /// directory.
+    fn fetch_listing(&self, path: &str) -> Result<Vec<u8>, ThumperError> {
+        let unrecognized = |err: anyhow::Error| {
+            ThumperError::Integrity(format!("Unable to read the listing of {path}: {err:#}"))
+        };
+        let mut items = vec![];
+        let mut expected = None;
+        for page in 1.. {
+            let url = match page {
+                1 => self.url_for(path),
+                _ => format!("{}?page={page}", self.url_for(path)),
+            };
+            let (body, total) = self.fetch_page(path, &url)?;
+            match listing::parse_page(&body).map_err(unrecognized)? {
+                Page::Complete if page == 1 => {
+                    // Only count the entries when there is a total to compare with
+                    let listed = match total {
+                        Some(_) => listing::count_entries(&body).map_err(unrecognized)?,
+                        None => 0,
+                    };
+                    if let Some(total) = total
+                        && (listed as u64) < total
+                    {
+                        return Err(incomplete(
+                            path,
+                            &format!("it has {listed} of {total} entries"),
+                        ));
+                    }
+                    return Ok(body);
+                }
+                Page::Complete => {
+                    return Err(incomplete(path, &format!("page {page} is not a page")));
+                }
+                Page::Partial {
+                    items: listed,
+                    total: page_total,
+                    more,
+                } => {
+                    let empty = listed.is_empty();
+                    items.extend(listed);
+                    expected = page_total.or(total).or(expected);
+                    if !more {
+                        break;
+                    }
+                    if empty {
+                        return Err(incomplete(
+                            path,
+                            &format!("page {page} is empty but says more follow"),
+                        ));
+                    }
+                }
+            }
+        }
+        if let Some(total) = expected
+            && (items.len() as u64) < total
+        {
+            return Err(incomplete(
+                path,
+                &format!("its pages have {} of {total} entries", items.len()),
+            ));
+        }
+        serde_json::to_vec(&items).map_err(|err| unrecognized(err.into()))
+    }
+
+    pub fn ls_dir(&self, path: &str) -> Result<Vec<FileInfo>, ThumperError> {
+        serde_json::from_slice(&self.fetch_listing(path)?)
+            .map_err(|err| ThumperError::Transport(Box::new(err)))
+    }
+
+    /// Walk the tree below `path` with `concurrency` workers fetching directory listings, handing
+    /// each listing to `on_listing` as it arrives. `on_listing` returns the subdirectories to walk.
+    fn walk<F>(&self, path: &str, concurrency: usize, mut on_listing: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&[u8]) -> anyhow::Result<Vec<String>>,
+    {
         let (post_work, receive_work) = unbounded();
         let (post_result, receive_result) = unbounded();
 
         post_work.send(path.to_string())?;
 
         thread::scope(|scope| {
-            let mut files = vec![];
-
             // Spawn workers
             let mut workers = Vec::with_capacity(concurrency);
             for _ in 0..concurrency {
@@ -84,108 +480,308 @@ impl StorageZoneClient {
                 let send_result = post_result.clone();
                 workers.push(scope.spawn(move || {
                     while let Ok(path) = receive_work.recv() {
-                        send_result.send(self.ls_dir(path.as_str()))?;
+                        let listing = self.fetch_listing(&path);
+                        send_result.send(listing)?;
                     }
                     // Channel closed
                     Ok::<(), anyhow::Error>(())
                 }));
             }
 
-            let global_prefix = format!("/{}/", self.storage_zone);
             let mut responses_needed = 1;
 
             while responses_needed > 0 {
-                let new = receive_result.recv()??;
+                let listing = receive_result.recv()??;
                 responses_needed -= 1;
-                for child in new {
-                    if child.is_directory {
-                        let subtree = format!(
-                            "{}/{}/",
-                            child
-                                .path
-                                .trim_start_matches(global_prefix.as_str())
-                                .trim_end_matches('/'),
-                            child.object_name.as_str()
-                        );
-                        if skip.iter().any(|skip| subtree.starts_with(skip)) {
-                            continue;
-                        }
-                        responses_needed += 1;
-                        post_work.send(subtree)?;
-                    } else {
-                        files.push(child);
-                    }
+                for subdirectory in on_listing(&listing)? {
+                    responses_needed += 1;
+                    post_work.send(subdirectory)?;
                 }
             }
             // Close channel to shut down workers
             drop(post_work);
-            Ok::<Vec<_>, anyhow::Error>(files)
+            Ok::<(), anyhow::Error>(())
+        })
+    }
+
+    /// Walk the tree below `path`, handing each file to `on_file` as its directory listing
+    /// arrives instead of collecting them all first
+    pub fn for_each_file<F>(
+        &self,
+        path: &str,
+        skip: &[String],
+        concurrency: usize,
+        mut on_file: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(FileInfo) -> anyhow::Result<()>,
+    {
+        let global_prefix = format!("/{}/", self.storage_zone);
+        let skip = PathRules::new(skip);
+        self.walk(path, concurrency, |listing| {
+            let mut subdirectories = vec![];
+            for child in serde_json::from_slice::<Vec<FileInfo>>(listing)? {
+                if child.is_directory {
+                    let subtree =
+                        listing::subdirectory(&child.path, &global_prefix, &child.object_name);
+                    if listing::descend(&subtree, |name| skip.matches(name)) {
+                        subdirectories.push(subtree);
+                    }
+                } else {
+                    on_file(child)?;
+                }
+            }
+            Ok(subdirectories)
         })
     }
 
+    /// Every file below `path` by name relative to the zone root, parsed straight from the
+    /// listings into the map. Planning needs all of them to tell what to delete, use
+    /// [`Self::for_each_file`] where one file at a time will do
     pub fn list_files(
         &self,
         path: &str,
         skip: &[String],
         concurrency: usize,
     ) -> anyhow::Result<FxHashMap<String, FileMeta>> {
-        let files = self.concurrent_discover_files(path, skip, concurrency)?;
+        let global_prefix = format!("/{}/", self.storage_zone);
         let mut files_by_name = FxHashMap::default();
-        let trim_prefix = format!("/{}/", self.storage_zone);
-        for fi in files {
-            let checksum = fi
-                .checksum
-                .map(|hex_checksum| {
-                    let mut checksum = [0; 32];
-                    hex::decode_to_slice(hex_checksum.as_bytes(), &mut checksum)?;
-                    Ok::<[u8; 32], anyhow::Error>(checksum)
-                })
-                .transpose()?;
-            files_by_name.insert(
-                format!(
-                    "{}{}",
-                    fi.path.trim_start_matches(trim_prefix.as_str()),
-                    fi.object_name
-                ),
-                FileMeta { checksum },
-            );
-        }
+        let skip = PathRules::new(skip);
+        self.walk(path, concurrency, |listing| {
+            listing::add_listing(
+                listing,
+                &global_prefix,
+                |name| skip.matches(name),
+                &mut files_by_name,
+            )
+        })?;
         Ok(files_by_name)
     }
+}
+
+impl StorageZoneClient<ReadWrite> {
+    /// An async client for uploads and deletes with the same keys and settings, sending requests
+    /// with `client`
+    pub fn to_async(&self, client: reqwest::Client) -> AsyncStorageZoneClient {
+        AsyncStorageZoneClient {
+            zone: self.clone(),
+            client,
+        }
+    }
 
     pub fn put_file(
         &self,
         path: &str,
         body: Vec<u8>,
         content_type: Option<&str>,
-    ) -> anyhow::Result<()> {
-        let url = self.url_for(path);
+    ) -> Result<(), ThumperError> {
+        let request = self.put_request(path, content_type).body(body);
+        self.send(request).map(|_| ())
+    }
 
-        let response = self
-            .client
-            .put(url)
-            .header("AccessKey", self.access_key.as_str())
-            .header(
-                "Content-Type",
-                content_type.unwrap_or("application/octet-stream"),
+    fn put_request(&self, path: &str, content_type: Option<&str>) -> RequestBuilder {
+        self.client.put(self.url_for(path)).header(
+            "Content-Type",
+            content_type.unwrap_or(self.default_content_type.as_str()),
+        )
+    }
+
+    /// Like [`Self::put_file`], but give up with [`Stalled`] when no part of the body is sent
+    /// and no response arrives for `stall`. The stuck request can't be cancelled, so it is left
+    /// to finish or fail on its own thread, holding on to its connection.
+    pub fn put_file_watched(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: Option<&str>,
+        stall: Duration,
+    ) -> Result<(), ThumperError> {
+        let length = body.len() as u64;
+        let body: Arc<[u8]> = body.into();
+        let reread = move || Ok(io::Cursor::new(body.clone()));
+        self.put_watched(path, reread, length, content_type, stall)
+    }
+
+    /// Upload the file at `local` as it is read, without holding all of it in memory. The file is
+    /// opened again when the upload is retried with a fallback key or after 429 Too Many Requests.
+    /// Watched for stalls like [`Self::put_file_watched`] if `stall` is set.
+    pub fn put_local_file(
+        &self,
+        path: &str,
+        local: &Path,
+        content_type: Option<&str>,
+        stall: Option<Duration>,
+    ) -> Result<(), ThumperError> {
+        let length = File::open(local)?.metadata()?.len();
+        let local = local.to_path_buf();
+        let reopen = move || File::open(&local);
+        match stall {
+            Some(stall) => self.put_watched(path, reopen, length, content_type, stall),
+            None => {
+                let put = self.put_request(path, content_type);
+                let request = move || {
+                    let file = reopen()?;
+                    Ok::<_, io::Error>(
+                        put.try_clone()
+                            .expect("a request without a body can be cloned")
+                            .body(Body::sized(file, length)),
+                    )
+                };
+                self.send_rebuilt(request()?, || request().ok()).map(|_| ())
+            }
+        }
+    }
+
+    /// Upload the body made by `body`, which is called again for each retry
+    fn put_watched<R: Read + Send + 'static>(
+        &self,
+        path: &str,
+        body: impl Fn() -> io::Result<R> + Send + 'static,
+        length: u64,
+        content_type: Option<&str>,
+        stall: Duration,
+    ) -> Result<(), ThumperError> {
+        let progress = Arc::new(Mutex::new(Instant::now()));
+        let put = self.put_request(path, content_type);
+        let watched = progress.clone();
+        let request = move || {
+            let reader = ProgressReader {
+                body: body()?,
+                progress: watched.clone(),
+            };
+            Ok::<_, io::Error>(
+                put.try_clone()
+                    .expect("a request without a body can be cloned")
+                    .body(Body::sized(reader, length)),
             )
-            .body(body)
-            .send()?;
+        };
+        let first = request()?;
+        let (send_done, done) = bounded(1);
+        let client = self.clone();
+        thread::spawn(move || {
+            let result = client.send_rebuilt(first, || request().ok());
+            // Nobody is waiting for the result once the upload is declared stalled
+            let _ = send_done.send(result.map(|_| ()));
+        });
+        loop {
+            if self.throttle.remaining().is_some() {
+                // Waiting out a 429 before sending the upload again is not a stall
+                *progress.lock().expect("progress lock poisoned") = Instant::now();
+            }
+            let idle = progress.lock().expect("progress lock poisoned").elapsed();
+            if idle >= stall {
+                return Err(Stalled {
+                    path: path.to_string(),
+                    after: stall,
+                }
+                .into());
+            }
+            match done.recv_timeout(stall - idle) {
+                Ok(result) => return result,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(ThumperError::Transport(
+                        format!("Upload of {path} panicked").into(),
+                    ));
+                }
+            }
+        }
+    }
 
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            Err(anyhow!("Request failed: {:?}", response.status()))
+    pub fn delete_file(&self, path: &str) -> Result<(), ThumperError> {
+        self.send(self.client.delete(self.url_for(path)))
+            .map(|_| ())
+    }
+
+    /// Delete a directory along with everything below it
+    pub fn delete_dir(&self, path: &str) -> Result<(), ThumperError> {
+        let path = format!("{}/", path.trim_end_matches('/'));
+        self.delete_file(&path)
+    }
+}
+
+/// Uploads and deletes from a tokio runtime, so many requests can be in flight without a thread
+/// for each. Made with [`StorageZoneClient::to_async`], and shares the active key with it.
+#[derive(Clone)]
+pub struct AsyncStorageZoneClient {
+    zone: StorageZoneClient<ReadWrite>,
+    client: reqwest::Client,
+}
+
+impl AsyncStorageZoneClient {
+    /// Like [`StorageZoneClient::send`]
+    async fn send(
+        &self,
+        mut request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, ThumperError> {
+        let zone = &self.zone;
+        let mut index = zone.active_key.load(Ordering::Relaxed);
+        let mut rate_limited = 0;
+        loop {
+            let retry = request.try_clone();
+            if let Some(remaining) = zone.throttle.remaining() {
+                tokio::time::sleep(remaining).await;
+            }
+            let built = request.header("AccessKey", zone.key(index)).build()?;
+            let result = match zone.chaos {
+                Some(chance) if rand::random_bool(chance) => {
+                    Err(injected_failure(built.method(), built.url()))
+                }
+                _ => execute_async(&self.client, zone.trace, built).await,
+            };
+            let err = match result {
+                Ok(response) => {
+                    if zone.active_key.fetch_max(index, Ordering::Relaxed) < index {
+                        eprintln!("Authenticated with fallback access key {index}");
+                    }
+                    return Ok(response);
+                }
+                Err(err) => err,
+            };
+            zone.hold_off(&err);
+            let status = err.http().map(|err| err.status);
+            match (status, retry) {
+                (Some(StatusCode::UNAUTHORIZED), Some(next))
+                    if index < zone.fallback_keys.len() =>
+                {
+                    eprintln!(
+                        "WARNING: bunny.net rejected access key {index}, trying the next one"
+                    );
+                    index += 1;
+                    request = next;
+                }
+                (Some(StatusCode::TOO_MANY_REQUESTS), Some(next))
+                    if rate_limited < RATE_LIMIT_RETRIES =>
+                {
+                    rate_limited += 1;
+                    request = next;
+                }
+                _ => return Err(zone.explain_rejection(index, err)),
+            }
         }
     }
 
-    pub fn delete_file(&self, path: &str) -> anyhow::Result<()> {
-        let response = self
+    pub async fn put_file(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<(), ThumperError> {
+        let request = self
             .client
-            .delete(self.url_for(path))
-            .header("AccessKey", self.access_key.as_str())
-            .send()?;
-        Ok(response.error_for_status().map(|_| ())?)
+            .put(self.zone.url_for(path))
+            .header(
+                "Content-Type",
+                content_type.unwrap_or(self.zone.default_content_type.as_str()),
+            )
+            .body(body);
+        self.send(request).await.map(|_| ())
+    }
+
+    pub async fn delete_file(&self, path: &str) -> Result<(), ThumperError> {
+        self.send(self.client.delete(self.zone.url_for(path)))
+            .await
+            .map(|_| ())
     }
 }
 
@@ -211,4 +807,95 @@ mod tests {
     fn test_parse() {
         let _: FileInfo = serde_json::from_str(EX).unwrap();
     }
+
+    #[test]
+    fn fails_requests_on_purpose() {
+        let client = StorageZoneClient::new(
+            "key".to_string(),
+            "storage.invalid".to_string(),
+            "docs".to_string(),
+        )
+        .with_chaos(Some(1.0));
+        let err = client.read_file("index.html").unwrap_err();
+        let http = err.http().unwrap();
+        assert_eq!(http.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(http.path, "/docs/index.html");
+    }
+
+    #[test]
+    fn read_only_clients_keep_their_settings() {
+        let client = StorageZoneClient::new(
+            "key".to_string(),
+            "storage.invalid".to_string(),
+            "docs".to_string(),
+        )
+        .with_chaos(Some(1.0))
+        .read_only();
+        assert_eq!(
+            client.url_for("index.html"),
+            "https://storage.invalid/docs/index.html"
+        );
+        assert!(client.read_file("index.html").unwrap_err().http().is_some());
+    }
+
+    #[test]
+    fn gives_up_on_stalled_uploads() {
+        // Accepts the connection, but never answers the TLS handshake
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let client = StorageZoneClient::new("key".to_string(), endpoint, "docs".to_string());
+        let started = Instant::now();
+        let err = client
+            .put_file_watched(
+                "index.html",
+                b"<html>".to_vec(),
+                None,
+                Duration::from_millis(200),
+            )
+            .unwrap_err();
+        assert!(err.is_stalled());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(listener);
+    }
+
+    #[test]
+    fn waits_out_rate_limits_before_declaring_uploads_stalled() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let client = StorageZoneClient::new("key".to_string(), endpoint, "docs".to_string());
+        client.throttle.pause(Duration::from_millis(600));
+        let started = Instant::now();
+        let err = client
+            .put_file_watched(
+                "index.html",
+                b"<html>".to_vec(),
+                None,
+                Duration::from_millis(200),
+            )
+            .unwrap_err();
+        assert!(err.is_stalled());
+        assert!(started.elapsed() >= Duration::from_millis(600));
+        drop(listener);
+    }
+
+    #[test]
+    fn tells_account_keys_from_zone_passwords() {
+        let account_key =
+            "a1b2c3d4-e5f6-4789-abcd-ef0123456789a1b2c3d4-e5f6-4789-abcd-ef0123456789";
+        assert!(looks_like_account_key(account_key));
+        assert!(!looks_like_account_key(
+            "0d3b2c1a-9f8e-4d7c-b6a5e4d3c2b1-a0f9-4e8d"
+        ));
+
+        let guidance = auth_guidance(account_key, "THUMPER_KEY", "docs", "storage.bunnycdn.com");
+        assert!(guidance.contains("THUMPER_KEY"));
+        assert!(guidance.contains("looks like an account API key"));
+        let guidance = auth_guidance(
+            "zone-password",
+            "--access-key",
+            "docs",
+            "storage.bunnycdn.com",
+        );
+        assert!(guidance.contains("read-only password"));
+    }
 }
@@ -0,0 +1,181 @@
+use crate::source::{FileSource, MemorySource, RemoteNames, remote_name};
+use anyhow::{Context, anyhow};
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use tar::{Archive, EntryType};
+use zip::ZipArchive;
+
+/// `name` if it stays inside the archive, failing on `..` and absolute paths, which would end up
+/// outside --path in the storage zone
+fn enclosed(name: &str) -> anyhow::Result<&str> {
+    let inside = Path::new(name)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if inside {
+        Ok(name)
+    } else {
+        Err(anyhow!("{name} in the archive points outside of it"))
+    }
+}
+
+/// Files inside a zip archive, read straight from the archive without unpacking it
+pub struct ZipSource<R = File> {
+    archive: Mutex<ZipArchive<R>>,
+}
+
+impl ZipSource {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Unable to open {}", path.display()))?;
+        Self::new(file).with_context(|| format!("{} is not a valid zip archive", path.display()))
+    }
+}
+
+impl<R: Read + Seek> ZipSource<R> {
+    pub fn new(reader: R) -> anyhow::Result<Self> {
+        Ok(ZipSource {
+            archive: Mutex::new(ZipArchive::new(reader)?),
+        })
+    }
+}
+
+impl<R: Read + Seek + Send> FileSource for ZipSource<R> {
+    fn files_by_remote_name(&self, root: &str, remote_root: &str) -> anyhow::Result<RemoteNames> {
+        let archive = self.archive.lock().expect("zip archive lock poisoned");
+        let mut names = vec![];
+        for name in archive.file_names().filter(|name| !name.ends_with('/')) {
+            if let Some(relative) = enclosed(name)?.strip_prefix(root) {
+                names.push((remote_name(remote_root, relative), PathBuf::from(name)));
+            }
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let name = path.to_str().ok_or(io::ErrorKind::InvalidInput)?;
+        let mut archive = self.archive.lock().expect("zip archive lock poisoned");
+        let mut entry = archive.by_name(name).map_err(io::Error::other)?;
+        // The size in the header may be corrupt, so let the content grow as it is read
+        let mut content = vec![];
+        entry.read_to_end(&mut content)?;
+        Ok(content)
+    }
+}
+
+/// Open a `.tar`, or a gzipped `.tar.gz` or `.tgz`. Tar can only be read sequentially, so the
+/// entries are read into memory up front.
+pub fn open_tar(path: &Path) -> anyhow::Result<MemorySource> {
+    let file = File::open(path).with_context(|| format!("Unable to open {}", path.display()))?;
+    let name = path.to_string_lossy();
+    if name.ends_with(".gz") || name.ends_with(".tgz") {
+        read_tar(GzDecoder::new(file))
+    } else {
+        read_tar(file)
+    }
+    .with_context(|| format!("{} is not a valid tar archive", path.display()))
+}
+
+pub fn read_tar<R: Read>(reader: R) -> anyhow::Result<MemorySource> {
+    let mut files = MemorySource::default();
+    for entry in Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type() != EntryType::Regular {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let name = enclosed(&name)?.trim_start_matches("./");
+        // Like in zip archives, the size in the header may be corrupt
+        let mut content = vec![];
+        entry.read_to_end(&mut content)?;
+        files.insert(name, content);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    fn zip_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn lists_and_reads_zip_entries() {
+        let zip = zip_of(&[("index.html", "<html>"), ("docs/guide.html", "guide")]);
+        let source = ZipSource::new(Cursor::new(zip)).unwrap();
+
+        let files = source.files_by_remote_name("", "site/").unwrap().files;
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            source.read(&files["site/docs/guide.html"]).unwrap(),
+            b"guide"
+        );
+
+        let docs = source.files_by_remote_name("docs/", "docs/").unwrap().files;
+        assert_eq!(docs.keys().collect::<Vec<_>>(), vec!["docs/guide.html"]);
+    }
+
+    #[test]
+    fn rejects_zip_entries_outside_the_archive() {
+        for name in [
+            "../escape.html",
+            "/etc/escape.html",
+            "docs/../../escape.html",
+        ] {
+            let zip = zip_of(&[("index.html", "<html>"), (name, "escape")]);
+            let source = ZipSource::new(Cursor::new(zip)).unwrap();
+            let err = source.files_by_remote_name("", "site/").err().unwrap();
+            assert!(err.to_string().contains("outside"), "{name}: {err}");
+        }
+    }
+
+    #[test]
+    fn reads_gzipped_tar_entries() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            vec![],
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "./docs/guide.html", "guide".as_bytes())
+            .unwrap();
+        let tgz = builder.into_inner().unwrap().finish().unwrap();
+
+        let source = read_tar(GzDecoder::new(Cursor::new(tgz))).unwrap();
+        let files = source.files_by_remote_name("", "/").unwrap().files;
+        assert_eq!(source.read(&files["docs/guide.html"]).unwrap(), b"guide");
+    }
+
+    #[test]
+    fn rejects_tar_entries_outside_the_archive() {
+        for name in ["../escape.html", "/etc/escape.html"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(6);
+            header.set_mode(0o644);
+            // The builder refuses to write such names, so set them in the header directly
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_cksum();
+            let mut builder = tar::Builder::new(vec![]);
+            builder.append(&header, "escape".as_bytes()).unwrap();
+            let tar = builder.into_inner().unwrap();
+            let err = read_tar(Cursor::new(tar)).err().unwrap();
+            assert!(err.to_string().contains("outside"), "{name}: {err}");
+        }
+    }
+}
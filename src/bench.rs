@@ -0,0 +1,197 @@
+use crate::api::StorageZoneClient;
+use crate::error::ThumperError;
+use anyhow::anyhow;
+use crossbeam::channel::unbounded;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Parse sizes like `512`, `4k`, `64KiB` or `1m` into a number of bytes
+pub fn parse_size(size: &str) -> anyhow::Result<usize> {
+    let lower = size.trim().to_ascii_lowercase();
+    let digits = lower
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(digits);
+    let number: usize = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid size: {size}"))?;
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        _ => return Err(anyhow!("Invalid size unit in {size}")),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("Size too large: {size}"))
+}
+
+pub fn format_bytes(bytes: f64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", units[unit])
+}
+
+/// Pick the p-th percentile (0.0..=1.0) from sorted latencies
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+/// Deterministic filler so that uploads are not trivially compressible
+fn synthetic_content(size: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+struct Measurement {
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+}
+
+fn measure<F>(names: &[String], concurrency: usize, op: F) -> anyhow::Result<Measurement>
+where
+    F: Fn(&str) -> anyhow::Result<()> + Sync,
+{
+    let (send_work, receive_work) = unbounded();
+    let (send_result, receive_result) = unbounded();
+    let start = Instant::now();
+
+    let mut latencies = thread::scope(|scope| {
+        for index in 0..names.len() {
+            send_work.send(index)?;
+        }
+        drop(send_work);
+
+        for _ in 0..concurrency {
+            let receive_work = receive_work.clone();
+            let send_result = send_result.clone();
+            let op = &op;
+            scope.spawn(move || {
+                while let Ok(index) = receive_work.recv() {
+                    let started = Instant::now();
+                    let r = op(names[index].as_str()).map(|_| started.elapsed());
+                    send_result.send(r)?;
+                }
+                Ok::<(), anyhow::Error>(())
+            });
+        }
+
+        let mut latencies = Vec::with_capacity(names.len());
+        for _ in 0..names.len() {
+            latencies.push(receive_result.recv()??);
+        }
+        Ok::<_, anyhow::Error>(latencies)
+    })?;
+    latencies.sort();
+    Ok(Measurement {
+        elapsed: start.elapsed(),
+        latencies,
+    })
+}
+
+fn report(op: &str, size: usize, concurrency: usize, measurement: &Measurement) {
+    let count = measurement.latencies.len();
+    let seconds = measurement.elapsed.as_secs_f64().max(f64::EPSILON);
+    let rate = count as f64 / seconds;
+    let throughput = if op == "put" {
+        format!(", {}/s", format_bytes((size * count) as f64 / seconds))
+    } else {
+        String::new()
+    };
+    let lat = &measurement.latencies;
+    println!(
+        "{op:>6} {:>10} x{count} @ concurrency {concurrency:>3}: {rate:.1} req/s{throughput}, p50 {:?} p90 {:?} p99 {:?} max {:?}",
+        format_bytes(size as f64),
+        percentile(lat, 0.5),
+        percentile(lat, 0.9),
+        percentile(lat, 0.99),
+        percentile(lat, 1.0),
+    );
+}
+
+pub struct BenchPlan {
+    pub path: String,
+    pub sizes: Vec<usize>,
+    pub count: usize,
+    pub concurrency: Vec<usize>,
+}
+
+/// Delete what a failed benchmark may have left in the zone, skipping files that aren't there
+fn clean_up(client: &StorageZoneClient, names: &[String]) {
+    for name in names {
+        match client.delete_file(name) {
+            Ok(()) | Err(ThumperError::NotFound(_)) => {}
+            Err(err) => eprintln!("WARNING: Unable to delete {name}: {err}"),
+        }
+    }
+}
+
+pub fn run(client: &StorageZoneClient, plan: &BenchPlan) -> anyhow::Result<()> {
+    for &size in &plan.sizes {
+        for &concurrency in &plan.concurrency {
+            let names: Vec<_> = (0..plan.count)
+                .map(|i| format!("{}{size}-{concurrency}-{i}.bin", plan.path))
+                .collect();
+            let content = synthetic_content(size, (size * concurrency) as u64);
+
+            let puts = measure(&names, concurrency, |name| {
+                Ok(client.put_file(name, content.clone(), Some("application/octet-stream"))?)
+            })
+            .inspect_err(|_| clean_up(client, &names))?;
+            report("put", size, concurrency, &puts);
+
+            let deletes = measure(&names, concurrency, |name| Ok(client.delete_file(name)?))
+                .inspect_err(|_| clean_up(client, &names))?;
+            report("delete", size, concurrency, &deletes);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("4k").unwrap(), 4096);
+        assert_eq!(parse_size("64KiB").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("1m").unwrap(), 1024 * 1024);
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("5x").is_err());
+        assert!(parse_size("99999999999999999g").is_err());
+    }
+
+    #[test]
+    fn picks_percentiles() {
+        let lat: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&lat, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&lat, 0.5), Duration::from_millis(51));
+        assert_eq!(percentile(&lat, 1.0), Duration::from_millis(100));
+        assert_eq!(percentile(&[], 0.5), Duration::ZERO);
+    }
+
+    #[test]
+    fn synthetic_content_has_requested_size() {
+        assert_eq!(synthetic_content(1000, 1).len(), 1000);
+        assert_ne!(synthetic_content(64, 1), synthetic_content(64, 2));
+    }
+}
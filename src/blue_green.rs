@@ -0,0 +1,94 @@
+use crate::bunny_api::BunnyApiClient;
+use crate::pullzone::get_pull_zone;
+use crate::purge::purge_pull_zone;
+use anyhow::anyhow;
+use serde_json::{Map, Value};
+
+/// The slots a blue/green deploy alternates between, below `slots/` in the storage zone
+const SLOTS: [&str; 2] = ["blue", "green"];
+
+/// Where a pull zone's origin points now, and where it should point after the next deploy
+#[derive(Debug, PartialEq, Eq)]
+pub struct Slots {
+    base: String,
+    pub active: &'static str,
+    pub inactive: &'static str,
+}
+
+impl Slots {
+    /// The slots after flipping, to flip back
+    pub fn flipped(&self) -> Slots {
+        Slots {
+            base: self.base.clone(),
+            active: self.inactive,
+            inactive: self.active,
+        }
+    }
+
+    /// Remote path the inactive slot is synced to
+    pub fn inactive_path(&self, path: &str) -> String {
+        format!("slots/{}/{}", self.inactive, path.trim_start_matches('/'))
+    }
+}
+
+/// Split an origin URL ending in `/slots/blue` or `/slots/green`
+fn parse_origin(origin_url: &str) -> Option<Slots> {
+    let (base, slot) = origin_url.trim_end_matches('/').rsplit_once("/slots/")?;
+    let active = SLOTS.into_iter().find(|candidate| *candidate == slot)?;
+    let inactive = SLOTS.into_iter().find(|candidate| *candidate != slot)?;
+    Some(Slots {
+        base: base.to_string(),
+        active,
+        inactive,
+    })
+}
+
+/// Find the slot the pull zone serves, so the other one can be synced
+pub fn slots(api: &BunnyApiClient, pullzone: u64) -> anyhow::Result<Slots> {
+    let zone = get_pull_zone(api, pullzone)?;
+    let origin_url = zone
+        .get("OriginUrl")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    parse_origin(origin_url).ok_or_else(|| {
+        anyhow!(
+            "The origin URL of pull zone {pullzone} must end with /slots/blue or /slots/green, it is {origin_url}"
+        )
+    })
+}
+
+/// Point the pull zone at the freshly synced slot and purge what it cached from the old one
+pub fn flip(api: &BunnyApiClient, pullzone: u64, slots: &Slots) -> anyhow::Result<()> {
+    let mut settings = Map::new();
+    settings.insert(
+        "OriginUrl".to_string(),
+        format!("{}/slots/{}", slots.base, slots.inactive).into(),
+    );
+    api.post(&format!("pullzone/{pullzone}"), &settings)?;
+    purge_pull_zone(api, pullzone)?;
+    eprintln!(
+        "Pull zone {pullzone} now serves slot {} instead of {}",
+        slots.inactive, slots.active
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternates_slots() {
+        let slots = parse_origin("https://site.b-cdn.net/slots/blue/").unwrap();
+        assert_eq!(slots.base, "https://site.b-cdn.net");
+        assert_eq!((slots.active, slots.inactive), ("blue", "green"));
+        assert_eq!(slots.inactive_path("/"), "slots/green/");
+        assert_eq!(slots.inactive_path("docs/"), "slots/green/docs/");
+
+        let slots = parse_origin("https://site.b-cdn.net/slots/green").unwrap();
+        assert_eq!(slots.inactive, "blue");
+        assert_eq!(slots.flipped().inactive, "green");
+        assert!(parse_origin("https://site.b-cdn.net/").is_none());
+        assert!(parse_origin("https://site.b-cdn.net/slots/red").is_none());
+    }
+}
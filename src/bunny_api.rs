@@ -0,0 +1,130 @@
+use crate::error::ThumperError;
+use crate::http::{HttpTrace, default_client, execute};
+use reqwest::Method;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+const API_BASE: &str = "https://api.bunny.net";
+
+/// The envelope bunny.net uses for listing endpoints
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    #[serde(default)]
+    pub has_more_items: bool,
+}
+
+/// Client for the account level bunny.net API, authenticated with the account API key
+#[derive(Clone)]
+pub struct BunnyApiClient {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    trace: HttpTrace,
+}
+
+impl BunnyApiClient {
+    pub fn new(api_key: String) -> Self {
+        BunnyApiClient {
+            client: default_client(),
+            api_key,
+            base_url: API_BASE.to_string(),
+            trace: HttpTrace::Off,
+        }
+    }
+
+    /// Use a preconfigured HTTP client, for example one built with [`crate::http::build_client`]
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_trace(mut self, trace: HttpTrace) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Point the client at another bunny.net API, like the Stream API
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Build a request for `path` relative to the base URL, absolute URLs are used as is
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        if path.starts_with("https://") {
+            self.client.request(method, path)
+        } else {
+            self.client
+                .request(method, format!("{}/{path}", self.base_url))
+        }
+    }
+
+    pub fn send(&self, request: RequestBuilder) -> Result<Response, ThumperError> {
+        let request = request
+            .header("AccessKey", self.api_key.as_str())
+            .header("Accept", "application/json")
+            .build()?;
+        execute(&self.client, self.trace, request)
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ThumperError> {
+        Ok(self.send(self.request(Method::GET, path))?.json()?)
+    }
+
+    /// Fetch every page of a listing endpoint
+    pub fn get_all<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<T>, ThumperError> {
+        let mut items = vec![];
+        for page in 1.. {
+            let response: Page<T> = self
+                .send(
+                    self.request(Method::GET, path)
+                        .query(&[("page", page), ("perPage", 1000)]),
+                )?
+                .json()?;
+            items.extend(response.items);
+            if !response.has_more_items {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    pub fn post<B: Serialize>(&self, path: &str, body: &B) -> Result<Response, ThumperError> {
+        self.send(self.request(Method::POST, path).json(body))
+    }
+
+    pub fn put<B: Serialize>(&self, path: &str, body: &B) -> Result<Response, ThumperError> {
+        self.send(self.request(Method::PUT, path).json(body))
+    }
+
+    pub fn delete(&self, path: &str) -> Result<(), ThumperError> {
+        self.send(self.request(Method::DELETE, path)).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Item {
+        id: u64,
+    }
+
+    #[test]
+    fn parses_pages() {
+        let page: Page<Item> = serde_json::from_str(
+            r#"{"Items": [{"Id": 1}, {"Id": 2}], "CurrentPage": 1, "TotalItems": 2, "HasMoreItems": false}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            page.items.iter().map(|i| i.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(!page.has_more_items);
+    }
+}
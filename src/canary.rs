@@ -0,0 +1,95 @@
+use crate::api::StorageZoneClient;
+use crate::planning::{SyncPlan, detect_mime_type};
+use std::path::Path;
+
+/// Take the uploads of the canary files out of the stages, to sync them ahead of the rest
+pub fn split(stages: Vec<Vec<SyncPlan>>, canary: &[String]) -> (Vec<SyncPlan>, Vec<Vec<SyncPlan>>) {
+    let mut canary_plans = vec![];
+    let rest = stages
+        .into_iter()
+        .map(|stage| {
+            let (picked, rest): (Vec<_>, Vec<_>) = stage.into_iter().partition(|plan| {
+                !matches!(plan, SyncPlan::Delete { .. })
+                    && canary
+                        .iter()
+                        .any(|name| name.trim_start_matches('/') == plan.remote())
+            });
+            canary_plans.extend(picked);
+            rest
+        })
+        .filter(|stage| !stage.is_empty())
+        .collect();
+    (canary_plans, rest)
+}
+
+/// What the canary files held before, `None` for files that are new
+pub fn backup(
+    client: &StorageZoneClient,
+    plans: &[SyncPlan],
+) -> anyhow::Result<Vec<(String, Option<Vec<u8>>)>> {
+    plans
+        .iter()
+        .map(|plan| match plan {
+            SyncPlan::Replace { remote, .. } => {
+                Ok((remote.clone(), Some(client.read_bytes(remote)?)))
+            }
+            _ => Ok((plan.remote().to_string(), None)),
+        })
+        .collect()
+}
+
+/// Put back what the uploaded canary files held, and delete those that were new
+pub fn revert(
+    client: &StorageZoneClient,
+    backups: Vec<(String, Option<Vec<u8>>)>,
+    uploaded: &[String],
+) -> anyhow::Result<usize> {
+    let mut reverted = 0;
+    for (remote, content) in backups {
+        if !uploaded.contains(&remote) {
+            continue;
+        }
+        match content {
+            Some(content) => {
+                let mime_type = detect_mime_type(Path::new(&remote), &content);
+                client.put_file(&remote, content, mime_type)?;
+            }
+            None => client.delete_file(&remote)?,
+        }
+        reverted += 1;
+    }
+    Ok(reverted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn splits_canary_uploads_from_the_rest() {
+        let put = |remote: &str| SyncPlan::Put {
+            local: PathBuf::from(remote),
+            remote: remote.to_string(),
+        };
+        let stages = vec![
+            vec![put("healthz.json"), put("app.js")],
+            vec![put("index.html")],
+            vec![SyncPlan::Delete {
+                remote: "old.html".to_string(),
+            }],
+        ];
+        let canary = vec!["/index.html".to_string(), "healthz.json".to_string()];
+        let (canary, rest) = split(stages, &canary);
+        assert_eq!(canary, vec![put("healthz.json"), put("index.html")]);
+        assert_eq!(
+            rest,
+            vec![
+                vec![put("app.js")],
+                vec![SyncPlan::Delete {
+                    remote: "old.html".to_string()
+                }]
+            ]
+        );
+    }
+}
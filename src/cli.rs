@@ -1,11 +1,191 @@
-use clap::{Parser, Subcommand};
+use crate::deploy_meta::parse_entry as parse_deploy_meta;
+use crate::http::{HttpOptions, HttpTrace, HttpVersion};
+use crate::purge::PurgePacing;
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Subcommand)]
 pub enum Action {
     /// Sync a local folder to a path within a bunny.net Storage Zone
     Sync {
         #[command(flatten)]
-        args: SyncArgs,
+        args: Box<SyncArgs>,
+    },
+    /// Show what a sync would change, optionally saving the plan or comparing it to a saved one
+    Plan {
+        #[command(flatten)]
+        args: Box<SyncArgs>,
+        /// Save the plan as JSON to this file
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Show which tasks were added or removed since the plan saved in this file
+        #[arg(long)]
+        compare: Option<PathBuf>,
+    },
+    /// Measure upload and delete performance against a storage zone
+    Bench {
+        #[command(flatten)]
+        args: BenchArgs,
+    },
+    /// Delete files or directories from a storage zone
+    Rm {
+        #[command(flatten)]
+        args: RmArgs,
+    },
+    /// Show the syncs run from this machine, as recorded in the local history
+    History {
+        /// Only show syncs to this storage zone
+        #[arg(name = "storage_zone")]
+        storage_zone: Option<String>,
+        /// How many of the latest syncs to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Sync, wait for replication, purge and health check an environment from thumper.toml
+    Release {
+        /// Name of the environment, as in [env.<name>]
+        #[arg(long)]
+        env: String,
+        /// Configuration file, defaults to thumper.toml in the current directory
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Show what the syncs would change without changing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// API key for bunny CDN, needed to purge pull zones -- looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Write a thumper.toml releasing the output of the static site generator in use
+    Init {
+        /// Storage zone to sync the site to
+        #[arg(name = "storage_zone")]
+        storage_zone: String,
+        /// Name of the environment to create
+        #[arg(long, default_value = "production")]
+        env: String,
+        /// Directory to publish, detected from Hugo, Zola, Jekyll, mdBook, Astro and Vite projects
+        /// if not given
+        #[arg(long)]
+        local_path: Option<String>,
+    },
+    /// Check the signature of the deploy manifest from sync --sign-manifest and that the
+    /// storage zone still matches it
+    VerifyManifest {
+        #[command(flatten)]
+        args: VerifyManifestArgs,
+    },
+    /// Manage previews synced with sync --preview
+    Preview {
+        #[command(subcommand)]
+        command: PreviewCommand,
+    },
+    /// Delete files below a prefix that haven't changed for a while, like old previews
+    Prune {
+        #[command(flatten)]
+        args: PruneArgs,
+    },
+    /// Write a CSV inventory of every file in a storage zone, optionally comparing it to an earlier one
+    Inventory {
+        #[command(flatten)]
+        args: InventoryArgs,
+    },
+    /// Report files whose ReplicatedZones lack some of the zone's regions, failing if there are any
+    VerifyReplication {
+        #[command(flatten)]
+        args: VerifyReplicationArgs,
+    },
+    /// Download a path in a storage zone to a local directory, the reverse of sync
+    Pull {
+        #[command(flatten)]
+        args: PullArgs,
+    },
+    /// Manage Bunny Edge Scripts
+    EdgeScript {
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, global = true, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(subcommand)]
+        command: EdgeScriptAction,
+    },
+    /// Manage Bunny DNS zones and records
+    Dns {
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, global = true, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(subcommand)]
+        command: DnsAction,
+    },
+    /// Work with Bunny Stream video libraries
+    Stream {
+        /// API key for bunny CDN, used to look up the library key -- looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, global = true, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        /// API key of the video library -- looked up in environment variable THUMPER_STREAM_KEY if not present
+        #[arg(long, global = true)]
+        library_key: Option<String>,
+        #[command(subcommand)]
+        command: StreamAction,
+    },
+    /// Manage pull zone configuration
+    Pullzone {
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, global = true, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(subcommand)]
+        command: PullZoneAction,
+    },
+    /// Check pull zone statistics
+    Stats {
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, global = true, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(subcommand)]
+        command: StatsAction,
+    },
+    /// Analyze pull zone request logs
+    Logs {
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, global = true, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(subcommand)]
+        command: LogsAction,
+    },
+    /// Audit the API keys of the account
+    Keys {
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, global = true, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(subcommand)]
+        command: KeysAction,
+    },
+    /// Manage storage zones
+    Zones {
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, global = true, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(subcommand)]
+        command: ZonesAction,
     },
     /// Provide shell completions
     Completions {
@@ -17,24 +197,391 @@ pub enum Action {
         /// URL to purge, wildcard * is allowed at the end
         #[arg(name = "url")]
         url: String,
-        /// API key for bunny CDN --  looked up in environment variable THUMPER_API_KEY if not present
-        #[arg(short, long)]
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, visible_alias = "account-key")]
         api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Purge storage zone paths from the bunny.net cache by expanding them into URLs
+    PurgePaths {
+        /// URL the storage zone is served from, like https://example.com
+        #[arg(long)]
+        base_url: String,
+        /// Paths relative to the storage zone root
+        #[arg(name = "paths")]
+        paths: Vec<String>,
+        /// Also purge the paths that were uploaded or deleted according to the output of
+        /// thumper sync --verbose in this file (- for stdin)
+        #[arg(long)]
+        from_sync_output: Option<PathBuf>,
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, visible_alias = "account-key")]
+        api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(flatten)]
+        pacing: PurgePacingArgs,
+        /// Also delete the paths from the Perma-Cache of this pull zone, which purging URLs
+        /// leaves alone
+        #[arg(long)]
+        purge_permacache: Option<u64>,
     },
     /// Purge an entire pull zone from bunny.net cache
     PurgeZone {
         /// Numeric ID of pull zone to purge
         #[arg(name = "pullzone")]
         pullzone: u64,
-        /// API key for bunny CDN --  looked up in environment variable THUMPER_API_KEY if not present
-        #[arg(short, long)]
+        /// API key for bunny CDN --  looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+        #[arg(short, long, visible_alias = "account-key")]
         api_key: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
         /// Optional Cache Tag to target
         #[arg(short, long)]
         cache_tag: Option<String>,
+        /// Also delete everything in the Perma-Cache of the pull zone, which purging the cache
+        /// leaves alone
+        #[arg(long, default_value_t = false, conflicts_with = "cache_tag")]
+        purge_permacache: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum EdgeScriptAction {
+    /// List the edge scripts in the account
+    List,
+    /// Show an edge script
+    Get {
+        /// Numeric ID of the edge script
+        #[arg(name = "script_id")]
+        script_id: u64,
+        /// Print the current code of the script instead of its details
+        #[arg(long, default_value_t = false)]
+        code: bool,
+    },
+    /// Upload code to an edge script and publish it
+    Deploy {
+        /// Numeric ID of the edge script
+        #[arg(name = "script_id")]
+        script_id: u64,
+        /// JavaScript file with the code to deploy
+        #[arg(name = "file")]
+        file: PathBuf,
+        /// Note to attach to the published release
+        #[arg(short, long)]
+        note: Option<String>,
+        /// Only upload the code, don't publish a new release
+        #[arg(long, default_value_t = false)]
+        no_publish: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DnsAction {
+    /// Work with DNS zones
+    Zones {
+        #[command(subcommand)]
+        command: DnsZonesAction,
+    },
+    /// Work with records in a DNS zone
+    Records {
+        #[command(subcommand)]
+        command: DnsRecordsAction,
+    },
+    /// Print all records in a DNS zone as JSON, suitable for import
+    Export {
+        /// Domain or numeric ID of the DNS zone
+        #[arg(name = "zone")]
+        zone: String,
+    },
+    /// Make a DNS zone match an exported JSON file, showing the changes first
+    Import {
+        /// JSON file created with dns export
+        #[arg(name = "file")]
+        file: PathBuf,
+        /// Domain or numeric ID of the DNS zone, defaults to the domain in the file
+        #[arg(short, long)]
+        zone: Option<String>,
+        /// Delete records that are not in the file
+        #[arg(long, default_value_t = false)]
+        prune: bool,
+        /// Only show the changes
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DnsZonesAction {
+    /// List the DNS zones in the account
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum DnsRecordsAction {
+    /// List the records in a DNS zone
+    List {
+        /// Domain or numeric ID of the DNS zone
+        #[arg(name = "zone")]
+        zone: String,
+    },
+    /// Create a record, or update the record with the same name and type
+    Upsert {
+        /// Domain or numeric ID of the DNS zone
+        #[arg(name = "zone")]
+        zone: String,
+        /// Name of the record relative to the zone, use @ for the apex
+        #[arg(short, long)]
+        name: String,
+        /// Record type, like A, AAAA, CNAME, TXT or MX
+        #[arg(short = 't', long = "type")]
+        record_type: String,
+        /// Value of the record
+        #[arg(short, long)]
+        value: String,
+        /// Time to live in seconds
+        #[arg(long, default_value_t = 300)]
+        ttl: u32,
+        /// Priority, for MX and SRV records
+        #[arg(long, default_value_t = 0)]
+        priority: u32,
+        /// Weight, for SRV records
+        #[arg(long, default_value_t = 0)]
+        weight: u32,
+        /// Port, for SRV records
+        #[arg(long, default_value_t = 0)]
+        port: u32,
+    },
+    /// Delete a record by ID, or by name and type
+    Delete {
+        /// Domain or numeric ID of the DNS zone
+        #[arg(name = "zone")]
+        zone: String,
+        /// Numeric ID of the record
+        #[arg(long, conflicts_with_all = ["name", "record_type"])]
+        id: Option<u64>,
+        /// Name of the record relative to the zone, use @ for the apex
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Record type, like A, AAAA, CNAME, TXT or MX
+        #[arg(short = 't', long = "type")]
+        record_type: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StreamAction {
+    /// Upload a video, resuming an earlier interrupted upload of the same file
+    Upload {
+        /// Numeric ID of the video library
+        #[arg(name = "library")]
+        library: u64,
+        /// Video file to upload
+        #[arg(name = "file")]
+        file: PathBuf,
+        /// Title of the video, defaults to the file name
+        #[arg(short, long)]
+        title: Option<String>,
+        /// ID of the collection to put the video in
+        #[arg(long)]
+        collection: Option<String>,
+        /// How much to send per request, like 8m
+        #[arg(long, default_value = "8m")]
+        chunk_size: String,
+    },
+    /// List video libraries, or the videos in a library
+    List {
+        /// Numeric ID of the video library to list videos in
+        #[arg(name = "library")]
+        library: Option<u64>,
+    },
+    /// Show status and playback URLs of a video
+    Info {
+        /// Numeric ID of the video library
+        #[arg(name = "library")]
+        library: u64,
+        /// GUID of the video
+        #[arg(name = "video")]
+        video: String,
+    },
+    /// Delete a video
+    Delete {
+        /// Numeric ID of the video library
+        #[arg(name = "library")]
+        library: u64,
+        /// GUID of the video
+        #[arg(name = "video")]
+        video: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PullZoneAction {
+    /// Change pull zone configuration to match a file, showing the changes first
+    Apply {
+        /// Numeric ID of the pull zone
+        #[arg(name = "pullzone")]
+        pullzone: u64,
+        /// TOML (or .json) file with settings, hostnames and edge rules using the API field names in snake_case
+        #[arg(short, long, default_value = "pullzone.toml")]
+        file: PathBuf,
+        /// Only show the changes
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Print the pull zone configuration, including hostnames and edge rules, in a format apply accepts
+    Export {
+        /// Numeric ID of the pull zone
+        #[arg(name = "pullzone")]
+        pullzone: u64,
+        /// Output format
+        #[arg(long, value_enum, default_value = "toml")]
+        format: ExportFormat,
+    },
+    /// Manage custom hostnames and their certificates
+    Hostname {
+        #[command(subcommand)]
+        command: HostnameAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HostnameAction {
+    /// Add a custom hostname to a pull zone
+    Add {
+        /// Numeric ID of the pull zone
+        #[arg(name = "pullzone")]
+        pullzone: u64,
+        /// Hostname to add, its DNS should point to the pull zone
+        #[arg(name = "hostname")]
+        hostname: String,
+        /// Issue a free certificate for the hostname
+        #[arg(long, default_value_t = false)]
+        load_free_cert: bool,
+        /// Redirect plain HTTP to HTTPS for the hostname
+        #[arg(long, default_value_t = false)]
+        force_ssl: bool,
+    },
+    /// Remove a custom hostname from a pull zone
+    Remove {
+        /// Numeric ID of the pull zone
+        #[arg(name = "pullzone")]
+        pullzone: u64,
+        /// Hostname to remove
+        #[arg(name = "hostname")]
+        hostname: String,
+    },
+    /// Redirect plain HTTP to HTTPS for a hostname
+    ForceSsl {
+        /// Numeric ID of the pull zone
+        #[arg(name = "pullzone")]
+        pullzone: u64,
+        /// Hostname to change
+        #[arg(name = "hostname")]
+        hostname: String,
+        /// Stop forcing SSL instead
+        #[arg(long, default_value_t = false)]
+        disable: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StatsAction {
+    /// Exit with an error when the cache hit rate is below a threshold, for pipelines and alerts
+    Gate {
+        /// Numeric ID of the pull zone
+        #[arg(name = "pullzone")]
+        pullzone: u64,
+        /// Lowest acceptable share of requests served from cache, between 0 and 1
+        #[arg(long, default_value_t = 0.9)]
+        min_hit_rate: f64,
+        /// How far back to look, like 1h or 1d
+        #[arg(long, default_value = "1h", value_parser = humantime::parse_duration)]
+        window: Duration,
+    },
+    /// Show how traffic is distributed between countries, largest first
+    Geo {
+        /// Numeric ID of the pull zone
+        #[arg(name = "pullzone")]
+        pullzone: u64,
+        /// How far back to look, like 7d or 30d
+        #[arg(long, default_value = "30d", value_parser = humantime::parse_duration)]
+        window: Duration,
+        /// Print the breakdown as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LogsAction {
+    /// Download a day of logs and summarize top paths, status codes and bandwidth by path prefix
+    Report {
+        /// Numeric ID of the pull zone
+        #[arg(name = "pullzone")]
+        pullzone: u64,
+        /// Day to report on, like 2025-04-15 (defaults to today, in UTC)
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        /// How many paths and prefixes to show
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+        /// How many directories deep to group bandwidth by
+        #[arg(long, default_value_t = 1)]
+        prefix_depth: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum KeysAction {
+    /// List the API keys of the account with the roles they grant, keys are partially masked
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum ZonesAction {
+    /// Rotate the password of a storage zone and print the new one on stdout
+    ResetPassword {
+        /// Name or numeric ID of the storage zone
+        #[arg(name = "zone")]
+        zone: String,
+        /// Reset the read-only password instead
+        #[arg(long, default_value_t = false)]
+        read_only: bool,
+    },
+    /// Show how many files and bytes a storage zone holds, and how that grew over time
+    Usage {
+        /// Name or numeric ID of the storage zone
+        #[arg(name = "zone")]
+        zone: String,
+        /// Add the current usage to the time series before reporting
+        #[arg(long, default_value_t = false)]
+        record: bool,
+        /// Keep the time series in .thumper/usage.jsonl in the storage zone instead of on this
+        /// machine
+        #[arg(long, default_value_t = false)]
+        remote: bool,
+        /// Time series file on this machine, defaults to THUMPER_USAGE or usage.jsonl next to
+        /// the local sync history
+        #[arg(long, conflicts_with = "remote")]
+        file: Option<PathBuf>,
     },
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LocalCacheHash {
+    /// Two to four times faster than SHA256, see benches/hashing.rs
+    Blake3,
+    Sha256,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Toml,
+    Json,
+}
+
 #[derive(Parser)]
 #[command(name = "thumper")]
 #[command(arg_required_else_help = true)]
@@ -56,50 +603,642 @@ HTML at the end, to ensure other assets like CSS are already updated by the time
 pub struct Cli {
     #[command(subcommand)]
     pub command: Action,
+    /// Format of the sync results, the summary and errors
+    #[arg(long, value_enum, global = true, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    /// One JSON object per line: a "task" for each file synced with its remote path, action,
+    /// bytes and duration_ms or error, then the "summary". Errors are objects with category, path,
+    /// status and retryable
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HttpVersionArg {
+    /// HTTP/1.1 only
+    #[value(name = "1")]
+    Http1,
+    /// Negotiate HTTP/2 when the server offers it
+    Auto,
+    /// HTTP/2 only
+    #[value(name = "2")]
+    Http2,
+}
+
+#[derive(Args)]
+pub struct HttpArgs {
+    /// Maximum number of idle connections to keep per host (defaults to unlimited)
+    #[arg(long, global = true)]
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How long idle connections are kept in the pool, like 90s
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    pub pool_idle_timeout: Option<Duration>,
+    /// Interval for TCP keepalive probes, like 30s (disabled if not set)
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    pub tcp_keepalive: Option<Duration>,
+    /// Give up on requests that take longer than this, like 5m (defaults to 30s)
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    pub http_timeout: Option<Duration>,
+    /// Give up on connecting to bunny.net after this long, like 10s
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    pub connect_timeout: Option<Duration>,
+    /// Send all requests through this proxy, like http://proxy.internal:3128 (HTTPS_PROXY and
+    /// friends are respected without it)
+    #[arg(long, global = true)]
+    pub proxy: Option<String>,
+    /// HTTP version to use when talking to bunny.net
+    #[arg(long, global = true, value_enum, default_value = "1")]
+    pub http_version: HttpVersionArg,
+    /// Log every request with method, URL, status and duration to stderr (the AccessKey is redacted)
+    #[arg(long, global = true, default_value_t = false)]
+    pub trace_http: bool,
+    /// Like --trace-http, and also dump response bodies of failed requests
+    #[arg(long, global = true, default_value_t = false)]
+    pub trace_http_bodies: bool,
+    /// Fail this fraction of storage requests on purpose, like p=0.05, to test retries and
+    /// lock cleanup
+    #[arg(long, global = true, hide = true, value_parser = parse_chaos)]
+    pub chaos: Option<f64>,
+}
+
+impl From<HttpArgs> for HttpOptions {
+    fn from(args: HttpArgs) -> Self {
+        HttpOptions {
+            pool_max_idle_per_host: args.pool_max_idle_per_host,
+            pool_idle_timeout: args.pool_idle_timeout,
+            tcp_keepalive: args.tcp_keepalive,
+            timeout: args.http_timeout,
+            connect_timeout: args.connect_timeout,
+            proxy: args.proxy,
+            version: match args.http_version {
+                HttpVersionArg::Http1 => HttpVersion::Http1,
+                HttpVersionArg::Auto => HttpVersion::Auto,
+                HttpVersionArg::Http2 => HttpVersion::Http2,
+            },
+            trace: if args.trace_http_bodies {
+                HttpTrace::Bodies
+            } else if args.trace_http {
+                HttpTrace::Requests
+            } else {
+                HttpTrace::Off
+            },
+            chaos: args.chaos,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Concurrency {
+    Fixed(usize),
+    Auto,
+}
+
+fn parse_chaos(value: &str) -> Result<f64, String> {
+    let chance: f64 = value
+        .strip_prefix("p=")
+        .unwrap_or(value)
+        .parse()
+        .map_err(|_| format!("expected p=<fraction>, got {value}"))?;
+    if (0.0..=1.0).contains(&chance) {
+        Ok(chance)
+    } else {
+        Err(format!("{chance} is not a fraction between 0 and 1"))
+    }
+}
+
+/// Requests per second, which pacing divides by
+fn parse_rate(value: &str) -> Result<f64, String> {
+    let rate: f64 = value
+        .parse()
+        .map_err(|_| format!("expected requests per second, got {value}"))?;
+    if rate.is_finite() && rate > 0.0 {
+        Ok(rate)
+    } else {
+        Err(format!(
+            "{value} is not a positive number of requests per second"
+        ))
+    }
+}
+
+fn parse_concurrency(value: &str) -> Result<Concurrency, String> {
+    if value == "auto" {
+        Ok(Concurrency::Auto)
+    } else {
+        value
+            .parse()
+            .map(Concurrency::Fixed)
+            .map_err(|_| format!("Expected a number or auto, got {value}"))
+    }
+}
+
+#[derive(Args)]
+pub struct PurgePacingArgs {
+    /// Number of purge requests to have in flight at once
+    #[arg(long, default_value_t = 4)]
+    pub purge_concurrency: usize,
+    /// Number of purge requests to send before slowing down to --purge-rate
+    #[arg(long, default_value_t = 20)]
+    pub purge_burst: usize,
+    /// Purge requests per second once the burst is spent, requests rejected with 429 are retried
+    #[arg(long, default_value_t = 5.0, value_parser = parse_rate)]
+    pub purge_rate: f64,
+}
+
+impl From<PurgePacingArgs> for PurgePacing {
+    fn from(args: PurgePacingArgs) -> Self {
+        PurgePacing {
+            concurrency: args.purge_concurrency,
+            burst: args.purge_burst,
+            rate: args.purge_rate,
+        }
+    }
 }
 
 #[derive(Parser)]
 pub struct SyncArgs {
-    /// Which bunny cdn endpoint to use
-    #[arg(short, long, default_value = "storage.bunnycdn.com")]
-    pub endpoint: String,
-    /// Password for the storage zone - looked up in environment variable THUMPER_KEY if not present
+    /// Configuration file, defaults to thumper.toml in the current directory when it exists
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Take the storage zone, endpoint and other sync defaults from the [profile.<name>] section
+    /// of thumper.toml, on top of its [sync] section
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// Which bunny cdn endpoint to use, defaults to endpoint in the [sync] section of thumper.toml
+    /// or storage.bunnycdn.com
     #[arg(short, long)]
+    pub endpoint: Option<String>,
+    /// Password for the storage zone - looked up in THUMPER_STORAGE_KEY, then THUMPER_KEY, if not present
+    #[arg(short, long, visible_alias = "storage-key")]
     pub access_key: Option<String>,
-    /// Local directory to put in the storage zone
+    /// Storage zone password to try when the access key is rejected, can be repeated to try
+    /// several in order
+    #[arg(long)]
+    pub fallback_access_key: Vec<String>,
+    /// Local directory, or .zip, .tar or .tar.gz archive, to put in the storage zone
     #[arg(name = "local_path", required = true, num_args = 1)]
     pub local_path: String,
-    /// Which storage zone to sync to
-    #[arg(name = "storage_zone", required = true, num_args = 1)]
-    pub storage_zone: String,
-    /// Path inside the storage zone to sync to, path to a directory
-    #[arg(short, long, default_value = "/")]
-    pub path: String,
+    /// Which storage zone to sync to, defaults to storage_zone in the [sync] section of
+    /// thumper.toml
+    #[arg(name = "storage_zone", num_args = 1)]
+    pub storage_zone: Option<String>,
+    /// Path inside the storage zone to sync to, path to a directory. Pass several times to sync
+    /// only those subtrees, each from the matching subdirectory of local_path. Defaults to
+    /// remote_path in the [sync] section of thumper.toml or /
+    #[arg(short, long)]
+    pub path: Vec<String>,
     /// Don't sync, just show what would change
     #[arg(long, default_value_t = false)]
     pub dry_run: bool,
+    /// Refuse to sync when there are more local files than this, like when a build emitted
+    /// node_modules into the directory to sync
+    #[arg(long)]
+    pub max_files: Option<usize>,
     /// Force a sync despite a hanging lock file
     #[arg(short, long, default_value_t = false)]
     pub force: bool,
-    /// Filename to use for the lockfile. thumper will not sync if this file exists in the destination.
-    #[arg(long, default_value = ".thumper.lock")]
-    pub lockfile: String,
-    /// Do not delete anything in the storage zone paths that start with this prefix (can pass multiple times)
+    /// Who is syncing, stored in the lockfile -- defaults to GITHUB_RUN_ID. A lock left by the
+    /// same owner, like a failed attempt of a retried CI run, does not need --force.
+    #[arg(long)]
+    pub lock_owner: Option<String>,
+    /// Filename to use for the lockfile, placed below each synced path. thumper will not sync if
+    /// this file exists in the destination. Defaults to .thumper.lock
+    #[arg(long)]
+    pub lockfile: Option<String>,
+    /// Place a single lockfile at the top of the storage zone instead of one below each synced
+    /// path, so no other sync to the zone can run at the same time. Without it, syncs of other
+    /// paths may run alongside, and only take a short lock in .thumper/ while they update the
+    /// deploy history and manifests there
+    #[arg(long, default_value_t = false)]
+    pub zone_lock: bool,
+    /// Don't lock the storage zone while syncing, saving a few requests when thumper is the only
+    /// writer (also lock = false in thumper.toml)
+    #[arg(long, default_value_t = false, conflicts_with_all = ["zone_lock", "force"])]
+    pub no_lock: bool,
+    /// Neither upload nor delete anything in the storage zone paths that start with this prefix, or match this glob like `**/*.pdf` (can pass multiple times, replaces ignore in the [sync] section of thumper.toml)
     #[arg(short, long)]
     pub ignore: Vec<String>,
+    /// Do not delete anything in the storage zone paths that start with this prefix, or match this glob like `uploads/**`, but still upload local files there (can pass multiple times)
+    #[arg(long)]
+    pub protect: Vec<String>,
+    /// Never delete storage zone paths that start with this prefix, or match this glob, whatever --ignore says, for objects other tools keep in the zone. The lockfile and .thumper/ are always kept (can pass multiple times, adds to system_prefixes in thumper.toml)
+    #[arg(long)]
+    pub system_prefix: Vec<String>,
     #[arg(short, long, default_value_t = false)]
     pub verbose: bool,
-    /// Number of threads to use when calling bunny.net API (default to number of cpus)
+    /// Don't show the progress bar on stderr while syncing, it is only drawn on a terminal anyway
+    #[arg(long, default_value_t = false)]
+    pub no_progress: bool,
+    /// Number of threads to use when calling bunny.net API (default to concurrency in thumper.toml
+    /// or the number of cpus), or auto to tune the number of uploads and deletes in flight from
+    /// observed latency and errors
+    #[arg(short, long, value_parser = parse_concurrency)]
+    pub concurrency: Option<Concurrency>,
+    /// Upload and delete on an async client with up to this many requests in flight, leaving the
+    /// --concurrency threads to read and hash files. Much faster for sites with many small files
+    #[arg(long, conflicts_with_all = ["keep_versions", "stall_timeout"])]
+    pub in_flight: Option<usize>,
+    /// Hash and upload files larger than this, like 16m or 1g, as they are read instead of reading
+    /// them into memory first. Doesn't apply to encrypted or transformed files
+    #[arg(long, default_value = "16m")]
+    pub stream_over: String,
+    /// Most uploads and deletes to send per second once --request-burst is spent, for sites of many
+    /// tiny files where the number of requests rather than bandwidth limits the sync
+    #[arg(long, value_parser = parse_rate)]
+    pub request_rate: Option<f64>,
+    /// Number of uploads and deletes to send before slowing down to --request-rate
+    #[arg(long, default_value_t = 100, requires = "request_rate")]
+    pub request_burst: usize,
+    /// Content-Type for files where neither the content nor the extension reveal the type
+    #[arg(long, default_value = "application/octet-stream")]
+    pub default_content_type: String,
+    /// Fail before uploading anything if a file would be uploaded as application/octet-stream, or
+    /// its content doesn't match its extension
+    #[arg(long, default_value_t = false)]
+    pub lint: bool,
+    /// Fail when several local files map to the same name in the storage zone, instead of
+    /// warning and syncing the first
+    #[arg(long, default_value_t = false)]
+    pub strict: bool,
+    /// Break the summary down by top-level directory, like docs/ and blog/, to see which
+    /// sections the sync touches
+    #[arg(long, default_value_t = false)]
+    pub summary_by_dir: bool,
+    /// Directory to cache remote listings in, reused by dry runs while younger than --remote-cache-ttl
+    #[arg(long)]
+    pub remote_cache: Option<PathBuf>,
+    /// How long a cached remote listing stays valid, like 90s or 10m
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    pub remote_cache_ttl: Duration,
+    /// How many times to retry an upload, delete or directory listing that failed with a 5xx
+    /// response or on the way before giving up on the sync
+    #[arg(long, default_value_t = 2)]
+    pub retries: usize,
+    /// How long to wait before the first retry, like 500ms or 2s. The wait doubles for each retry
+    /// after that, with some jitter so workers don't retry in lockstep.
+    #[arg(long, default_value = "1s", value_parser = humantime::parse_duration)]
+    pub retry_delay: Duration,
+    /// Longest wait between two retries, like 30s
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    pub max_retry_delay: Duration,
+    /// Abort an upload that sends nothing and gets no response for this long, like 30s, and retry
+    /// it on a fresh connection
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub stall_timeout: Option<Duration>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+    /// After syncing, wait until uploaded files are replicated to these regions, like SG,NY
+    /// (without regions, wait for the regions other files in the zone report)
+    #[arg(long, num_args = 0..=1, value_delimiter = ',', require_equals = false)]
+    pub wait_replicated: Option<Vec<String>>,
+    /// How long to wait for replication before failing, like 5m
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    pub replication_timeout: Duration,
+    /// Only sync the files in this newline separated list of paths relative to local_path (- for stdin).
+    /// Listed files that no longer exist locally are deleted from the storage zone.
+    #[arg(long)]
+    pub files_from: Option<PathBuf>,
+    /// Metadata to record in the lockfile and print with the summary, like build=42 (can pass
+    /// multiple times). git_sha and git_branch are detected when syncing from a git repository.
+    #[arg(long, value_parser = parse_deploy_meta)]
+    pub deploy_meta: Vec<(String, String)>,
+    /// File to remember checksums of local files in between runs, so unchanged files only need
+    /// a fast hash instead of SHA256
+    #[arg(long)]
+    pub local_cache: Option<PathBuf>,
+    /// Hash used to tell whether a file changed since the last run
+    #[arg(long, value_enum, default_value = "blake3")]
+    pub local_cache_hash: LocalCacheHash,
+    /// Only upload files that are missing in the storage zone, never replace or delete anything
+    #[arg(long, default_value_t = false)]
+    pub skip_existing: bool,
+    /// Only replace files that already exist in the storage zone, never create or delete anything
+    #[arg(long, default_value_t = false, conflicts_with = "skip_existing")]
+    pub existing_only: bool,
+    /// Don't append a record of this sync to .thumper/history.jsonl in the storage zone
+    #[arg(long, default_value_t = false)]
+    pub no_history: bool,
+    /// After syncing, list this many randomly chosen uploaded files again and check that their
+    /// checksums match what was sent
+    #[arg(long, default_value_t = 0, conflicts_with = "encrypt")]
+    pub spot_check: usize,
+    /// Sync to the slots/blue/ or slots/green/ prefix the pull zone with this ID does not serve,
+    /// then point its origin URL at that slot and purge it. The origin URL must end with
+    /// /slots/blue or /slots/green.
+    #[arg(long, conflicts_with = "purge_html")]
+    pub blue_green: Option<u64>,
+    /// After syncing, check that these URLs are healthy, like https://example.com/ (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    pub health_check: Vec<String>,
+    /// Status code the health checks expect
+    #[arg(long, default_value_t = 200)]
+    pub expect_status: u16,
+    /// Text the health checked pages must contain
+    #[arg(long)]
+    pub expect_contains: Option<String>,
+    /// How long to keep retrying failing health checks, like 2m
+    #[arg(long, default_value = "2m", value_parser = humantime::parse_duration)]
+    pub health_timeout: Duration,
+    /// Flip the pull zone back to the previous slot when the health checks fail
+    #[arg(long, default_value_t = false, requires_all = ["blue_green", "health_check"])]
+    pub rollback_on_failure: bool,
+    /// Sync these files first, like index.html,healthz.json, then check --canary-check URLs
+    /// before syncing the rest. When the checks fail, the canary files are reverted.
+    #[arg(long, value_delimiter = ',', requires = "canary_check")]
+    pub canary: Vec<String>,
+    /// URLs that must answer 200 OK after the canary files are synced (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    pub canary_check: Vec<String>,
+    /// How long to keep retrying failing canary checks, like 1m
+    #[arg(long, default_value = "1m", value_parser = humantime::parse_duration)]
+    pub canary_timeout: Duration,
+    /// Sign a manifest of the synced files with the ed25519 key in this file (64 hex digits) and
+    /// upload it to .thumper/, check it later with thumper verify-manifest
+    #[arg(long, conflicts_with_all = ["encrypt", "files_from", "skip_existing", "existing_only"])]
+    pub sign_manifest: Option<PathBuf>,
+    /// Encrypt files before uploading them, with age:<identity file> or aes-gcm:<key file>.
+    /// Checksums of the plaintext are kept in an encrypted manifest in .thumper/
+    #[arg(long)]
+    pub encrypt: Option<String>,
+    /// Upload an empty file with this name, like .keep, into empty local directories so they
+    /// exist in the storage zone too, and never delete remote files with this name
+    #[arg(long)]
+    pub keep_marker: Option<String>,
+    /// After syncing, purge the uploaded and deleted HTML pages from the CDN cache, along with
+    /// the directories of index pages
+    #[arg(long, default_value_t = false, requires = "purge_base_url")]
+    pub purge_html: bool,
+    /// URL the storage zone is served from, like https://example.com, used by --purge-html and
+    /// to print the URL of a --preview
+    #[arg(long)]
+    pub purge_base_url: Option<String>,
+    /// Sync to previews/<id>/ instead, like a pull request number. Deletes stay within the
+    /// preview, and thumper preview rm removes it again.
+    #[arg(long, conflicts_with = "blue_green")]
+    pub preview: Option<String>,
+    /// Before replacing a changed file, keep this many of its previous versions next to it as
+    /// <name>.~1~, <name>.~2~ and so on, 1 being the most recent
+    #[arg(long, default_value_t = 0)]
+    pub keep_versions: usize,
+    /// After syncing, delete the uploaded and deleted files from the Perma-Cache of this pull
+    /// zone, which purging the cache leaves alone
+    #[arg(long)]
+    pub purge_permacache: Option<u64>,
+    /// After syncing, purge everything cached by this pull zone, but only when a page was
+    /// uploaded or deleted. Pages are .html and .htm files unless upload.pages in thumper.toml
+    /// says otherwise, deploys that only change assets leave the cache alone.
+    #[arg(long, conflicts_with = "purge_html")]
+    pub purge_if_pages_changed: Option<u64>,
+    /// API key for bunny CDN, used by --purge-html, --purge-permacache, --purge-if-pages-changed
+    /// and --blue-green -- looked up in THUMPER_ACCOUNT_KEY, then THUMPER_API_KEY, if not present
+    #[arg(long, visible_alias = "account-key")]
+    pub api_key: Option<String>,
+    #[command(flatten)]
+    pub pacing: PurgePacingArgs,
+    /// Upload bandwidth per second, like 10m or 500k, for the transfer time estimate of a dry run.
+    /// Measured from earlier syncs in the local history if not given.
+    #[arg(long)]
+    pub bandwidth: Option<String>,
+    /// Stop starting new uploads and deletes once the sync has run this long, like 20m. In-flight
+    /// ones finish, the lock is released and thumper exits with code 75.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub deadline: Option<Duration>,
+    /// Where to record what a sync stopped at its deadline left undone
+    #[arg(long, default_value = ".thumper-state.json")]
+    pub state_file: PathBuf,
+}
+
+#[derive(Parser)]
+pub struct BenchArgs {
+    /// Which bunny cdn endpoint to use
+    #[arg(short, long, default_value = "storage.bunnycdn.com")]
+    pub endpoint: String,
+    /// Password for the storage zone - looked up in THUMPER_STORAGE_KEY, then THUMPER_KEY, if not present
+    #[arg(short, long, visible_alias = "storage-key")]
+    pub access_key: Option<String>,
+    /// Which storage zone to benchmark against
+    #[arg(name = "storage_zone", required = true, num_args = 1)]
+    pub storage_zone: String,
+    /// Path inside the storage zone to place synthetic files in, removed again after each round
+    #[arg(short, long, default_value = ".thumper-bench/")]
+    pub path: String,
+    /// File sizes to test, like 1k or 4m (comma separated)
+    #[arg(short, long, value_delimiter = ',', default_value = "1k,64k,1m")]
+    pub sizes: Vec<String>,
+    /// Number of files to upload and delete for each size and concurrency level
+    #[arg(short = 'n', long, default_value_t = 20)]
+    pub count: usize,
+    /// Concurrency levels to test (comma separated)
+    #[arg(short, long, value_delimiter = ',', default_value = "1,4,16")]
+    pub concurrency: Vec<usize>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+}
+
+#[derive(Parser)]
+pub struct RmArgs {
+    /// Which bunny cdn endpoint to use
+    #[arg(short, long, default_value = "storage.bunnycdn.com")]
+    pub endpoint: String,
+    /// Password for the storage zone - looked up in THUMPER_STORAGE_KEY, then THUMPER_KEY, if not present
+    #[arg(short, long, visible_alias = "storage-key")]
+    pub access_key: Option<String>,
+    /// Which storage zone to delete from
+    #[arg(name = "storage_zone", required = true, num_args = 1)]
+    pub storage_zone: String,
+    /// Path inside the storage zone to delete
+    #[arg(name = "path", required = true, num_args = 1)]
+    pub path: String,
+    /// Delete the directory at path and everything below it
+    #[arg(short, long, default_value_t = false)]
+    pub recursive: bool,
+    /// Number of threads to list and delete with (default to number of cpus)
+    #[arg(short, long)]
+    pub concurrency: Option<usize>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+}
+
+#[derive(Subcommand)]
+pub enum PreviewCommand {
+    /// Delete a preview along with everything below previews/<id>/
+    Rm {
+        #[command(flatten)]
+        args: PreviewRmArgs,
+    },
+}
+
+#[derive(Parser)]
+pub struct PreviewRmArgs {
+    /// Which bunny cdn endpoint to use
+    #[arg(short, long, default_value = "storage.bunnycdn.com")]
+    pub endpoint: String,
+    /// Password for the storage zone - looked up in THUMPER_STORAGE_KEY, then THUMPER_KEY, if not present
+    #[arg(short, long, visible_alias = "storage-key")]
+    pub access_key: Option<String>,
+    /// Which storage zone the preview is in
+    #[arg(name = "storage_zone", required = true, num_args = 1)]
+    pub storage_zone: String,
+    /// ID the preview was synced with
+    #[arg(name = "id", required = true, num_args = 1)]
+    pub id: String,
+    /// Number of threads to list and delete with (default to number of cpus)
     #[arg(short, long)]
     pub concurrency: Option<usize>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+}
+
+#[derive(Parser)]
+pub struct PruneArgs {
+    /// Which bunny cdn endpoint to use
+    #[arg(short, long, default_value = "storage.bunnycdn.com")]
+    pub endpoint: String,
+    /// Password for the storage zone - looked up in THUMPER_STORAGE_KEY, then THUMPER_KEY, if not present
+    #[arg(short, long, visible_alias = "storage-key")]
+    pub access_key: Option<String>,
+    /// Which storage zone to prune
+    #[arg(name = "storage_zone", required = true, num_args = 1)]
+    pub storage_zone: String,
+    /// Only prune files below this path, like previews/
+    #[arg(long)]
+    pub prefix: String,
+    /// Prune files last changed longer ago than this, like 30d
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub older_than: Duration,
+    /// Show what would be pruned without deleting anything
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+    /// Number of threads to list and delete with (default to number of cpus)
+    #[arg(short, long)]
+    pub concurrency: Option<usize>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+}
+
+#[derive(Parser)]
+pub struct VerifyManifestArgs {
+    /// Which bunny cdn endpoint to use
+    #[arg(short, long, default_value = "storage.bunnycdn.com")]
+    pub endpoint: String,
+    /// Password for the storage zone - looked up in THUMPER_STORAGE_KEY, then THUMPER_KEY, if not present
+    #[arg(short, long, visible_alias = "storage-key")]
+    pub access_key: Option<String>,
+    /// Which storage zone to verify
+    #[arg(name = "storage_zone", required = true, num_args = 1)]
+    pub storage_zone: String,
+    /// Public ed25519 key as 64 hex digits, printed by sync --sign-manifest
+    #[arg(long)]
+    pub public_key: String,
+    /// Number of threads to list the storage zone with (default to number of cpus)
+    #[arg(short, long)]
+    pub concurrency: Option<usize>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+}
+
+#[derive(Parser)]
+pub struct InventoryArgs {
+    /// Which bunny cdn endpoint to use
+    #[arg(short, long, default_value = "storage.bunnycdn.com")]
+    pub endpoint: String,
+    /// Password for the storage zone - looked up in THUMPER_STORAGE_KEY, then THUMPER_KEY, if not present
+    #[arg(short, long, visible_alias = "storage-key")]
+    pub access_key: Option<String>,
+    /// Which storage zone to take inventory of
+    #[arg(name = "storage_zone", required = true, num_args = 1)]
+    pub storage_zone: String,
+    /// Only include files below this path
+    #[arg(short, long, default_value = "/")]
+    pub path: String,
+    /// Write the inventory to this file instead of stdout
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    /// Show which files were added, removed or changed since this earlier inventory
+    #[arg(long)]
+    pub compare: Option<PathBuf>,
+    /// Number of threads to list the storage zone with (default to number of cpus)
+    #[arg(short, long)]
+    pub concurrency: Option<usize>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+}
+
+#[derive(Parser)]
+pub struct VerifyReplicationArgs {
+    /// Which bunny cdn endpoint to use
+    #[arg(short, long, default_value = "storage.bunnycdn.com")]
+    pub endpoint: String,
+    /// Password for the storage zone - looked up in THUMPER_STORAGE_KEY, then THUMPER_KEY, if not present
+    #[arg(short, long, visible_alias = "storage-key")]
+    pub access_key: Option<String>,
+    /// Which storage zone to check
+    #[arg(name = "storage_zone", required = true, num_args = 1)]
+    pub storage_zone: String,
+    /// Only check files below this path
+    #[arg(short, long, default_value = "/")]
+    pub path: String,
+    /// Regions every file must be replicated to, like SE,NY,SG (default to every region any file
+    /// reports)
+    #[arg(long, value_delimiter = ',')]
+    pub regions: Vec<String>,
+    /// Number of threads to list the storage zone with (default to number of cpus)
+    #[arg(short, long)]
+    pub concurrency: Option<usize>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+}
+
+#[derive(Parser)]
+pub struct PullArgs {
+    /// Which bunny cdn endpoint to use
+    #[arg(short, long, default_value = "storage.bunnycdn.com")]
+    pub endpoint: String,
+    /// Password for the storage zone - looked up in THUMPER_STORAGE_KEY, then THUMPER_KEY, if not present
+    #[arg(short, long, visible_alias = "storage-key")]
+    pub access_key: Option<String>,
+    /// Which storage zone to pull from
+    #[arg(name = "storage_zone", required = true, num_args = 1)]
+    pub storage_zone: String,
+    /// Local directory to download to, created if missing
+    #[arg(name = "local_path", required = true, num_args = 1)]
+    pub local_path: PathBuf,
+    /// Path inside the storage zone to pull, path to a directory
+    #[arg(short, long, default_value = "/")]
+    pub path: String,
+    /// Delete local files that are not in the storage zone
+    #[arg(long, default_value_t = false)]
+    pub delete: bool,
+    /// Don't pull, just show what would change
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+    /// Number of threads to list and download with (default to number of cpus)
+    #[arg(short, long)]
+    pub concurrency: Option<usize>,
+    /// Decrypt files synced with --encrypt, with the same age:<identity file> or aes-gcm:<key file>
+    #[arg(long)]
+    pub encrypt: Option<String>,
+    #[command(flatten)]
+    pub http: HttpArgs,
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::cli::Cli;
+    use crate::cli::{Cli, parse_rate};
     use clap::CommandFactory;
     use std::fs;
 
+    #[test]
+    fn rejects_rates_that_are_not_positive() {
+        assert_eq!(parse_rate("2.5"), Ok(2.5));
+        for rate in ["0", "-1", "NaN", "inf", "fast"] {
+            assert!(parse_rate(rate).is_err(), "{rate}");
+        }
+    }
+
     #[test]
     fn render_help() {
         let mut cli = Cli::command();
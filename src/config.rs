@@ -0,0 +1,357 @@
+use crate::lint::DEFAULT_REQUIRED;
+use crate::planning::{DEFAULT_PAGES, UploadTiers};
+use anyhow::{Context, anyhow};
+use fxhash::FxHashMap;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+/// Read when present and no other configuration file is given
+pub const DEFAULT_CONFIG: &str = "thumper.toml";
+
+/// Settings from thumper.toml that are awkward to pass as flags
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Take a lock in the storage zone while syncing, only safe to turn off with a single writer
+    #[serde(default = "locking_default")]
+    pub lock: bool,
+    #[serde(default)]
+    pub upload: UploadConfig,
+    #[serde(default)]
+    pub lint: LintConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    /// Sync defaults for one site each, picked with --profile, by name
+    #[serde(default)]
+    pub profile: FxHashMap<String, SyncConfig>,
+    /// Commands to pipe files matching glob patterns through before uploading them
+    #[serde(default)]
+    pub transform: toml::Table,
+    /// Release environments for thumper release, by name
+    #[serde(default)]
+    pub env: FxHashMap<String, Environment>,
+}
+
+fn locking_default() -> bool {
+    true
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            lock: locking_default(),
+            upload: UploadConfig::default(),
+            lint: LintConfig::default(),
+            sync: SyncConfig::default(),
+            profile: FxHashMap::default(),
+            transform: toml::Table::default(),
+            env: FxHashMap::default(),
+        }
+    }
+}
+
+/// Everything thumper release does for one environment
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Environment {
+    pub sync: Vec<ZoneSync>,
+    /// Regions to wait for uploads to replicate to after each sync, empty for the zone's own
+    pub wait_replicated: Option<Vec<String>>,
+    /// Pull zones to purge once everything is synced
+    #[serde(default)]
+    pub purge_pullzones: Vec<u64>,
+    /// URLs that must answer 200 OK after purging
+    #[serde(default)]
+    pub health_checks: Vec<String>,
+    /// How long to keep retrying health checks, like 2m
+    pub health_timeout: Option<String>,
+    /// Run the syncs at the same time instead of one after the other
+    #[serde(default)]
+    pub parallel: bool,
+    /// Most requests in flight to one storage endpoint across parallel syncs
+    pub max_connections_per_endpoint: Option<usize>,
+    /// Most requests in flight across all parallel syncs
+    pub max_connections: Option<usize>,
+}
+
+/// One sync of a release, as if passed to thumper sync
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneSync {
+    /// Left out to publish the output directory of the static site generator in use
+    pub local_path: Option<String>,
+    pub storage_zone: String,
+    /// Further thumper sync arguments, like ["--path", "docs/"]
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadConfig {
+    /// Glob patterns for files to upload after everything else, each tier after the one before
+    pub tiers: Option<Vec<Vec<String>>>,
+    /// Extensions of pages to upload after the files they link to, in place of .html and .htm,
+    /// like [".html", ".xml", ""] where "" is files without an extension
+    pub pages: Option<Vec<String>>,
+}
+
+impl UploadConfig {
+    /// Extensions of pages, the configured ones or .html and .htm
+    pub fn page_extensions(&self) -> Vec<String> {
+        match &self.pages {
+            Some(pages) => pages.clone(),
+            None => DEFAULT_PAGES.iter().map(|page| page.to_string()).collect(),
+        }
+    }
+
+    pub fn tiers(&self) -> anyhow::Result<UploadTiers> {
+        match (&self.tiers, &self.pages) {
+            (Some(_), Some(_)) => Err(anyhow!(
+                "upload.pages only changes the default tiers, list the pages in upload.tiers instead"
+            )),
+            (Some(tiers), None) => UploadTiers::new(tiers),
+            (None, Some(pages)) => UploadTiers::with_pages(pages),
+            (None, None) => Ok(UploadTiers::default()),
+        }
+    }
+}
+
+/// Defaults for thumper sync, each one replaced by its flag when that is given
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncConfig {
+    pub endpoint: Option<String>,
+    pub storage_zone: Option<String>,
+    /// Directory of the storage zone to sync into, like --path
+    pub remote_path: Option<String>,
+    /// Prefixes or glob patterns to neither upload nor delete, like --ignore
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    pub concurrency: Option<usize>,
+    pub lockfile: Option<String>,
+    /// Prefixes or glob patterns never to delete, like --system-prefix
+    #[serde(default)]
+    pub system_prefixes: Vec<String>,
+}
+
+impl SyncConfig {
+    /// These defaults, with the ones `profile` sets in their place
+    fn overlaid(&self, profile: &SyncConfig) -> SyncConfig {
+        let profile = profile.clone();
+        SyncConfig {
+            endpoint: profile.endpoint.or_else(|| self.endpoint.clone()),
+            storage_zone: profile.storage_zone.or_else(|| self.storage_zone.clone()),
+            remote_path: profile.remote_path.or_else(|| self.remote_path.clone()),
+            ignore: if profile.ignore.is_empty() {
+                self.ignore.clone()
+            } else {
+                profile.ignore
+            },
+            concurrency: profile.concurrency.or(self.concurrency),
+            lockfile: profile.lockfile.or_else(|| self.lockfile.clone()),
+            // A profile can only add to the objects kept for other tools
+            system_prefixes: self
+                .system_prefixes
+                .iter()
+                .chain(&profile.system_prefixes)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Checks of the plan before syncing
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintConfig {
+    /// Files, relative to the zone root, to warn about when a sync would delete them or leave the
+    /// zone without them. Empty to turn the warnings off
+    pub required: Option<Vec<String>>,
+}
+
+impl LintConfig {
+    /// The configured required files, or index.html, 404.html and robots.txt
+    pub fn required_files(&self) -> Vec<String> {
+        match &self.required {
+            Some(required) => required.clone(),
+            None => DEFAULT_REQUIRED
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// thumper.toml in the user's configuration directory, $XDG_CONFIG_HOME or ~/.config
+pub fn user_config() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))?;
+    Some(dir.join("thumper").join(DEFAULT_CONFIG))
+}
+
+impl Config {
+    /// Load `path`, or else thumper.toml in the current directory or in ~/.config/thumper/,
+    /// whichever exists first
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        let found = path.map(Path::to_path_buf).or_else(|| {
+            [PathBuf::from(DEFAULT_CONFIG)]
+                .into_iter()
+                .chain(user_config())
+                .find(|path| path.is_file())
+        });
+        let Some(path) = found else {
+            return Ok(Config::default());
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Invalid config in {}", path.display()))
+    }
+
+    /// The [sync] defaults, or those of [profile.<name>] on top of them
+    pub fn sync_defaults(&self, profile: Option<&str>) -> anyhow::Result<SyncConfig> {
+        let Some(name) = profile else {
+            return Ok(self.sync.clone());
+        };
+        match self.profile.get(name) {
+            Some(profile) => Ok(self.sync.overlaid(profile)),
+            None => {
+                let mut known: Vec<_> = self.profile.keys().map(String::as_str).collect();
+                known.sort_unstable();
+                Err(anyhow!(
+                    "No [profile.{name}] in the configuration, the profiles are: {}",
+                    if known.is_empty() {
+                        "none".to_string()
+                    } else {
+                        known.join(", ")
+                    }
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_upload_tiers() {
+        let config: Config = toml::from_str(
+            r#"
+            [upload]
+            tiers = [["*.html"], ["sw.js", "*.webmanifest"]]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.upload.tiers.unwrap(),
+            vec![vec!["*.html"], vec!["sw.js", "*.webmanifest"]]
+        );
+        assert!(toml::from_str::<Config>("[upload]\ntier = []").is_err());
+        assert!(config.lock);
+        assert!(!toml::from_str::<Config>("lock = false").unwrap().lock);
+    }
+
+    #[test]
+    fn configures_page_extensions() {
+        let config: Config = toml::from_str(
+            r#"
+            [upload]
+            pages = [".html", "xml", ""]
+            "#,
+        )
+        .unwrap();
+        assert!(config.upload.tiers().is_ok());
+        let config: Config = toml::from_str(
+            r#"
+            [upload]
+            tiers = [["*.html"]]
+            pages = [".xml"]
+            "#,
+        )
+        .unwrap();
+        assert!(config.upload.tiers().is_err());
+    }
+
+    #[test]
+    fn configures_required_files() {
+        assert_eq!(Config::default().lint.required_files().len(), 3);
+        let config: Config = toml::from_str("[lint]\nrequired = []").unwrap();
+        assert!(config.lint.required_files().is_empty());
+    }
+
+    #[test]
+    fn parses_sync_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            [sync]
+            storage_zone = "example"
+            endpoint = "ny.storage.bunnycdn.com"
+            remote_path = "docs/"
+            ignore = ["uploads/", "**/*.pdf"]
+            concurrency = 8
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.sync.storage_zone.as_deref(), Some("example"));
+        assert_eq!(config.sync.ignore.len(), 2);
+        assert_eq!(config.sync.concurrency, Some(8));
+        assert!(config.sync.lockfile.is_none());
+        assert!(toml::from_str::<Config>("[sync]\nstorage_zones = []").is_err());
+    }
+
+    #[test]
+    fn overlays_profiles_on_sync_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            [sync]
+            endpoint = "ny.storage.bunnycdn.com"
+            ignore = ["uploads/"]
+
+            [profile.blog]
+            storage_zone = "blog"
+            ignore = ["drafts/"]
+
+            [profile.docs]
+            storage_zone = "docs"
+            endpoint = "storage.bunnycdn.com"
+            "#,
+        )
+        .unwrap();
+        let blog = config.sync_defaults(Some("blog")).unwrap();
+        assert_eq!(blog.storage_zone.as_deref(), Some("blog"));
+        assert_eq!(blog.endpoint.as_deref(), Some("ny.storage.bunnycdn.com"));
+        assert_eq!(blog.ignore, vec!["drafts/"]);
+        let docs = config.sync_defaults(Some("docs")).unwrap();
+        assert_eq!(docs.endpoint.as_deref(), Some("storage.bunnycdn.com"));
+        assert_eq!(docs.ignore, vec!["uploads/"]);
+        assert!(config.sync_defaults(None).unwrap().storage_zone.is_none());
+        let err = config.sync_defaults(Some("shop")).unwrap_err();
+        assert!(err.to_string().ends_with("the profiles are: blog, docs"));
+    }
+
+    #[test]
+    fn parses_environments() {
+        let config: Config = toml::from_str(
+            r#"
+            [env.production]
+            wait_replicated = ["DE", "NY"]
+            purge_pullzones = [1234]
+            health_checks = ["https://example.com/"]
+
+            [[env.production.sync]]
+            local_path = "public"
+            storage_zone = "example"
+            args = ["--path", "docs/"]
+            "#,
+        )
+        .unwrap();
+        let production = &config.env["production"];
+        assert_eq!(production.sync[0].storage_zone, "example");
+        assert_eq!(production.purge_pullzones, vec![1234]);
+        assert!(production.health_timeout.is_none());
+    }
+}
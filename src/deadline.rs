@@ -0,0 +1,83 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Exit code when a sync stopped at its deadline, EX_TEMPFAIL from sysexits.h
+pub const EXIT_CODE: u8 = 75;
+
+/// A sync stopped dispatching tasks because it ran out of time
+#[derive(Debug)]
+pub struct DeadlineExceeded {
+    pub pending: usize,
+    pub state_file: PathBuf,
+}
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Stopped at the deadline with {} tasks left, run the sync again to resume (see {})",
+            self.pending,
+            self.state_file.display()
+        )
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// What a sync stopped at its deadline left undone. Running the sync again plans only what is
+/// still different, so the state is for people and tools to see where it stopped.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResumeState {
+    pub storage_zone: String,
+    pub paths: Vec<String>,
+    pub stopped_at: String,
+    /// Remote names of the tasks that were never started
+    pub pending: Vec<String>,
+}
+
+impl ResumeState {
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    /// The state a previous sync to `storage_zone` left in `path`, if any
+    pub fn load(path: &Path, storage_zone: &str) -> Option<Self> {
+        let state: ResumeState = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+        (state.storage_zone == storage_zone).then_some(state)
+    }
+
+    pub fn clear(path: &Path) -> anyhow::Result<()> {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("Unable to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_state_for_the_same_zone() {
+        let path = std::env::temp_dir().join(format!("thumper-state-{}.json", std::process::id()));
+        let state = ResumeState {
+            storage_zone: "site".to_string(),
+            paths: vec!["/".to_string()],
+            stopped_at: "2025-01-01T00:20:00+00:00".to_string(),
+            pending: vec!["index.html".to_string()],
+        };
+        state.save(&path).unwrap();
+        assert!(ResumeState::load(&path, "other").is_none());
+        assert_eq!(ResumeState::load(&path, "site"), Some(state));
+        ResumeState::clear(&path).unwrap();
+        ResumeState::clear(&path).unwrap();
+        assert!(ResumeState::load(&path, "site").is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::anyhow;
+use std::path::Path;
+use std::process::Command;
+
+/// Metadata describing a deploy, like the git commit it was built from
+#[derive(Debug, Default, PartialEq)]
+pub struct DeployMeta {
+    pub entries: Vec<(String, String)>,
+}
+
+pub fn parse_entry(entry: &str) -> anyhow::Result<(String, String)> {
+    let (key, value) = entry
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected key=value, got {entry}"))?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(anyhow!("Invalid deploy metadata key in {entry}"));
+    }
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()?;
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+impl DeployMeta {
+    /// Detect git_sha and git_branch when `dir` is inside a git repository, then add the
+    /// explicit entries, which win over detected ones
+    pub fn collect(dir: &Path, explicit: Vec<(String, String)>) -> Self {
+        let mut meta = DeployMeta::default();
+        if let Some(sha) = git(dir, &["rev-parse", "HEAD"]) {
+            meta.set("git_sha", sha);
+            if let Some(branch) = git(dir, &["rev-parse", "--abbrev-ref", "HEAD"]) {
+                meta.set("git_branch", branch);
+            }
+        }
+        for (key, value) in explicit {
+            meta.set(&key, value);
+        }
+        meta
+    }
+
+    fn set(&mut self, key: &str, value: String) {
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.entries.push((key.to_string(), value)),
+        }
+    }
+
+    /// key=value lines, as stored in the lockfile below the lock time
+    pub fn lines(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(key, value)| format!("{key}={value}\n"))
+            .collect()
+    }
+
+    pub fn describe(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_entries_override_detected_ones() {
+        let mut meta = DeployMeta::default();
+        meta.set("git_sha", "abc123".to_string());
+        meta.set("build", "41".to_string());
+        meta.set("build", "42".to_string());
+        assert_eq!(meta.lines(), "git_sha=abc123\nbuild=42\n");
+        assert_eq!(meta.describe(), "git_sha=abc123, build=42");
+    }
+
+    #[test]
+    fn parses_entries() {
+        assert_eq!(
+            parse_entry("pipeline = 1234").unwrap(),
+            ("pipeline".to_string(), "1234".to_string())
+        );
+        assert_eq!(
+            parse_entry("url=https://ci/?a=b").unwrap(),
+            ("url".to_string(), "https://ci/?a=b".to_string())
+        );
+        assert!(parse_entry("nothing").is_err());
+        assert!(parse_entry("=value").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// What a sync did below one top-level directory of the storage zone
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct DirSummary {
+    pub uploaded: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+}
+
+/// The first segment of a remote name, like `docs/`, or `/` for files at the top of the zone
+fn top_level(remote: &str) -> &str {
+    match remote.split_once('/') {
+        Some((dir, _)) => &remote[..=dir.len()],
+        None => "/",
+    }
+}
+
+/// Sync results tallied by top-level directory
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct ByDir(BTreeMap<String, DirSummary>);
+
+impl ByDir {
+    /// Count an event from the workers, tasks skipped at the deadline aren't counted
+    pub fn count(&mut self, remote: &str, event: &str) {
+        if !matches!(event, "put" | "delete" | "unchanged") {
+            return;
+        }
+        let dir = self.0.entry(top_level(remote).to_string()).or_default();
+        match event {
+            "put" => dir.uploaded += 1,
+            "delete" => dir.deleted += 1,
+            _ => dir.unchanged += 1,
+        }
+    }
+
+    pub fn extend(&mut self, other: ByDir) {
+        for (name, other) in other.0 {
+            let dir = self.0.entry(name).or_default();
+            dir.uploaded += other.uploaded;
+            dir.deleted += other.deleted;
+            dir.unchanged += other.unchanged;
+        }
+    }
+
+    /// One line per directory, with the directories the sync touched first
+    pub fn lines(&self) -> Vec<String> {
+        let mut dirs: Vec<_> = self.0.iter().collect();
+        dirs.sort_by_key(|(_, dir)| dir.uploaded + dir.deleted == 0);
+        let width = dirs.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        dirs.into_iter()
+            .map(|(name, dir)| {
+                format!(
+                    "  {name:<width$}  {} uploaded, {} deleted, {} unchanged",
+                    dir.uploaded, dir.deleted, dir.unchanged
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tallies_by_top_level_directory() {
+        let mut by_dir = ByDir::default();
+        by_dir.count("blog/2024/post.html", "unchanged");
+        by_dir.count("docs/index.html", "put");
+        by_dir.count("docs/api/old.html", "delete");
+        let mut more = ByDir::default();
+        more.count("index.html", "put");
+        more.count("docs/guide.html", "unchanged");
+        by_dir.extend(more);
+        assert_eq!(
+            by_dir.0["docs/"],
+            DirSummary {
+                uploaded: 1,
+                deleted: 1,
+                unchanged: 1
+            }
+        );
+        assert_eq!(
+            by_dir.lines(),
+            vec![
+                "  /      1 uploaded, 0 deleted, 0 unchanged",
+                "  docs/  1 uploaded, 1 deleted, 1 unchanged",
+                "  blog/  0 uploaded, 0 deleted, 1 unchanged",
+            ]
+        );
+    }
+}
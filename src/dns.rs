@@ -0,0 +1,502 @@
+use crate::bunny_api::BunnyApiClient;
+use crate::cli::{DnsAction, DnsRecordsAction, DnsZonesAction};
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Record types, in the order of the numeric codes the bunny.net API uses
+const RECORD_TYPES: [&str; 13] = [
+    "A", "AAAA", "CNAME", "TXT", "MX", "REDIRECT", "FLATTEN", "PULLZONE", "SRV", "CAA", "PTR",
+    "SCRIPT", "NS",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RecordType(pub u8);
+
+impl FromStr for RecordType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RECORD_TYPES
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(s))
+            .map(|code| RecordType(code as u8))
+            .ok_or_else(|| anyhow!("Unknown DNS record type: {s}"))
+    }
+}
+
+impl Display for RecordType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match RECORD_TYPES.get(self.0 as usize) {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "TYPE{}", self.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DnsRecord {
+    #[serde(default, skip_serializing)]
+    pub id: u64,
+    #[serde(rename = "Type")]
+    pub record_type: RecordType,
+    #[serde(default)]
+    pub name: String,
+    pub value: String,
+    pub ttl: u32,
+    #[serde(default)]
+    pub priority: u32,
+    #[serde(default)]
+    pub weight: u32,
+    #[serde(default)]
+    pub port: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DnsZone {
+    pub id: u64,
+    pub domain: String,
+    #[serde(default)]
+    pub records: Vec<DnsRecord>,
+}
+
+/// A record as it is written in an exported zone file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedRecord {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: String,
+    pub value: String,
+    pub ttl: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub weight: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub port: u32,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedZone {
+    pub domain: String,
+    pub records: Vec<ExportedRecord>,
+}
+
+impl From<&DnsRecord> for ExportedRecord {
+    fn from(record: &DnsRecord) -> Self {
+        ExportedRecord {
+            name: if record.name.is_empty() {
+                "@".to_string()
+            } else {
+                record.name.clone()
+            },
+            record_type: record.record_type.to_string(),
+            value: record.value.clone(),
+            ttl: record.ttl,
+            priority: record.priority,
+            weight: record.weight,
+            port: record.port,
+        }
+    }
+}
+
+impl ExportedRecord {
+    fn to_record(&self) -> anyhow::Result<DnsRecord> {
+        Ok(DnsRecord {
+            id: 0,
+            record_type: self.record_type.parse()?,
+            name: normalize_name(&self.name).to_string(),
+            value: self.value.clone(),
+            ttl: self.ttl,
+            priority: self.priority,
+            weight: self.weight,
+            port: self.port,
+        })
+    }
+}
+
+/// One step needed to make the zone look like the imported file
+#[derive(Debug, PartialEq, Eq)]
+pub enum ZoneChange {
+    Add(DnsRecord),
+    Update { id: u64, record: DnsRecord },
+    Delete(DnsRecord),
+}
+
+/// Records are equal when their content is, regardless of their ID
+impl PartialEq for DnsRecord {
+    fn eq(&self, other: &Self) -> bool {
+        self.same_value(other)
+            && self.ttl == other.ttl
+            && self.priority == other.priority
+            && self.weight == other.weight
+            && self.port == other.port
+    }
+}
+
+impl Eq for DnsRecord {}
+
+impl DnsRecord {
+    fn same_value(&self, other: &DnsRecord) -> bool {
+        self.record_type == other.record_type
+            && self.name.eq_ignore_ascii_case(&other.name)
+            && self.value == other.value
+    }
+}
+
+/// Compare desired records to the current ones. Records that only differ in ttl, priority,
+/// weight or port are updated in place, records missing from `desired` are only deleted when
+/// `prune` is set.
+pub fn diff_zone(current: &[DnsRecord], desired: &[DnsRecord], prune: bool) -> Vec<ZoneChange> {
+    let mut unmatched: Vec<&DnsRecord> = current.iter().collect();
+    let mut changes = vec![];
+    for want in desired {
+        if let Some(pos) = unmatched.iter().position(|have| *have == want) {
+            unmatched.remove(pos);
+        } else if let Some(pos) = unmatched.iter().position(|have| have.same_value(want)) {
+            let have = unmatched.remove(pos);
+            changes.push(ZoneChange::Update {
+                id: have.id,
+                record: want.clone(),
+            });
+        } else {
+            changes.push(ZoneChange::Add(want.clone()));
+        }
+    }
+    if prune {
+        changes.extend(unmatched.into_iter().cloned().map(ZoneChange::Delete));
+    }
+    changes
+}
+
+/// The API uses an empty name for the zone apex, most people write @
+pub fn normalize_name(name: &str) -> &str {
+    if name == "@" { "" } else { name }
+}
+
+pub fn list_zones(api: &BunnyApiClient) -> anyhow::Result<Vec<DnsZone>> {
+    Ok(api.get_all("dnszone")?)
+}
+
+/// Look up a zone by numeric ID or by domain name
+pub fn get_zone(api: &BunnyApiClient, zone: &str) -> anyhow::Result<DnsZone> {
+    let id = match zone.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => {
+            list_zones(api)?
+                .into_iter()
+                .find(|z| z.domain.eq_ignore_ascii_case(zone.trim_end_matches('.')))
+                .ok_or_else(|| anyhow!("No DNS zone named {zone}"))?
+                .id
+        }
+    };
+    Ok(api.get(&format!("dnszone/{id}"))?)
+}
+
+pub fn matching_records<'a>(
+    records: &'a [DnsRecord],
+    name: &str,
+    record_type: RecordType,
+) -> Vec<&'a DnsRecord> {
+    let name = normalize_name(name);
+    records
+        .iter()
+        .filter(|r| r.record_type == record_type && r.name.eq_ignore_ascii_case(name))
+        .collect()
+}
+
+pub fn add_record(api: &BunnyApiClient, zone_id: u64, record: &DnsRecord) -> anyhow::Result<()> {
+    api.put(&format!("dnszone/{zone_id}/records"), record)?;
+    Ok(())
+}
+
+pub fn update_record(
+    api: &BunnyApiClient,
+    zone_id: u64,
+    record_id: u64,
+    record: &DnsRecord,
+) -> anyhow::Result<()> {
+    api.post(&format!("dnszone/{zone_id}/records/{record_id}"), record)?;
+    Ok(())
+}
+
+pub fn delete_record(api: &BunnyApiClient, zone_id: u64, record_id: u64) -> anyhow::Result<()> {
+    Ok(api.delete(&format!("dnszone/{zone_id}/records/{record_id}"))?)
+}
+
+fn export(api: &BunnyApiClient, zone: &str) -> anyhow::Result<()> {
+    let zone = get_zone(api, zone)?;
+    let exported = ExportedZone {
+        domain: zone.domain,
+        records: zone.records.iter().map(ExportedRecord::from).collect(),
+    };
+    println!("{}", serde_json::to_string_pretty(&exported)?);
+    Ok(())
+}
+
+fn import(
+    api: &BunnyApiClient,
+    file: &Path,
+    zone: Option<String>,
+    prune: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let content = fs::read(file).with_context(|| format!("Unable to read {}", file.display()))?;
+    let exported: ExportedZone = serde_json::from_slice(&content)?;
+    let zone = get_zone(api, zone.as_deref().unwrap_or(exported.domain.as_str()))?;
+    let desired = exported
+        .records
+        .iter()
+        .map(ExportedRecord::to_record)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let changes = diff_zone(&zone.records, &desired, prune);
+    if changes.is_empty() {
+        println!("{} is up to date", zone.domain);
+        return Ok(());
+    }
+    for change in &changes {
+        match change {
+            ZoneChange::Add(record) => println!("+ {}", describe(record)),
+            ZoneChange::Update { id, record } => {
+                println!(
+                    "~ {}",
+                    describe(&DnsRecord {
+                        id: *id,
+                        ..record.clone()
+                    })
+                )
+            }
+            ZoneChange::Delete(record) => println!("- {}", describe(record)),
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+    for change in changes {
+        match change {
+            ZoneChange::Add(record) => add_record(api, zone.id, &record)?,
+            ZoneChange::Update { id, record } => update_record(api, zone.id, id, &record)?,
+            ZoneChange::Delete(record) => delete_record(api, zone.id, record.id)?,
+        }
+    }
+    Ok(())
+}
+
+fn describe(record: &DnsRecord) -> String {
+    let name = if record.name.is_empty() {
+        "@"
+    } else {
+        record.name.as_str()
+    };
+    format!(
+        "{}\t{name}\t{}\t{}\t{}",
+        record.id, record.record_type, record.ttl, record.value
+    )
+}
+
+pub fn run(api: &BunnyApiClient, action: DnsAction) -> anyhow::Result<()> {
+    match action {
+        DnsAction::Zones {
+            command: DnsZonesAction::List,
+        } => {
+            for zone in list_zones(api)? {
+                println!("{}\t{}", zone.id, zone.domain);
+            }
+        }
+        DnsAction::Records { command } => run_records(api, command)?,
+        DnsAction::Export { zone } => export(api, &zone)?,
+        DnsAction::Import {
+            file,
+            zone,
+            prune,
+            dry_run,
+        } => import(api, &file, zone, prune, dry_run)?,
+    }
+    Ok(())
+}
+
+fn run_records(api: &BunnyApiClient, action: DnsRecordsAction) -> anyhow::Result<()> {
+    match action {
+        DnsRecordsAction::List { zone } => {
+            for record in get_zone(api, &zone)?.records {
+                println!("{}", describe(&record));
+            }
+        }
+        DnsRecordsAction::Upsert {
+            zone,
+            name,
+            record_type,
+            value,
+            ttl,
+            priority,
+            weight,
+            port,
+        } => {
+            let zone = get_zone(api, &zone)?;
+            let record_type: RecordType = record_type.parse()?;
+            let record = DnsRecord {
+                id: 0,
+                record_type,
+                name: normalize_name(&name).to_string(),
+                value,
+                ttl,
+                priority,
+                weight,
+                port,
+            };
+            match matching_records(&zone.records, &name, record_type).as_slice() {
+                [] => {
+                    add_record(api, zone.id, &record)?;
+                    println!("Added {record_type} record {name} in {}", zone.domain);
+                }
+                [existing] => {
+                    update_record(api, zone.id, existing.id, &record)?;
+                    println!(
+                        "Updated {record_type} record {name} ({}) in {}",
+                        existing.id, zone.domain
+                    );
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Several {record_type} records named {name} exist in {}, delete by --id first",
+                        zone.domain
+                    ));
+                }
+            }
+        }
+        DnsRecordsAction::Delete {
+            zone,
+            id,
+            name,
+            record_type,
+        } => {
+            let zone = get_zone(api, &zone)?;
+            let record_id = match (id, name, record_type) {
+                (Some(id), _, _) => id,
+                (None, Some(name), Some(record_type)) => {
+                    match matching_records(&zone.records, &name, record_type.parse()?).as_slice() {
+                        [existing] => existing.id,
+                        [] => return Err(anyhow!("No {record_type} record named {name}")),
+                        _ => {
+                            return Err(anyhow!(
+                                "Several {record_type} records named {name}, delete by --id"
+                            ));
+                        }
+                    }
+                }
+                _ => return Err(anyhow!("Provide either --id or both --name and --type")),
+            };
+            delete_record(api, zone.id, record_id)?;
+            println!("Deleted record {record_id} in {}", zone.domain);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZONE: &str = r#"{
+        "Id": 1234,
+        "Domain": "example.com",
+        "Records": [
+            {"Id": 1, "Type": 0, "Ttl": 300, "Value": "192.0.2.1", "Name": "", "Weight": 0, "Priority": 0, "Port": 0},
+            {"Id": 2, "Type": 2, "Ttl": 300, "Value": "example.b-cdn.net", "Name": "www", "Weight": 0, "Priority": 0, "Port": 0},
+            {"Id": 3, "Type": 3, "Ttl": 300, "Value": "v=spf1 -all", "Name": "", "Weight": 0, "Priority": 0, "Port": 0}
+        ]
+    }"#;
+
+    #[test]
+    fn parses_record_types() {
+        assert_eq!("cname".parse::<RecordType>().unwrap(), RecordType(2));
+        assert_eq!(RecordType(12).to_string(), "NS");
+        assert_eq!(RecordType(99).to_string(), "TYPE99");
+        assert!("BOGUS".parse::<RecordType>().is_err());
+    }
+
+    #[test]
+    fn finds_records_by_name_and_type() {
+        let zone: DnsZone = serde_json::from_str(ZONE).unwrap();
+        let apex_a = matching_records(&zone.records, "@", RecordType(0));
+        assert_eq!(apex_a.len(), 1);
+        assert_eq!(apex_a[0].id, 1);
+        let www = matching_records(&zone.records, "WWW", "CNAME".parse().unwrap());
+        assert_eq!(www[0].value, "example.b-cdn.net");
+        assert!(matching_records(&zone.records, "www", RecordType(0)).is_empty());
+    }
+
+    fn record(id: u64, name: &str, record_type: u8, value: &str, ttl: u32) -> DnsRecord {
+        DnsRecord {
+            id,
+            record_type: RecordType(record_type),
+            name: name.to_string(),
+            value: value.to_string(),
+            ttl,
+            priority: 0,
+            weight: 0,
+            port: 0,
+        }
+    }
+
+    #[test]
+    fn diffs_zones() {
+        let current = vec![
+            record(1, "", 0, "192.0.2.1", 300),
+            record(2, "www", 2, "old.b-cdn.net", 300),
+            record(3, "", 3, "v=spf1 -all", 300),
+        ];
+        let desired = vec![
+            record(0, "", 0, "192.0.2.1", 300),
+            record(0, "www", 2, "new.b-cdn.net", 300),
+            record(0, "", 3, "v=spf1 -all", 3600),
+        ];
+        let changes = diff_zone(&current, &desired, false);
+        assert_eq!(
+            changes,
+            vec![
+                ZoneChange::Add(record(0, "www", 2, "new.b-cdn.net", 300)),
+                ZoneChange::Update {
+                    id: 3,
+                    record: record(0, "", 3, "v=spf1 -all", 3600)
+                },
+            ]
+        );
+        let pruned = diff_zone(&current, &desired, true);
+        assert_eq!(pruned.len(), 3);
+        assert_eq!(
+            pruned[2],
+            ZoneChange::Delete(record(2, "www", 2, "old.b-cdn.net", 300))
+        );
+        assert!(diff_zone(&current, &current, true).is_empty());
+    }
+
+    #[test]
+    fn round_trips_exported_records() {
+        let zone: DnsZone = serde_json::from_str(ZONE).unwrap();
+        let exported: Vec<_> = zone.records.iter().map(ExportedRecord::from).collect();
+        assert_eq!(exported[0].name, "@");
+        assert_eq!(exported[1].record_type, "CNAME");
+        let imported: Vec<_> = exported.iter().map(|r| r.to_record().unwrap()).collect();
+        assert!(diff_zone(&zone.records, &imported, true).is_empty());
+    }
+
+    #[test]
+    fn serializes_without_id() {
+        let zone: DnsZone = serde_json::from_str(ZONE).unwrap();
+        let json = serde_json::to_value(&zone.records[1]).unwrap();
+        assert!(json.get("Id").is_none());
+        assert_eq!(json["Type"], 2);
+        assert_eq!(describe(&zone.records[0]), "1\t@\tA\t300\t192.0.2.1");
+    }
+}
@@ -0,0 +1,131 @@
+use crate::bunny_api::BunnyApiClient;
+use crate::cli::EdgeScriptAction;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EdgeScript {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub script_type: u8,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    #[serde(default)]
+    pub default_hostname: Option<String>,
+}
+
+impl EdgeScript {
+    fn kind(&self) -> &'static str {
+        match self.script_type {
+            0 => "dns",
+            1 => "standalone",
+            2 => "middleware",
+            _ => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ScriptCode {
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Publish {
+    note: Option<String>,
+}
+
+pub fn list(api: &BunnyApiClient) -> anyhow::Result<Vec<EdgeScript>> {
+    Ok(api.get_all("compute/script")?)
+}
+
+pub fn get(api: &BunnyApiClient, id: u64) -> anyhow::Result<EdgeScript> {
+    Ok(api.get(&format!("compute/script/{id}"))?)
+}
+
+pub fn get_code(api: &BunnyApiClient, id: u64) -> anyhow::Result<String> {
+    let code: ScriptCode = api.get(&format!("compute/script/{id}/code"))?;
+    Ok(code.code)
+}
+
+/// Replace the code of a script, and publish it as a new release unless `publish` is `None`
+pub fn deploy(
+    api: &BunnyApiClient,
+    id: u64,
+    code: String,
+    publish: Option<Option<String>>,
+) -> anyhow::Result<()> {
+    api.post(&format!("compute/script/{id}/code"), &ScriptCode { code })?;
+    if let Some(note) = publish {
+        api.post(&format!("compute/script/{id}/publish"), &Publish { note })?;
+    }
+    Ok(())
+}
+
+fn describe(script: &EdgeScript) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        script.id,
+        script.name,
+        script.kind(),
+        script.default_hostname.as_deref().unwrap_or("-"),
+        script.last_modified.as_deref().unwrap_or("-"),
+    )
+}
+
+pub fn run(api: &BunnyApiClient, action: EdgeScriptAction) -> anyhow::Result<()> {
+    match action {
+        EdgeScriptAction::List => {
+            for script in list(api)? {
+                println!("{}", describe(&script));
+            }
+        }
+        EdgeScriptAction::Get { script_id, code } => {
+            if code {
+                print!("{}", get_code(api, script_id)?);
+            } else {
+                println!("{}", describe(&get(api, script_id)?));
+            }
+        }
+        EdgeScriptAction::Deploy {
+            script_id,
+            file,
+            note,
+            no_publish,
+        } => {
+            let code = fs::read_to_string(&file)
+                .with_context(|| format!("Unable to read {}", file.display()))?;
+            deploy(api, script_id, code, (!no_publish).then_some(note))?;
+            if no_publish {
+                println!("Uploaded {} to edge script {script_id}", file.display());
+            } else {
+                println!("Deployed {} to edge script {script_id}", file.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_script() {
+        let script: EdgeScript = serde_json::from_str(
+            r#"{"Id": 42, "Name": "router", "ScriptType": 1, "LastModified": "2025-04-15T16:52:33Z",
+                "DefaultHostname": "router-abc.b-cdn.net", "CurrentReleaseId": 7, "Deleted": false}"#,
+        )
+        .unwrap();
+        assert_eq!(script.kind(), "standalone");
+        assert_eq!(
+            describe(&script),
+            "42\trouter\tstandalone\trouter-abc.b-cdn.net\t2025-04-15T16:52:33Z"
+        );
+    }
+}
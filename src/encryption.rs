@@ -0,0 +1,205 @@
+use crate::api::{FileMeta, StorageZoneClient};
+use crate::error::ThumperError;
+use crate::sync_job::within;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{Context, anyhow};
+use fxhash::FxHashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Checksums of the plaintext of encrypted files, since the zone only sees ciphertext
+pub const MANIFEST_FILE: &str = ".thumper/manifest.enc";
+
+const NONCE_LEN: usize = 12;
+
+/// Encrypts file content before it is uploaded, and decrypts what thumper reads back
+pub enum Cipher {
+    Age(Box<age::x25519::Identity>),
+    AesGcm(Box<Aes256Gcm>),
+}
+
+impl Cipher {
+    /// Parse `age:<identity file>` or `aes-gcm:<key file>`, where the key file holds 32 bytes
+    /// either raw or as hex
+    pub fn parse(spec: &str) -> anyhow::Result<Cipher> {
+        let (kind, path) = spec
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Expected age:<identity file> or aes-gcm:<key file>"))?;
+        let key = fs::read(Path::new(path)).with_context(|| format!("Unable to read {path}"))?;
+        match kind {
+            "age" => Cipher::age(&String::from_utf8_lossy(&key)),
+            "aes-gcm" => Cipher::aes_gcm(&key),
+            _ => Err(anyhow!("Unknown encryption {kind}, use age or aes-gcm")),
+        }
+    }
+
+    /// Use the first secret key in an identity file as written by age-keygen
+    fn age(identities: &str) -> anyhow::Result<Cipher> {
+        let line = identities
+            .lines()
+            .map(str::trim)
+            .find(|line| line.starts_with("AGE-SECRET-KEY-"))
+            .context("No AGE-SECRET-KEY in the identity file")?;
+        let identity = age::x25519::Identity::from_str(line).map_err(|err| anyhow!(err))?;
+        Ok(Cipher::Age(Box::new(identity)))
+    }
+
+    fn aes_gcm(key: &[u8]) -> anyhow::Result<Cipher> {
+        let trimmed = String::from_utf8_lossy(key);
+        let key = match hex::decode(trimmed.trim()) {
+            Ok(decoded) => decoded,
+            Err(_) => key.to_vec(),
+        };
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow!("AES-GCM keys must be 32 bytes, got {}", key.len()))?;
+        Ok(Cipher::AesGcm(Box::new(cipher)))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Cipher::Age(identity) => Ok(age::encrypt(&identity.to_public(), plaintext)?),
+            Cipher::AesGcm(cipher) => {
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+                let mut sealed = nonce.to_vec();
+                sealed.extend(
+                    cipher
+                        .encrypt(&nonce, plaintext)
+                        .map_err(|_| anyhow!("AES-GCM encryption failed"))?,
+                );
+                Ok(sealed)
+            }
+        }
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Cipher::Age(identity) => Ok(age::decrypt(identity.as_ref(), ciphertext)?),
+            Cipher::AesGcm(cipher) => {
+                if ciphertext.len() < NONCE_LEN {
+                    return Err(anyhow!("Ciphertext is too short"));
+                }
+                let (nonce, sealed) = ciphertext.split_at(NONCE_LEN);
+                cipher
+                    .decrypt(Nonce::from_slice(nonce), sealed)
+                    .map_err(|_| anyhow!("AES-GCM decryption failed, is it the right key?"))
+            }
+        }
+    }
+}
+
+/// Load the manifest, which is empty before the first encrypted sync
+pub fn load_manifest<Access>(
+    client: &StorageZoneClient<Access>,
+    cipher: &Cipher,
+) -> anyhow::Result<FxHashMap<String, String>> {
+    match client.read_bytes(MANIFEST_FILE) {
+        Ok(content) => {
+            let manifest = cipher
+                .decrypt(&content)
+                .context("Unable to decrypt the manifest")?;
+            Ok(serde_json::from_slice(&manifest)?)
+        }
+        Err(ThumperError::NotFound(_)) => Ok(FxHashMap::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The zone only knows checksums of ciphertext, compare against the plaintext in `manifest` instead
+pub fn use_plaintext_checksums(
+    remote: &mut FxHashMap<String, FileMeta>,
+    manifest: &FxHashMap<String, String>,
+) {
+    for (name, meta) in remote.iter_mut() {
+        meta.checksum = manifest.get(name).and_then(|checksum| {
+            let mut digest = [0; 32];
+            hex::decode_to_slice(checksum, &mut digest).ok()?;
+            Some(digest)
+        });
+    }
+}
+
+/// Take this run's entries below `paths` into the manifest in the zone, which syncs of other
+/// paths may have updated since it was loaded
+fn merge_manifest(
+    mut remote: FxHashMap<String, String>,
+    ours: &FxHashMap<String, String>,
+    paths: &[String],
+) -> FxHashMap<String, String> {
+    remote.retain(|name, _| !within(paths, name));
+    remote.extend(
+        ours.iter()
+            .filter(|(name, _)| within(paths, name))
+            .map(|(name, checksum)| (name.clone(), checksum.clone())),
+    );
+    remote
+}
+
+/// Merge this run's entries below `paths` into the manifest in the zone and write it back,
+/// call while holding the lock on `.thumper/`
+pub fn save_manifest(
+    client: &StorageZoneClient,
+    cipher: &Cipher,
+    manifest: &FxHashMap<String, String>,
+    paths: &[String],
+) -> anyhow::Result<()> {
+    let remote = load_manifest(client, cipher)?;
+    write_manifest(client, cipher, &merge_manifest(remote, manifest, paths))
+}
+
+fn write_manifest(
+    client: &StorageZoneClient,
+    cipher: &Cipher,
+    manifest: &FxHashMap<String, String>,
+) -> anyhow::Result<()> {
+    let content = cipher.encrypt(&serde_json::to_vec(manifest)?)?;
+    client.put_file(MANIFEST_FILE, content, Some("application/octet-stream"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_with_aes_gcm() {
+        let cipher = Cipher::aes_gcm(hex::encode([7; 32]).as_bytes()).unwrap();
+        let sealed = cipher.encrypt(b"secret").unwrap();
+        assert_ne!(sealed, cipher.encrypt(b"secret").unwrap());
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), b"secret");
+
+        let other = Cipher::aes_gcm(&[8; 32]).unwrap();
+        assert!(other.decrypt(&sealed).is_err());
+        assert!(Cipher::aes_gcm(b"short").is_err());
+    }
+
+    #[test]
+    fn round_trips_with_age() {
+        let identity = age::x25519::Identity::generate();
+        let file = format!(
+            "# public key: {}\n{}\n",
+            identity.to_public(),
+            age::secrecy::ExposeSecret::expose_secret(&identity.to_string())
+        );
+        let cipher = Cipher::age(&file).unwrap();
+        let sealed = cipher.encrypt(b"secret").unwrap();
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn merges_only_entries_below_the_synced_paths() {
+        let entries = |pairs: &[(&str, &str)]| -> FxHashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(name, checksum)| (name.to_string(), checksum.to_string()))
+                .collect()
+        };
+        let remote = entries(&[("docs/a", "1"), ("docs/gone", "2"), ("blog/b", "3")]);
+        let ours = entries(&[("docs/a", "4"), ("docs/new", "5"), ("blog/b", "stale")]);
+        assert_eq!(
+            merge_manifest(remote, &ours, &["/docs/".to_string()]),
+            entries(&[("docs/a", "4"), ("docs/new", "5"), ("blog/b", "3")])
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use crate::api::{HttpError, Stalled};
+use reqwest::StatusCode;
+use std::any::Any;
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::{fmt, io};
+
+/// What went wrong talking to bunny.net or syncing, in categories callers can react to
+#[derive(Debug)]
+pub enum ThumperError {
+    /// bunny.net rejected the key with 401 Unauthorized or 403 Forbidden
+    Auth {
+        error: HttpError,
+        /// The usual reasons the key was rejected
+        guidance: Option<String>,
+    },
+    /// The file, directory or zone doesn't exist
+    NotFound(HttpError),
+    /// bunny.net answered 429 Too Many Requests
+    RateLimited(HttpError),
+    /// The request failed on the way, stalled, or bunny.net answered with another error
+    Transport(Box<dyn Error + Send + Sync>),
+    /// The storage zone doesn't hold what was uploaded or signed
+    Integrity(String),
+    /// Another sync holds the lock
+    Conflict(String),
+    Io(io::Error),
+}
+
+impl ThumperError {
+    /// The unsuccessful response behind the error, if there was one
+    pub fn http(&self) -> Option<&HttpError> {
+        match self {
+            ThumperError::Auth { error, .. }
+            | ThumperError::NotFound(error)
+            | ThumperError::RateLimited(error) => Some(error),
+            ThumperError::Transport(error) => error.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// Whether an upload was given up on because it made no progress
+    pub fn is_stalled(&self) -> bool {
+        matches!(self, ThumperError::Transport(error) if error.is::<Stalled>())
+    }
+
+    /// Whether running the same command again may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ThumperError::RateLimited(_) | ThumperError::Conflict(_) => true,
+            ThumperError::Transport(error) => match self.http() {
+                Some(http) => http.status.is_server_error(),
+                None => error
+                    .downcast_ref::<reqwest::Error>()
+                    .is_none_or(|network| {
+                        network.is_timeout() || network.is_connect() || network.is_request()
+                    }),
+            },
+            _ => false,
+        }
+    }
+
+    /// Exit code for the error, from sysexits.h
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            // EX_NOPERM
+            ThumperError::Auth { .. } => 77,
+            // EX_NOINPUT
+            ThumperError::NotFound(_) => 66,
+            // EX_TEMPFAIL
+            ThumperError::RateLimited(_) | ThumperError::Conflict(_) => 75,
+            // EX_UNAVAILABLE
+            ThumperError::Transport(_) => 69,
+            // EX_DATAERR
+            ThumperError::Integrity(_) => 65,
+            // EX_IOERR
+            ThumperError::Io(_) => 74,
+        }
+    }
+
+    /// The first cause of `err` that is a [`ThumperError`]
+    pub fn find(err: &anyhow::Error) -> Option<&ThumperError> {
+        err.chain().find_map(|cause| cause.downcast_ref())
+    }
+}
+
+impl fmt::Display for ThumperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThumperError::Auth {
+                error,
+                guidance: Some(guidance),
+            } => write!(f, "{guidance}: {error}"),
+            ThumperError::Auth { error, .. }
+            | ThumperError::NotFound(error)
+            | ThumperError::RateLimited(error) => error.fmt(f),
+            ThumperError::Transport(error) => error.fmt(f),
+            ThumperError::Integrity(message) | ThumperError::Conflict(message) => {
+                f.write_str(message)
+            }
+            ThumperError::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for ThumperError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ThumperError::Transport(error) => error.source(),
+            ThumperError::Io(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<HttpError> for ThumperError {
+    fn from(error: HttpError) -> Self {
+        match error.status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ThumperError::Auth {
+                error,
+                guidance: None,
+            },
+            StatusCode::NOT_FOUND => ThumperError::NotFound(error),
+            StatusCode::TOO_MANY_REQUESTS => ThumperError::RateLimited(error),
+            _ => ThumperError::Transport(Box::new(error)),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ThumperError {
+    fn from(error: reqwest::Error) -> Self {
+        ThumperError::Transport(Box::new(error))
+    }
+}
+
+impl From<Stalled> for ThumperError {
+    fn from(error: Stalled) -> Self {
+        ThumperError::Transport(Box::new(error))
+    }
+}
+
+impl From<io::Error> for ThumperError {
+    fn from(error: io::Error) -> Self {
+        ThumperError::Io(error)
+    }
+}
+
+/// A task that panicked, which is a bug in thumper rather than a problem with the file or zone
+#[derive(Debug)]
+pub struct TaskPanicked(String);
+
+impl fmt::Display for TaskPanicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "thumper panicked: {}. This is a bug, please report it",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for TaskPanicked {}
+
+impl TaskPanicked {
+    /// The message a panic was raised with
+    pub fn from_payload(payload: Box<dyn Any + Send>) -> TaskPanicked {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload
+                .downcast_ref::<&str>()
+                .map_or("no message", |message| message)
+                .to_string(),
+        };
+        TaskPanicked(message)
+    }
+}
+
+/// Run `task`, turning a panic into a [`TaskPanicked`] error so the worker survives it
+pub fn contain_panic<T>(task: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(task))
+        .unwrap_or_else(|payload| Err(TaskPanicked::from_payload(payload).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use reqwest::Method;
+
+    fn http_error(status: StatusCode) -> HttpError {
+        HttpError {
+            method: Method::PUT,
+            path: "/docs/index.html".to_string(),
+            status,
+            retry_after: None,
+        }
+    }
+
+    #[test]
+    fn classifies_unsuccessful_responses() {
+        let err = ThumperError::from(http_error(StatusCode::FORBIDDEN));
+        assert!(matches!(err, ThumperError::Auth { .. }));
+        assert_eq!(err.exit_code(), 77);
+        let err = ThumperError::from(http_error(StatusCode::TOO_MANY_REQUESTS));
+        assert!(err.is_retryable());
+        let err = ThumperError::from(http_error(StatusCode::BAD_GATEWAY));
+        assert!(matches!(err, ThumperError::Transport(_)) && err.is_retryable());
+        let err = ThumperError::from(http_error(StatusCode::BAD_REQUEST));
+        assert!(!err.is_retryable());
+    }
+
+    #[test]
+    fn finds_errors_behind_context() {
+        let err = Err::<(), _>(ThumperError::from(http_error(StatusCode::NOT_FOUND)))
+            .context("Unable to read the lockfile")
+            .unwrap_err();
+        assert!(matches!(
+            ThumperError::find(&err),
+            Some(ThumperError::NotFound(_))
+        ));
+        assert!(ThumperError::find(&anyhow::anyhow!("Dangling lock")).is_none());
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::anyhow;
+use std::fs;
+use std::path::Path;
+
+/// A static site generator recognized from its project files
+#[derive(Debug, PartialEq)]
+pub struct Generator {
+    pub name: &'static str,
+    /// Where the generator writes the built site, relative to the project
+    pub publish_dir: String,
+}
+
+/// Generators with a configuration file of their own, as (name, files, publish directory)
+const MARKERS: &[(&str, &[&str], &str)] = &[
+    ("Hugo", &["hugo.toml", "hugo.yaml", "hugo.json"], "public"),
+    ("Jekyll", &["_config.yml", "_config.yaml"], "_site"),
+    ("mdBook", &["book.toml"], "book"),
+    (
+        "Astro",
+        &["astro.config.mjs", "astro.config.js", "astro.config.ts"],
+        "dist",
+    ),
+    (
+        "Vite",
+        &["vite.config.js", "vite.config.ts", "vite.config.mjs"],
+        "dist",
+    ),
+];
+
+/// mdBook lets book.toml move the output with build.build-dir
+fn mdbook_build_dir(project: &Path) -> Option<String> {
+    let book: toml::Table = fs::read_to_string(project.join("book.toml"))
+        .ok()?
+        .parse()
+        .ok()?;
+    Some(book.get("build")?.get("build-dir")?.as_str()?.to_string())
+}
+
+pub fn detect(project: &Path) -> Option<Generator> {
+    for (name, files, publish_dir) in MARKERS {
+        if files.iter().any(|file| project.join(file).is_file()) {
+            let configured = match *name {
+                "mdBook" => mdbook_build_dir(project),
+                _ => None,
+            };
+            return Some(Generator {
+                name,
+                publish_dir: configured.unwrap_or_else(|| publish_dir.to_string()),
+            });
+        }
+    }
+    // Zola and older Hugo sites both use config.toml, but spell the base URL differently
+    let config = fs::read_to_string(project.join("config.toml")).ok()?;
+    let name = if config.contains("base_url") {
+        "Zola"
+    } else if config.contains("baseURL") {
+        "Hugo"
+    } else {
+        return None;
+    };
+    Some(Generator {
+        name,
+        publish_dir: "public".to_string(),
+    })
+}
+
+/// The publish directory of the generator used in `project`, announced on stderr
+pub fn publish_dir(project: &Path) -> anyhow::Result<String> {
+    let generator = detect(project).ok_or_else(|| {
+        anyhow!(
+            "No local path given, and no Hugo, Zola, Jekyll, mdBook, Astro or Vite project found in {}",
+            project.display()
+        )
+    })?;
+    eprintln!(
+        "Detected {}, publishing {}",
+        generator.name, generator.publish_dir
+    );
+    Ok(generator.publish_dir)
+}
+
+/// A thumper.toml with one environment syncing `local_path` to `storage_zone`
+pub fn config_template(env: &str, local_path: &str, storage_zone: &str) -> String {
+    let quote = |value: &str| toml::Value::String(value.to_string()).to_string();
+    format!(
+        "[[env.{env}.sync]]\nlocal_path = {}\nstorage_zone = {}\n",
+        quote(local_path),
+        quote(storage_zone)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_generators_from_project_files() {
+        let dir = std::env::temp_dir().join(format!("thumper-generator-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(detect(&dir), None);
+
+        fs::write(
+            dir.join("config.toml"),
+            "base_url = \"https://example.com\"",
+        )
+        .unwrap();
+        assert_eq!(detect(&dir).unwrap().name, "Zola");
+
+        fs::write(dir.join("book.toml"), "[build]\nbuild-dir = \"out\"").unwrap();
+        let detected = detect(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            detected,
+            Some(Generator {
+                name: "mdBook",
+                publish_dir: "out".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn templates_parse_as_config() {
+        let template = config_template("production", "_site", "my-site");
+        let config: crate::config::Config = toml::from_str(&template).unwrap();
+        let sync = &config.env["production"].sync[0];
+        assert_eq!(sync.local_path.as_deref(), Some("_site"));
+        assert_eq!(sync.storage_zone, "my-site");
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::anyhow;
+use reqwest::blocking::Client;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait between rounds of failing health checks
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What a healthy URL answers
+#[derive(Debug, Clone)]
+pub struct Expectation {
+    pub status: u16,
+    /// Text the body must contain
+    pub contains: Option<String>,
+    /// How long to keep retrying failing checks
+    pub timeout: Duration,
+}
+
+impl Expectation {
+    /// 200 OK with any body
+    pub fn ok(timeout: Duration) -> Self {
+        Expectation {
+            status: 200,
+            contains: None,
+            timeout,
+        }
+    }
+
+    fn check(&self, status: u16, body: &str) -> Result<(), String> {
+        if status != self.status {
+            Err(format!("answered {status}, expected {}", self.status))
+        } else if let Some(text) = self.contains.as_deref().filter(|text| !body.contains(text)) {
+            Err(format!("body does not contain {text:?}"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn check_url(client: &Client, url: &str, expect: &Expectation) -> Result<(), String> {
+    let response = client.get(url).send().map_err(|err| err.to_string())?;
+    let status = response.status().as_u16();
+    let body = response.text().map_err(|err| err.to_string())?;
+    expect.check(status, &body)
+}
+
+/// Check the URLs until all pass or the timeout runs out, retrying the failing ones
+pub fn run_checks(client: &Client, urls: &[String], expect: &Expectation) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let mut pending: Vec<_> = urls.iter().collect();
+    loop {
+        let mut failures = vec![];
+        pending.retain(|url| match check_url(client, url, expect) {
+            Ok(()) => {
+                println!("Health check passed: {url}");
+                false
+            }
+            Err(problem) => {
+                failures.push(format!("{url}: {problem}"));
+                true
+            }
+        });
+        if pending.is_empty() {
+            return Ok(());
+        }
+        if started.elapsed() + RETRY_INTERVAL > expect.timeout {
+            return Err(anyhow!("Health checks failed:\n{}", failures.join("\n")));
+        }
+        eprintln!(
+            "Waiting for {} failing health checks, retrying in {RETRY_INTERVAL:?}",
+            pending.len()
+        );
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_status_and_body() {
+        let expect = Expectation {
+            status: 200,
+            contains: Some("build 42".to_string()),
+            timeout: Duration::ZERO,
+        };
+        assert!(expect.check(200, "<p>build 42</p>").is_ok());
+        assert!(expect.check(200, "<p>build 41</p>").is_err());
+        assert!(expect.check(503, "build 42").is_err());
+        assert!(Expectation::ok(Duration::ZERO).check(200, "").is_ok());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::api::StorageZoneClient;
+use crate::deploy_meta::DeployMeta;
+use crate::error::ThumperError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::time::Duration;
+
+/// Directory in the storage zone for objects thumper maintains itself
+pub const THUMPER_DIR: &str = ".thumper/";
+pub const HISTORY_FILE: &str = ".thumper/history.jsonl";
+
+/// One line of the deploy history
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeployRecord {
+    pub timestamp: String,
+    pub user: String,
+    pub host: String,
+    pub paths: Vec<String>,
+    pub uploaded: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+    pub duration_secs: f64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meta: BTreeMap<String, String>,
+}
+
+impl DeployRecord {
+    pub fn new(
+        paths: Vec<String>,
+        (uploaded, deleted, unchanged): (usize, usize, usize),
+        duration: Duration,
+        meta: &DeployMeta,
+    ) -> Self {
+        DeployRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            user: env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .unwrap_or_else(|_| "unknown".to_string()),
+            host: gethostname::gethostname().to_string_lossy().into_owned(),
+            paths,
+            uploaded,
+            deleted,
+            unchanged,
+            duration_secs: duration.as_secs_f64(),
+            meta: meta.entries.iter().cloned().collect(),
+        }
+    }
+}
+
+/// Add a line to `history`, which may be empty or lack a trailing newline
+fn append_line(mut history: String, record: &DeployRecord) -> anyhow::Result<String> {
+    if !history.is_empty() && !history.ends_with('\n') {
+        history.push('\n');
+    }
+    history.push_str(&serde_json::to_string(record)?);
+    history.push('\n');
+    Ok(history)
+}
+
+/// Append the record to the history in the zone, call while holding the lock
+pub fn append(client: &StorageZoneClient, record: &DeployRecord) -> anyhow::Result<()> {
+    let history = match client.read_file(HISTORY_FILE) {
+        Ok(history) => history,
+        Err(ThumperError::NotFound(_)) => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let history = append_line(history, record)?;
+    client.put_file(
+        HISTORY_FILE,
+        history.into_bytes(),
+        Some("application/x-ndjson"),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_records_as_lines() {
+        let mut meta = DeployMeta::default();
+        meta.entries
+            .push(("git_sha".to_string(), "abc123".to_string()));
+        let record = DeployRecord::new(
+            vec!["/".to_string()],
+            (3, 1, 20),
+            Duration::from_millis(1500),
+            &meta,
+        );
+
+        let history = append_line("{}".to_string(), &record).unwrap();
+        let lines: Vec<_> = history.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let parsed: DeployRecord = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.meta["git_sha"], "abc123");
+        assert!(history.ends_with('\n'));
+    }
+}
@@ -0,0 +1,294 @@
+use crate::api::HttpError;
+use crate::error::ThumperError;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::{Client, Request, Response};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Method, Proxy, StatusCode, Url};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Sent with every request, so bunny.net support can tell thumper's requests apart
+const USER_AGENT: &str = concat!("thumper/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// Only speak HTTP/1.1
+    Http1,
+    /// Let TLS negotiation pick HTTP/2 when the server supports it
+    Auto,
+    /// Speak HTTP/2 without negotiating
+    Http2,
+}
+
+/// What to log about each request to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpTrace {
+    Off,
+    /// Method, URL, headers with secrets redacted, status and duration
+    Requests,
+    /// Like `Requests`, but also dump response bodies of failed requests
+    Bodies,
+}
+
+/// Settings for the HTTP client every command talks to bunny.net with
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout: Option<Duration>,
+    pub tcp_keepalive: Option<Duration>,
+    /// Give up on requests that take longer than this, reqwest's default of 30s if not set
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// Proxy for all requests, in addition to the ones configured with HTTP_PROXY and friends
+    pub proxy: Option<String>,
+    pub version: HttpVersion,
+    pub trace: HttpTrace,
+    /// Fraction of storage requests to fail on purpose, for testing how a pipeline copes
+    pub chaos: Option<f64>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            tcp_keepalive: None,
+            timeout: None,
+            connect_timeout: None,
+            proxy: None,
+            version: HttpVersion::Http1,
+            trace: HttpTrace::Off,
+            chaos: None,
+        }
+    }
+}
+
+/// Apply the options to a blocking or async client builder, which have the same methods
+macro_rules! configure {
+    ($builder:expr, $options:expr) => {{
+        let options: &HttpOptions = $options;
+        let mut builder = $builder
+            .user_agent(USER_AGENT)
+            .tcp_keepalive(options.tcp_keepalive);
+        if let Some(timeout) = options.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(connect_timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(proxy) = &options.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(max_idle) = options.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(idle_timeout) = options.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(idle_timeout);
+        }
+        match options.version {
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Auto => builder,
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        }
+    }};
+}
+
+pub fn build_client(options: &HttpOptions) -> Result<Client, ThumperError> {
+    Ok(configure!(Client::builder(), options).build()?)
+}
+
+/// Like [`build_client`], for requests sent from a tokio runtime
+pub fn build_async_client(options: &HttpOptions) -> Result<reqwest::Client, ThumperError> {
+    Ok(configure!(reqwest::Client::builder(), options).build()?)
+}
+
+/// The client for commands without HTTP flags, built once so they share its connection pool
+pub fn default_client() -> Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| build_client(&HttpOptions::default()).unwrap_or_else(|_| Client::new()))
+        .clone()
+}
+
+/// Render headers for tracing, without leaking the AccessKey
+fn describe_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            if name.as_str().eq_ignore_ascii_case("AccessKey") {
+                format!("{name}: <redacted>")
+            } else {
+                format!("{name}: {}", value.to_str().unwrap_or("<binary>"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Log a finished request when tracing
+fn trace_request<T>(
+    trace: HttpTrace,
+    method: &Method,
+    url: &Url,
+    headers: &str,
+    response: &Result<T, reqwest::Error>,
+    status: impl Fn(&T) -> StatusCode,
+    elapsed: Duration,
+) {
+    if trace != HttpTrace::Off {
+        match response {
+            Ok(response) => eprintln!(
+                "[http] {method} {url} [{headers}] -> {} in {elapsed:?}",
+                status(response)
+            ),
+            Err(err) => eprintln!("[http] {method} {url} [{headers}] -> {err} in {elapsed:?}"),
+        }
+    }
+}
+
+fn headers_for_trace(trace: HttpTrace, headers: &HeaderMap) -> String {
+    if trace == HttpTrace::Off {
+        String::new()
+    } else {
+        describe_headers(headers)
+    }
+}
+
+/// Send the request, tracing it if requested and turning unsuccessful status codes into errors
+pub fn execute(
+    client: &Client,
+    trace: HttpTrace,
+    request: Request,
+) -> Result<Response, ThumperError> {
+    let method = request.method().clone();
+    let url = request.url().clone();
+    let headers = headers_for_trace(trace, request.headers());
+
+    let started = Instant::now();
+    let response = client.execute(request);
+    let elapsed = started.elapsed();
+    trace_request(
+        trace,
+        &method,
+        &url,
+        &headers,
+        &response,
+        Response::status,
+        elapsed,
+    );
+
+    let response = response?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        let retry_after = retry_after(response.headers());
+        if trace == HttpTrace::Bodies {
+            let body = response.text().unwrap_or_default();
+            eprintln!("[http] {method} {url} response body: {body}");
+        }
+        Err(HttpError {
+            method,
+            path: url.path().to_string(),
+            status,
+            retry_after,
+        }
+        .into())
+    }
+}
+
+/// The wait a Retry-After header asks for, given in seconds or as an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Like [`execute`], with an async client
+pub async fn execute_async(
+    client: &reqwest::Client,
+    trace: HttpTrace,
+    request: reqwest::Request,
+) -> Result<reqwest::Response, ThumperError> {
+    let method = request.method().clone();
+    let url = request.url().clone();
+    let headers = headers_for_trace(trace, request.headers());
+
+    let started = Instant::now();
+    let response = client.execute(request).await;
+    let elapsed = started.elapsed();
+    let status = reqwest::Response::status;
+    trace_request(trace, &method, &url, &headers, &response, status, elapsed);
+
+    let response = response?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        let retry_after = retry_after(response.headers());
+        if trace == HttpTrace::Bodies {
+            let body = response.text().await.unwrap_or_default();
+            eprintln!("[http] {method} {url} response body: {body}");
+        }
+        Err(HttpError {
+            method,
+            path: url.path().to_string(),
+            status,
+            retry_after,
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_access_key_in_trace() {
+        let mut headers = HeaderMap::new();
+        headers.insert("AccessKey", "hunter2".parse().unwrap());
+        headers.insert("Content-Type", "text/html".parse().unwrap());
+        let described = describe_headers(&headers);
+        assert!(!described.contains("hunter2"));
+        assert!(described.contains("accesskey: <redacted>"));
+        assert!(described.contains("content-type: text/html"));
+    }
+
+    #[test]
+    fn reads_retry_after_in_seconds_or_as_a_date() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        let soon = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        headers.insert(RETRY_AFTER, soon.parse().unwrap());
+        let wait = retry_after(&headers).unwrap();
+        assert!(wait > Duration::from_secs(25) && wait <= Duration::from_secs(30));
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn rejects_invalid_proxies() {
+        let options = HttpOptions {
+            proxy: Some("not a proxy".to_string()),
+            ..HttpOptions::default()
+        };
+        assert!(build_client(&options).is_err());
+        assert!(build_client(&HttpOptions::default()).is_ok());
+        assert!(build_async_client(&options).is_err());
+    }
+}
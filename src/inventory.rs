@@ -0,0 +1,170 @@
+use crate::api::{ReadOnly, StorageZoneClient};
+use anyhow::anyhow;
+use std::collections::BTreeMap;
+
+/// One object in the storage zone as recorded in an inventory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub path: String,
+    pub size: u64,
+    pub checksum: String,
+    pub last_changed: String,
+    pub replicated_zones: String,
+}
+
+const HEADER: &str = "path,size,checksum,last_changed,replicated_zones";
+
+/// Every file below `path`, sorted by path
+pub fn collect(
+    client: &StorageZoneClient<ReadOnly>,
+    path: &str,
+    concurrency: usize,
+) -> anyhow::Result<Vec<Item>> {
+    let mut items = vec![];
+    client.for_each_file(path, &[], concurrency, |fi| {
+        items.push(Item {
+            path: client.remote_name(&fi),
+            size: fi.length,
+            checksum: fi.checksum.unwrap_or_default(),
+            last_changed: fi.last_changed,
+            replicated_zones: fi.replicated_zones.unwrap_or_default(),
+        });
+        Ok(())
+    })?;
+    items.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(items)
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Split a CSV line into fields, undoing [`quote`]
+fn split(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(ch),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+pub fn to_csv(items: &[Item]) -> String {
+    let mut csv = format!("{HEADER}\n");
+    for item in items {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            quote(&item.path),
+            item.size,
+            item.checksum,
+            quote(&item.last_changed),
+            quote(&item.replicated_zones)
+        ));
+    }
+    csv
+}
+
+pub fn parse_csv(csv: &str) -> anyhow::Result<Vec<Item>> {
+    let mut lines = csv.lines();
+    if lines.next() != Some(HEADER) {
+        return Err(anyhow!(
+            "Not a thumper inventory, expected the header {HEADER}"
+        ));
+    }
+    lines
+        .filter(|line| !line.is_empty())
+        .map(|line| match split(line).as_slice() {
+            [path, size, checksum, last_changed, replicated_zones] => Ok(Item {
+                path: path.clone(),
+                size: size
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid size in inventory line {line}"))?,
+                checksum: checksum.clone(),
+                last_changed: last_changed.clone(),
+                replicated_zones: replicated_zones.clone(),
+            }),
+            _ => Err(anyhow!("Invalid inventory line {line}")),
+        })
+        .collect()
+}
+
+/// Objects added, removed or with new content since the `previous` inventory
+pub fn changes_since(previous: &[Item], current: &[Item]) -> Vec<String> {
+    let before: BTreeMap<_, _> = previous.iter().map(|item| (&item.path, item)).collect();
+    let after: BTreeMap<_, _> = current.iter().map(|item| (&item.path, item)).collect();
+    let mut changes = vec![];
+    for (path, item) in &after {
+        match before.get(path) {
+            None => changes.push(format!("+ {path}")),
+            Some(old) if old.checksum != item.checksum => changes.push(format!("~ {path}")),
+            Some(_) => {}
+        }
+    }
+    changes.extend(
+        before
+            .keys()
+            .filter(|path| !after.contains_key(*path))
+            .map(|path| format!("- {path}")),
+    );
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(path: &str, checksum: &str) -> Item {
+        Item {
+            path: path.to_string(),
+            size: 42,
+            checksum: checksum.to_string(),
+            last_changed: "2025-04-15T16:52:33.824".to_string(),
+            replicated_zones: "DE,NY".to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trips_csv() {
+        let items = vec![
+            item("docs/a \"quoted\", name.html", "AB"),
+            item("index.html", ""),
+        ];
+        let csv = to_csv(&items);
+        assert!(csv.contains("\"docs/a \"\"quoted\"\", name.html\",42,AB,"));
+        assert_eq!(parse_csv(&csv).unwrap(), items);
+        assert!(parse_csv("name,size\n").is_err());
+    }
+
+    #[test]
+    fn diffs_inventories() {
+        let before = vec![
+            item("a.html", "1"),
+            item("b.html", "2"),
+            item("c.html", "3"),
+        ];
+        let after = vec![
+            item("a.html", "1"),
+            item("b.html", "9"),
+            item("d.html", "4"),
+        ];
+        assert_eq!(
+            changes_since(&before, &after),
+            vec!["~ b.html", "+ d.html", "- c.html"]
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use crate::bunny_api::BunnyApiClient;
+use crate::cli::KeysAction;
+use anyhow::anyhow;
+use serde::Deserialize;
+
+/// Environment variables with the storage zone password, in the order they're looked up
+pub const STORAGE_KEY_VARS: [&str; 2] = ["THUMPER_STORAGE_KEY", "THUMPER_KEY"];
+/// Environment variables with the account API key, in the order they're looked up
+pub const ACCOUNT_KEY_VARS: [&str; 2] = ["THUMPER_ACCOUNT_KEY", "THUMPER_API_KEY"];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiKey {
+    pub id: u64,
+    pub key: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+pub fn list(api: &BunnyApiClient) -> anyhow::Result<Vec<ApiKey>> {
+    Ok(api.get_all("apikey")?)
+}
+
+/// The key given with `flag`, or else the first of `vars` that `lookup` finds, with where it
+/// came from
+pub fn resolve(
+    key: Option<String>,
+    flag: &'static str,
+    vars: &[&'static str],
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<(String, &'static str)> {
+    key.map(|key| (key, flag)).or_else(|| {
+        vars.iter()
+            .find_map(|var| lookup(var).map(|key| (key, *var)))
+    })
+}
+
+/// The storage API and the account API take different keys, catch one passed as both
+pub fn check_distinct(
+    (storage_key, storage_origin): (&str, &str),
+    (account_key, account_origin): (&str, &str),
+) -> anyhow::Result<()> {
+    if storage_key == account_key {
+        return Err(anyhow!(
+            "The storage zone password from {storage_origin} is the same as the account API key \
+             from {account_origin}. Pass the zone password with --storage-key or \
+             THUMPER_STORAGE_KEY and the account API key with --account-key or THUMPER_ACCOUNT_KEY"
+        ));
+    }
+    Ok(())
+}
+
+/// Show just enough of a key to recognize it, like `a1b2…f9e8`
+fn mask(key: &str) -> String {
+    let chars: Vec<_> = key.chars().collect();
+    if chars.len() <= 8 {
+        return "…".to_string();
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{head}…{tail}")
+}
+
+fn describe(key: &ApiKey) -> String {
+    let roles = if key.roles.is_empty() {
+        "-".to_string()
+    } else {
+        key.roles.join(",")
+    };
+    format!("{}\t{}\t{roles}", key.id, mask(&key.key))
+}
+
+pub fn run(api: &BunnyApiClient, action: KeysAction) -> anyhow::Result<()> {
+    match action {
+        KeysAction::List => {
+            for key in list(api)? {
+                println!("{}", describe(&key));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_keys() {
+        let key: ApiKey = serde_json::from_str(
+            r#"{"Id": 7, "Key": "a1b2c3d4-0000-1111-2222-33334444f9e8", "Roles": ["User", "Billing"]}"#,
+        )
+        .unwrap();
+        assert_eq!(describe(&key), "7\ta1b2…f9e8\tUser,Billing");
+        assert_eq!(mask("short"), "…");
+    }
+
+    #[test]
+    fn resolves_keys_from_flag_then_environment() {
+        let lookup = |var: &str| (var != "THUMPER_STORAGE_KEY").then(|| format!("{var}-value"));
+        assert_eq!(
+            resolve(
+                Some("flag".to_string()),
+                "--storage-key",
+                &STORAGE_KEY_VARS,
+                lookup
+            ),
+            Some(("flag".to_string(), "--storage-key"))
+        );
+        assert_eq!(
+            resolve(None, "--storage-key", &STORAGE_KEY_VARS, lookup),
+            Some(("THUMPER_KEY-value".to_string(), "THUMPER_KEY"))
+        );
+        assert_eq!(
+            resolve(None, "--account-key", &ACCOUNT_KEY_VARS, lookup),
+            Some((
+                "THUMPER_ACCOUNT_KEY-value".to_string(),
+                "THUMPER_ACCOUNT_KEY"
+            ))
+        );
+        assert_eq!(
+            resolve(None, "--account-key", &ACCOUNT_KEY_VARS, |_| None),
+            None
+        );
+        assert!(check_distinct(("same", "THUMPER_KEY"), ("same", "--account-key")).is_err());
+        assert!(check_distinct(("zone", "THUMPER_KEY"), ("account", "--account-key")).is_ok());
+    }
+}
@@ -0,0 +1,63 @@
+//! Sync static files to bunny.net storage zones. The thumper binary is a thin command line
+//! around this crate: build a [`sync_job::SyncJob`], plan it with [`planning::plan`] and carry
+//! out the [`planning::SyncPlan`] tasks of the resulting [`planning::Plan`] with the
+//! [`api::StorageZoneClient`]. Plans serialize with serde, so other tools can store, inspect
+//! and submit them.
+
+pub mod adaptive;
+pub mod api;
+pub mod archive;
+pub mod bench;
+pub mod blue_green;
+pub mod bunny_api;
+pub mod canary;
+pub mod cli;
+pub mod config;
+pub mod deadline;
+pub mod deploy_meta;
+pub mod dir_summary;
+pub mod dns;
+pub mod edge_script;
+pub mod encryption;
+pub mod error;
+pub mod generator;
+pub mod health;
+pub mod history;
+pub mod http;
+pub mod inventory;
+pub mod keys;
+pub mod lint;
+pub mod listing;
+pub mod local_cache;
+pub mod local_history;
+pub mod local_path;
+pub mod lock;
+pub mod logs;
+pub mod output;
+pub mod pacing;
+pub mod permacache;
+pub mod planning;
+pub mod preflight;
+pub mod preview;
+pub mod progress;
+pub mod prune;
+pub mod pull;
+pub mod pullzone;
+pub mod purge;
+pub mod release;
+pub mod remote_cache;
+pub mod replication;
+pub mod retry;
+pub mod rm;
+pub mod saved_plan;
+pub mod signing;
+pub mod source;
+pub mod spot_check;
+pub mod stats;
+pub mod stream;
+pub mod sync_job;
+pub mod transform;
+pub mod usage;
+pub mod validation;
+pub mod versions;
+pub mod zones;
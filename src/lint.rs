@@ -0,0 +1,144 @@
+use crate::api::FileMeta;
+use crate::planning::{SyncPlan, detect_mime_type};
+use crate::source::FileSource;
+use anyhow::anyhow;
+use fxhash::FxHashMap;
+use std::path::Path;
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Files a site is broken without, unless thumper.toml lists others in lint.required
+pub const DEFAULT_REQUIRED: [&str; 3] = ["index.html", "404.html", "robots.txt"];
+
+/// What's wrong with the Content-Type a file would be uploaded with, if anything
+fn lint_file(local: &Path, content: &[u8], default_content_type: &str) -> Option<String> {
+    let content_type = detect_mime_type(local, content).unwrap_or(default_content_type);
+    if content_type == OCTET_STREAM {
+        return Some(format!("would be uploaded as {OCTET_STREAM}"));
+    }
+    let sniffed = infer::get(content)?.mime_type();
+    let guessed = mime_guess::from_path(local);
+    // Only compare the kind of type, the same type often goes by several names
+    let kind = |mime_type: &str| mime_type.split('/').next().unwrap_or_default().to_string();
+    if guessed.iter_raw().next().is_none()
+        || guessed
+            .iter_raw()
+            .any(|mime_type| kind(mime_type) == kind(sniffed))
+    {
+        None
+    } else {
+        Some(format!(
+            "content is {sniffed}, but the extension suggests {}",
+            guessed.first_raw().unwrap_or_default()
+        ))
+    }
+}
+
+/// Check the Content-Type of every file the job would upload, before anything is uploaded
+pub fn check(
+    source: &dyn FileSource,
+    job: &[Vec<SyncPlan>],
+    default_content_type: &str,
+) -> anyhow::Result<()> {
+    let mut problems = vec![];
+    for plan in job.iter().flatten() {
+        if let SyncPlan::Put { local, remote } | SyncPlan::Replace { local, remote, .. } = plan {
+            let content = source.read(local)?;
+            if let Some(problem) = lint_file(local, &content, default_content_type) {
+                problems.push(format!("{remote}: {problem}"));
+            }
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Content-Type problems in {} files:\n  {}",
+            problems.len(),
+            problems.join("\n  ")
+        ))
+    }
+}
+
+/// Problems with `required` files in one scope of a sync: the plan deleting them, or the zone
+/// ending up without them. Names are relative to the zone root, only those below `path` count.
+pub fn check_required(
+    required: &[String],
+    path: &str,
+    remote: &FxHashMap<String, FileMeta>,
+    plan: &[SyncPlan],
+) -> Vec<String> {
+    let scope = path.trim_start_matches('/');
+    let mut problems = vec![];
+    for name in required.iter().filter(|name| name.starts_with(scope)) {
+        let planned = plan.iter().find(|plan| plan.remote() == name);
+        match planned {
+            Some(SyncPlan::Delete { .. }) => {
+                problems.push(format!("{name} would be deleted from the storage zone"))
+            }
+            Some(_) => {}
+            None if remote.contains_key(name) => {}
+            None => problems.push(format!(
+                "{name} is missing, neither the local files nor the storage zone have it"
+            )),
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_octet_stream_and_mismatched_types() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(lint_file(Path::new("logo.png"), png, OCTET_STREAM), None);
+        assert_eq!(
+            lint_file(Path::new("notes.txt"), png, OCTET_STREAM),
+            Some("content is image/png, but the extension suggests text/plain".to_string())
+        );
+        assert_eq!(
+            lint_file(Path::new("data.unknownext"), b"plain", OCTET_STREAM),
+            Some("would be uploaded as application/octet-stream".to_string())
+        );
+        assert_eq!(
+            lint_file(Path::new("data.unknownext"), b"plain", "text/plain"),
+            None
+        );
+        assert_eq!(
+            lint_file(Path::new("index.html"), b"<html></html>", OCTET_STREAM),
+            None
+        );
+    }
+
+    #[test]
+    fn flags_deleted_and_missing_required_files() {
+        let required: Vec<_> = DEFAULT_REQUIRED
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let mut remote = FxHashMap::default();
+        for name in ["index.html", "404.html"] {
+            remote.insert(name.to_string(), FileMeta { checksum: None });
+        }
+        let plan = vec![
+            SyncPlan::Replace {
+                local: "public/index.html".into(),
+                remote: "index.html".to_string(),
+                remote_checksum: None,
+            },
+            SyncPlan::Delete {
+                remote: "404.html".to_string(),
+            },
+        ];
+        assert_eq!(
+            check_required(&required, "/", &remote, &plan),
+            vec![
+                "404.html would be deleted from the storage zone",
+                "robots.txt is missing, neither the local files nor the storage zone have it",
+            ]
+        );
+        assert!(check_required(&required, "/docs/", &remote, &plan).is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+//! Turning storage zone directory listings into files by remote name. Kept free of other thumper
+//! modules so the listing benchmark can include it.
+use anyhow::{Context, anyhow};
+use fxhash::FxHashMap;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMeta {
+    pub checksum: Option<[u8; 32]>,
+}
+
+/// A listing entry borrowing from the response body where JSON escapes allow it
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Entry<'a> {
+    #[serde(borrow)]
+    path: Cow<'a, str>,
+    #[serde(borrow)]
+    object_name: Cow<'a, str>,
+    #[serde(borrow)]
+    checksum: Option<Cow<'a, str>>,
+    is_directory: bool,
+}
+
+/// One response to a directory listing
+#[derive(Debug, PartialEq)]
+pub enum Page {
+    /// A plain array of entries, the whole directory as bunny.net lists it today
+    Complete,
+    /// Entries wrapped in an object like the account API pages its lists with,
+    /// `{"Items": [...], "HasMoreItems": true}`
+    Partial {
+        items: Vec<serde_json::Value>,
+        /// How many entries the directory has, if the page says
+        total: Option<u64>,
+        more: bool,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Paged {
+    items: Vec<serde_json::Value>,
+    #[serde(default)]
+    has_more_items: bool,
+    total_items: Option<u64>,
+}
+
+/// Tell a complete listing from one page of it, failing on anything else rather than
+/// mistaking it for an empty directory
+pub fn parse_page(body: &[u8]) -> anyhow::Result<Page> {
+    match body.iter().find(|byte| !byte.is_ascii_whitespace()) {
+        Some(b'[') => Ok(Page::Complete),
+        Some(b'{') => {
+            let paged: Paged = serde_json::from_slice(body)
+                .context("Unrecognized directory listing, an object without Items")?;
+            Ok(Page::Partial {
+                items: paged.items,
+                total: paged.total_items,
+                more: paged.has_more_items,
+            })
+        }
+        _ => Err(anyhow!(
+            "Unrecognized directory listing, expected an array of entries"
+        )),
+    }
+}
+
+/// How many entries a complete listing has, without parsing them
+pub fn count_entries(body: &[u8]) -> anyhow::Result<usize> {
+    Ok(serde_json::from_slice::<Vec<IgnoredAny>>(body)?.len())
+}
+
+/// The directory of a listed object relative to the zone root, `zone_prefix` being /<zone>/
+fn relative_dir<'a>(path: &'a str, zone_prefix: &str) -> &'a str {
+    path.strip_prefix(zone_prefix).unwrap_or(path)
+}
+
+/// The path to list for a subdirectory found in a listing of `path`
+pub fn subdirectory(path: &str, zone_prefix: &str, name: &str) -> String {
+    let parent = relative_dir(path, zone_prefix).trim_end_matches('/');
+    let mut subdirectory = String::with_capacity(parent.len() + name.len() + 2);
+    subdirectory.push_str(parent);
+    subdirectory.push('/');
+    subdirectory.push_str(name);
+    subdirectory.push('/');
+    subdirectory
+}
+
+/// Whether to list a subdirectory found in a listing, unless `skip` matches its name relative
+/// to the zone root, like it would the names of files in it
+pub fn descend(subdirectory: &str, skip: impl Fn(&str) -> bool) -> bool {
+    !skip(subdirectory.trim_start_matches('/'))
+}
+
+/// Insert the files of one directory listing into `files`, returning the subdirectories to list
+/// next except those `skip` matches
+pub fn add_listing(
+    body: &[u8],
+    zone_prefix: &str,
+    skip: impl Fn(&str) -> bool,
+    files: &mut FxHashMap<String, FileMeta>,
+) -> anyhow::Result<Vec<String>> {
+    let entries: Vec<Entry> = serde_json::from_slice(body)?;
+    let mut subdirectories = vec![];
+    files.reserve(entries.len());
+    for entry in entries {
+        if entry.is_directory {
+            let subdirectory = subdirectory(&entry.path, zone_prefix, &entry.object_name);
+            if descend(&subdirectory, &skip) {
+                subdirectories.push(subdirectory);
+            }
+            continue;
+        }
+        let checksum = entry
+            .checksum
+            .map(|hex_checksum| {
+                let mut checksum = [0; 32];
+                hex::decode_to_slice(hex_checksum.as_bytes(), &mut checksum)?;
+                Ok::<[u8; 32], anyhow::Error>(checksum)
+            })
+            .transpose()?;
+        let dir = relative_dir(&entry.path, zone_prefix);
+        let mut name = String::with_capacity(dir.len() + entry.object_name.len());
+        name.push_str(dir);
+        name.push_str(&entry.object_name);
+        files.insert(name, FileMeta { checksum });
+    }
+    Ok(subdirectories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_files_and_returns_subdirectories() {
+        let body = r#"[
+            {"Path": "/site/docs/", "ObjectName": "index.html", "IsDirectory": false,
+             "Checksum": "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08"},
+            {"Path": "/site/docs/", "ObjectName": "caf\u00e9.html", "IsDirectory": false, "Checksum": null},
+            {"Path": "/site/docs/", "ObjectName": "img", "IsDirectory": true, "Checksum": null},
+            {"Path": "/site/docs/", "ObjectName": "tmp", "IsDirectory": true, "Checksum": null}
+        ]"#;
+        let mut files = FxHashMap::default();
+        let subdirectories = add_listing(
+            body.as_bytes(),
+            "/site/",
+            |name: &str| name.starts_with("docs/tmp/"),
+            &mut files,
+        )
+        .unwrap();
+        assert_eq!(subdirectories, vec!["docs/img/"]);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files["docs/index.html"].checksum.unwrap()[0], 0x9f);
+        assert!(files["docs/café.html"].checksum.is_none());
+    }
+
+    #[test]
+    fn tells_complete_listings_from_pages() {
+        let body = br#" [{"ObjectName": "index.html"}, {"ObjectName": "img"}]"#;
+        assert_eq!(parse_page(body).unwrap(), Page::Complete);
+        assert_eq!(count_entries(body).unwrap(), 2);
+        let page = parse_page(br#"{"Items": [{"ObjectName": "a"}], "HasMoreItems": true}"#);
+        assert!(matches!(
+            page.unwrap(),
+            Page::Partial { items, total: None, more: true } if items.len() == 1
+        ));
+        assert!(parse_page(br#"{"Message": "Too many files"}"#).is_err());
+        assert!(parse_page(b"").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::cli::LocalCacheHash;
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Remembers the SHA256 of local files between runs, keyed by a cheaper hash of their content,
+/// so unchanged files only need the cheap hash to compare against the remote checksum. Since
+/// the key is the content, files copied to several paths only get one SHA256 computed.
+pub struct LocalCache {
+    path: PathBuf,
+    hash: LocalCacheHash,
+    known: Mutex<FxHashMap<String, [u8; 32]>>,
+    seen: Mutex<FxHashMap<PathBuf, Entry>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    hash: String,
+    sha256: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    hash: String,
+    entries: FxHashMap<PathBuf, Entry>,
+}
+
+fn hash_name(hash: LocalCacheHash) -> &'static str {
+    match hash {
+        LocalCacheHash::Blake3 => "blake3",
+        LocalCacheHash::Sha256 => "sha256",
+    }
+}
+
+impl LocalCache {
+    /// Load the cache, starting over if it is missing, unreadable or used another hash
+    pub fn load(path: PathBuf, hash: LocalCacheHash) -> Self {
+        let previous = fs::read(&path)
+            .ok()
+            .and_then(|content| serde_json::from_slice::<CacheFile>(&content).ok())
+            .filter(|file| file.hash == hash_name(hash))
+            .map(|file| file.entries)
+            .unwrap_or_default();
+        let known = previous
+            .into_values()
+            .filter_map(|entry| {
+                let mut sha256 = [0; 32];
+                hex::decode_to_slice(&entry.sha256, &mut sha256).ok()?;
+                Some((entry.hash, sha256))
+            })
+            .collect();
+        LocalCache {
+            path,
+            hash,
+            known: Mutex::new(known),
+            seen: Mutex::new(FxHashMap::default()),
+        }
+    }
+
+    fn digest(&self, content: &[u8]) -> String {
+        match self.hash {
+            LocalCacheHash::Blake3 => blake3::hash(content).to_hex().to_string(),
+            LocalCacheHash::Sha256 => hex::encode(Sha256::digest(content)),
+        }
+    }
+
+    /// SHA256 of the content of `local`, reused when the same content was hashed before
+    pub fn sha256(&self, local: &Path, content: &[u8]) -> [u8; 32] {
+        let hash = self.digest(content);
+        let cached = self
+            .known
+            .lock()
+            .expect("local cache lock poisoned")
+            .get(&hash)
+            .copied();
+        let sha256 = cached.unwrap_or_else(|| {
+            let sha256: [u8; 32] = Sha256::digest(content).into();
+            self.known
+                .lock()
+                .expect("local cache lock poisoned")
+                .insert(hash.clone(), sha256);
+            sha256
+        });
+        self.seen.lock().expect("local cache lock poisoned").insert(
+            local.to_path_buf(),
+            Entry {
+                hash,
+                sha256: hex::encode(sha256),
+            },
+        );
+        sha256
+    }
+
+    /// Write the files seen during this run, forgetting files that are gone
+    pub fn save(self) -> anyhow::Result<()> {
+        let file = CacheFile {
+            hash: hash_name(self.hash).to_string(),
+            entries: self.seen.into_inner().expect("local cache lock poisoned"),
+        };
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_vec(&file)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_digest_of_unchanged_files() {
+        let path =
+            std::env::temp_dir().join(format!("thumper-local-cache-{}.json", std::process::id()));
+        let local = Path::new("site/index.html");
+        let expected: [u8; 32] = Sha256::digest(b"hello").into();
+
+        let cache = LocalCache::load(path.clone(), LocalCacheHash::Blake3);
+        assert_eq!(cache.sha256(local, b"hello"), expected);
+        cache.save().unwrap();
+
+        let cache = LocalCache::load(path.clone(), LocalCacheHash::Blake3);
+        assert_eq!(cache.known.lock().unwrap().len(), 1);
+        assert_eq!(cache.sha256(local, b"hello"), expected);
+        assert_eq!(
+            cache.sha256(local, b"changed"),
+            <[u8; 32]>::from(Sha256::digest(b"changed"))
+        );
+
+        let cache = LocalCache::load(path.clone(), LocalCacheHash::Sha256);
+        assert!(cache.known.lock().unwrap().is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hashes_identical_content_once() {
+        let cache = LocalCache::load(PathBuf::from("unused.json"), LocalCacheHash::Blake3);
+        let marker = [7; 32];
+        cache
+            .known
+            .lock()
+            .unwrap()
+            .insert(cache.digest(b"font"), marker);
+        assert_eq!(cache.sha256(Path::new("a/font.woff2"), b"font"), marker);
+        assert_eq!(cache.sha256(Path::new("b/font.woff2"), b"font"), marker);
+        assert_eq!(cache.seen.lock().unwrap().len(), 2);
+    }
+}
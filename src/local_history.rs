@@ -0,0 +1,204 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// One sync run as remembered on this machine
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub timestamp: String,
+    /// Release environment the sync was part of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    pub storage_zone: String,
+    pub paths: Vec<String>,
+    pub uploaded: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+    #[serde(default)]
+    pub uploaded_bytes: u64,
+    pub duration_secs: f64,
+    pub outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Files uploaded, deleted and unchanged and bytes uploaded by a run, or how it failed
+pub type Outcome<'a> = Result<(usize, usize, usize, u64), &'a anyhow::Error>;
+
+/// How many recent runs to a zone the measured bandwidth is averaged over
+const MEASURED_RUNS: usize = 10;
+
+impl RunRecord {
+    pub fn new(
+        env: Option<&str>,
+        storage_zone: &str,
+        paths: &[String],
+        duration: Duration,
+        outcome: Outcome,
+    ) -> Self {
+        let ((uploaded, deleted, unchanged, uploaded_bytes), error) = match outcome {
+            Ok(counts) => (counts, None),
+            Err(err) => ((0, 0, 0, 0), Some(format!("{err:#}"))),
+        };
+        RunRecord {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            env: env.map(str::to_string),
+            storage_zone: storage_zone.to_string(),
+            paths: paths.to_vec(),
+            uploaded,
+            deleted,
+            unchanged,
+            uploaded_bytes,
+            duration_secs: duration.as_secs_f64(),
+            outcome: if error.is_some() { "failed" } else { "ok" }.to_string(),
+            error,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let env = self
+            .env
+            .as_deref()
+            .map(|env| format!(" [{env}]"))
+            .unwrap_or_default();
+        let what = match &self.error {
+            Some(error) => format!("failed: {error}"),
+            None => format!(
+                "{} uploaded, {} deleted, {} unchanged",
+                self.uploaded, self.deleted, self.unchanged
+            ),
+        };
+        format!(
+            "{} {}{env} {}: {what} in {:.1}s",
+            self.timestamp,
+            self.storage_zone,
+            self.paths.join(","),
+            self.duration_secs
+        )
+    }
+}
+
+/// THUMPER_HISTORY, or history.jsonl in the user's data directory
+pub fn history_path() -> Option<PathBuf> {
+    if let Ok(path) = env::var("THUMPER_HISTORY") {
+        return Some(PathBuf::from(path));
+    }
+    let data_dir = env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("APPDATA").map(PathBuf::from))
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .ok()?;
+    Some(data_dir.join("thumper").join("history.jsonl"))
+}
+
+pub fn append(record: &RunRecord) -> anyhow::Result<()> {
+    let path = history_path().context("Unable to find a directory for the local history")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+/// Parse the history, skipping lines that don't parse
+fn parse(history: &str) -> Vec<RunRecord> {
+    history
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Bytes per second of the latest successful uploading runs, counting their whole duration
+fn measured_bandwidth(records: &[RunRecord], storage_zone: &str) -> Option<f64> {
+    let (bytes, seconds) = records
+        .iter()
+        .rev()
+        .filter(|record| {
+            record.storage_zone == storage_zone
+                && record.error.is_none()
+                && record.uploaded_bytes > 0
+        })
+        .take(MEASURED_RUNS)
+        .fold((0, 0.0), |(bytes, seconds), record| {
+            (
+                bytes + record.uploaded_bytes,
+                seconds + record.duration_secs,
+            )
+        });
+    (seconds > 0.0).then(|| bytes as f64 / seconds)
+}
+
+/// The bandwidth earlier syncs to `storage_zone` from this machine achieved, if any uploaded
+pub fn bandwidth_to(storage_zone: &str) -> Option<f64> {
+    let history = fs::read_to_string(history_path()?).ok()?;
+    measured_bandwidth(&parse(&history), storage_zone)
+}
+
+/// Print the latest runs, optionally only those to one storage zone
+pub fn show(storage_zone: Option<&str>, limit: usize) -> anyhow::Result<()> {
+    let path = history_path().context("Unable to find a directory for the local history")?;
+    let history = match fs::read_to_string(&path) {
+        Ok(history) => history,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("Unable to read {}", path.display())),
+    };
+    let records: Vec<_> = parse(&history)
+        .into_iter()
+        .filter(|record| storage_zone.is_none_or(|zone| record.storage_zone == zone))
+        .collect();
+    if records.is_empty() {
+        eprintln!("No runs recorded in {}", path.display());
+    }
+    for record in &records[records.len().saturating_sub(limit)..] {
+        println!("{}", record.describe());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn records_outcomes() {
+        let paths = vec!["/".to_string()];
+        let ok = RunRecord::new(
+            None,
+            "site",
+            &paths,
+            Duration::from_secs(3),
+            Ok((2, 1, 7, 4096)),
+        );
+        let err = anyhow!("Dangling lock");
+        let failed = RunRecord::new(
+            Some("production"),
+            "site",
+            &paths,
+            Duration::from_secs(1),
+            Err(&err),
+        );
+        let history = format!(
+            "{}\nnot json\n{}\n",
+            serde_json::to_string(&ok).unwrap(),
+            serde_json::to_string(&failed).unwrap()
+        );
+        let records = parse(&history);
+        assert_eq!(records, vec![ok, failed]);
+        assert!(
+            records[0]
+                .describe()
+                .contains("site /: 2 uploaded, 1 deleted, 7 unchanged")
+        );
+        assert!(
+            records[1]
+                .describe()
+                .contains("site [production] /: failed: Dangling lock")
+        );
+    }
+}
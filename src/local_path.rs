@@ -1,15 +1,14 @@
+use crate::source::{RemoteNames, remote_name};
 use anyhow::{Context, anyhow};
-use fxhash::{FxHashMap, FxHashSet};
+use fxhash::FxHashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 
-pub fn files_by_remote_name(
-    root: &str,
-    remote_root: &str,
-) -> anyhow::Result<FxHashMap<String, PathBuf>> {
+pub fn files_by_remote_name(root: &str, remote_root: &str) -> anyhow::Result<RemoteNames> {
     let files = discover_files(root)?;
     let remote_root = remote_root.trim_start_matches("/").trim_end_matches("/");
-    let mut by_name = FxHashMap::default();
+    let mut by_name = RemoteNames::default();
     for file in files {
         let remote_name = file
             .strip_prefix(root)?
@@ -25,6 +24,51 @@ pub fn files_by_remote_name(
     Ok(by_name)
 }
 
+/// Read a file, or stdin when the path is `-`
+pub fn read_input(source: &Path) -> anyhow::Result<String> {
+    if source == Path::new("-") {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        Ok(input)
+    } else {
+        fs::read_to_string(source).with_context(|| format!("Unable to read {}", source.display()))
+    }
+}
+
+/// Remote names of the listed files that fall inside a scope syncing `scope_root` to `remote_root`.
+/// The listed paths are relative to `root`, which contains `scope_root`.
+pub fn listed_remote_names(
+    listed: &[String],
+    root: &str,
+    scope_root: &str,
+    remote_root: &str,
+) -> FxHashSet<String> {
+    let scope = scope_root.strip_prefix(root).unwrap_or(scope_root);
+    listed
+        .iter()
+        .map(|path| path.trim().trim_start_matches("./"))
+        .filter(|path| !path.is_empty())
+        .filter_map(|path| path.strip_prefix(scope))
+        .map(|relative| remote_name(remote_root, relative))
+        .collect()
+}
+
+/// Directories below `root` without any files or subdirectories
+pub fn empty_dirs(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut empty = vec![];
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.is_dir() && !path.is_symlink() {
+            if fs::read_dir(&path)?.next().is_none() {
+                empty.push(path);
+            } else {
+                empty.extend(empty_dirs(&path)?);
+            }
+        }
+    }
+    Ok(empty)
+}
+
 fn discover_files(root: &str) -> anyhow::Result<FxHashSet<PathBuf>> {
     let root_path = PathBuf::from(root);
     let mut files = FxHashSet::default();
@@ -51,10 +95,29 @@ mod tests {
 
     #[test]
     fn files_by_remote_name_smoketest() {
-        let files = files_by_remote_name("src", "sources").unwrap();
+        let files = files_by_remote_name("src", "sources").unwrap().files;
         assert_eq!(
             files.get("sources/main.rs"),
             Some(&PathBuf::new().join("src").join("main.rs"))
         );
     }
+
+    #[test]
+    fn maps_listed_files_into_scopes() {
+        let listed = vec![
+            "./docs/index.html".to_string(),
+            "blog/post.html".to_string(),
+            "".to_string(),
+        ];
+        let docs = listed_remote_names(&listed, "site/", "site/docs/", "docs/");
+        assert_eq!(
+            docs.into_iter().collect::<Vec<_>>(),
+            vec!["docs/index.html"]
+        );
+        let mut all: Vec<_> = listed_remote_names(&listed, "site/", "site/", "/www/")
+            .into_iter()
+            .collect();
+        all.sort();
+        assert_eq!(all, vec!["www/blog/post.html", "www/docs/index.html"]);
+    }
 }
@@ -0,0 +1,179 @@
+use crate::api::StorageZoneClient;
+use crate::deploy_meta::DeployMeta;
+use crate::error::ThumperError;
+use crate::history::THUMPER_DIR;
+use chrono::Local;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The line in the lockfile naming who holds the lock
+const OWNER_PREFIX: &str = "owner=";
+/// Name of the lockfile unless --lockfile or thumper.toml says otherwise
+pub const DEFAULT_LOCKFILE: &str = ".thumper.lock";
+/// How long to wait for another sync to finish updating `.thumper/`
+const META_LOCK_WAIT: Duration = Duration::from_secs(60);
+const META_LOCK_POLL: Duration = Duration::from_secs(2);
+
+/// A lock found in the storage zone
+#[derive(Debug, PartialEq)]
+struct Held<'a> {
+    since: &'a str,
+    owner: Option<&'a str>,
+    holder: Vec<&'a str>,
+}
+
+fn parse(lock: &str) -> Held<'_> {
+    let mut lines = lock.lines().filter(|line| !line.is_empty());
+    let since = lines.next().unwrap_or_default();
+    let mut owner = None;
+    let mut holder = Vec::new();
+    for line in lines {
+        match line.strip_prefix(OWNER_PREFIX) {
+            Some(name) => owner = Some(name),
+            None => holder.push(line),
+        }
+    }
+    Held {
+        since,
+        owner,
+        holder,
+    }
+}
+
+/// Where to place the lock: below each synced path, so pipelines deploying separate subtrees of
+/// a zone don't contend, or once at the top of the zone. Locks below the paths don't cover
+/// `.thumper/`, which is shared by every sync to the zone, see [`with_meta_lock`].
+pub fn lockfiles(paths: &[&str], lockfile: &str, zone_lock: bool) -> Vec<String> {
+    if zone_lock {
+        vec![lockfile.to_string()]
+    } else {
+        paths
+            .iter()
+            .map(|path| format!("{}{lockfile}", path.trim_start_matches('/')))
+            .collect()
+    }
+}
+
+/// Take every lock, releasing the ones already taken if one of them is held
+pub fn take_all(
+    client: &StorageZoneClient,
+    lockfiles: &[String],
+    force: bool,
+    owner: Option<&str>,
+    meta: &DeployMeta,
+) -> anyhow::Result<()> {
+    for (i, lockfile) in lockfiles.iter().enumerate() {
+        if let Err(err) = take(client, lockfile, force, owner, meta) {
+            remove_all(client, &lockfiles[..i])?;
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Take the lock, unless someone else holds it and `force` is off. A lock held by `owner`
+/// is left over from an earlier attempt of the same run, and is taken over.
+fn take(
+    client: &StorageZoneClient,
+    lockfile: &str,
+    force: bool,
+    owner: Option<&str>,
+    meta: &DeployMeta,
+) -> anyhow::Result<()> {
+    if let Ok(lock) = client.read_file(lockfile) {
+        let held = parse(&lock);
+        let mut holder = held.holder.clone();
+        if let Some(owner) = held.owner {
+            holder.insert(0, owner);
+        }
+        if holder.is_empty() {
+            eprintln!("WARNING: Remote is locked since {}", held.since);
+        } else {
+            eprintln!(
+                "WARNING: Remote is locked since {} by {}",
+                held.since,
+                holder.join(", ")
+            );
+        }
+        let retried = owner.is_some() && held.owner == owner;
+        if retried {
+            eprintln!("Taking over the lock from an earlier attempt by the same owner");
+        } else if !force {
+            return Err(ThumperError::Conflict(format!(
+                "Dangling lock in {lockfile} prevents sync"
+            ))
+            .into());
+        }
+    }
+    let owner = owner
+        .map(|owner| format!("{OWNER_PREFIX}{owner}\n"))
+        .unwrap_or_default();
+    let lock = format!("{}\n{owner}{}", Local::now().to_rfc3339(), meta.lines());
+    client.put_file(lockfile, lock.into_bytes(), Some("text/plain"))?;
+    Ok(())
+}
+
+/// Run `update` on the shared history and manifests in `.thumper/` while holding a short lock on
+/// it, waiting a while for other syncs to finish their updates. `lockfile` is `None` when the
+/// sync runs without a lock, or when a lock at the top of the zone already covers `.thumper/`.
+pub fn with_meta_lock<T>(
+    client: &StorageZoneClient,
+    lockfile: Option<&str>,
+    force: bool,
+    owner: Option<&str>,
+    meta: &DeployMeta,
+    update: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let Some(lockfile) = lockfile else {
+        return update();
+    };
+    let lockfile = format!("{THUMPER_DIR}{lockfile}");
+    let started = Instant::now();
+    while !force && started.elapsed() < META_LOCK_WAIT && client.read_file(&lockfile).is_ok() {
+        thread::sleep(META_LOCK_POLL);
+    }
+    take(client, &lockfile, force, owner, meta)?;
+    let updated = update();
+    client.delete_file(&lockfile)?;
+    updated
+}
+
+pub fn remove_all(client: &StorageZoneClient, lockfiles: &[String]) -> anyhow::Result<()> {
+    for lockfile in lockfiles {
+        client.delete_file(lockfile)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_owner_and_holder() {
+        let held = parse("2025-01-01T00:00:00+00:00\nowner=1234\ngit_sha=abc\nbuild=42\n");
+        assert_eq!(
+            held,
+            Held {
+                since: "2025-01-01T00:00:00+00:00",
+                owner: Some("1234"),
+                holder: vec!["git_sha=abc", "build=42"],
+            }
+        );
+        let held = parse("2025-01-01T00:00:00+00:00\n");
+        assert_eq!(held.owner, None);
+        assert!(held.holder.is_empty());
+    }
+
+    #[test]
+    fn scopes_lockfiles_to_paths() {
+        assert_eq!(
+            lockfiles(&["/", "docs/", "/blog/"], ".thumper.lock", false),
+            vec![".thumper.lock", "docs/.thumper.lock", "blog/.thumper.lock"]
+        );
+        assert_eq!(
+            lockfiles(&["docs/", "blog/"], ".thumper.lock", true),
+            vec![".thumper.lock"]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::bench::format_bytes;
+use crate::bunny_api::BunnyApiClient;
+use crate::cli::LogsAction;
+use anyhow::{Context, anyhow};
+use chrono::{NaiveDate, Utc};
+use fxhash::FxHashMap;
+use reqwest::Method;
+
+const LOGGING_BASE: &str = "https://logging.bunnycdn.com";
+
+/// One request from the pull zone log, which is pipe separated:
+/// cache status, status code, timestamp, bytes sent, pull zone, ip, referer, url, ...
+#[derive(Debug, PartialEq)]
+pub struct LogLine<'a> {
+    pub cache_status: &'a str,
+    pub status: u16,
+    pub bytes: u64,
+    pub path: &'a str,
+}
+
+pub fn parse_line(line: &str) -> anyhow::Result<LogLine<'_>> {
+    let fields: Vec<_> = line.split('|').collect();
+    if fields.len() < 8 {
+        return Err(anyhow!("Unexpected log line: {line}"));
+    }
+    let url = fields[7];
+    let path = match url.find("://") {
+        Some(scheme) => {
+            let rest = &url[scheme + 3..];
+            rest.find('/').map(|pos| &rest[pos..]).unwrap_or("/")
+        }
+        None => url,
+    };
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    Ok(LogLine {
+        cache_status: fields[0],
+        status: fields[1].parse().context("Invalid status code")?,
+        bytes: fields[3].parse().context("Invalid byte count")?,
+        path,
+    })
+}
+
+/// The first `depth` directories of the path, like `/assets/` for depth 1
+fn prefix(path: &str, depth: usize) -> &str {
+    let mut end = 0;
+    for _ in 0..depth {
+        match path[end + 1..].find('/') {
+            Some(pos) => end += pos + 1,
+            None => break,
+        }
+    }
+    &path[..=end]
+}
+
+#[derive(Debug, Default)]
+pub struct TrafficReport {
+    pub requests: u64,
+    pub bytes: u64,
+    pub hits: u64,
+    pub paths: FxHashMap<String, u64>,
+    pub statuses: FxHashMap<u16, u64>,
+    pub prefixes: FxHashMap<String, u64>,
+}
+
+impl TrafficReport {
+    pub fn add(&mut self, line: &LogLine, prefix_depth: usize) {
+        self.requests += 1;
+        self.bytes += line.bytes;
+        if line.cache_status == "HIT" {
+            self.hits += 1;
+        }
+        *self.paths.entry(line.path.to_string()).or_default() += 1;
+        *self.statuses.entry(line.status).or_default() += 1;
+        *self
+            .prefixes
+            .entry(prefix(line.path, prefix_depth).to_string())
+            .or_default() += line.bytes;
+    }
+}
+
+/// Largest entries first, ties broken by key so the output is stable
+fn top<K: Ord + Clone>(counts: &FxHashMap<K, u64>, n: usize) -> Vec<(K, u64)> {
+    let mut entries: Vec<_> = counts.iter().map(|(k, v)| (k.clone(), *v)).collect();
+    entries.sort_by(|(ka, va), (kb, vb)| vb.cmp(va).then_with(|| ka.cmp(kb)));
+    entries.truncate(n);
+    entries
+}
+
+pub fn download(api: &BunnyApiClient, pullzone: u64, date: NaiveDate) -> anyhow::Result<String> {
+    let url = format!("{LOGGING_BASE}/{}/{pullzone}.log", date.format("%m-%d-%y"));
+    Ok(api.send(api.request(Method::GET, &url))?.text()?)
+}
+
+fn print_report(report: &TrafficReport, top_n: usize) {
+    let hit_rate = report.hits as f64 * 100.0 / report.requests.max(1) as f64;
+    println!(
+        "{} requests, {} sent, {hit_rate:.1}% cache hits",
+        report.requests,
+        format_bytes(report.bytes as f64)
+    );
+    println!("\nTop paths:");
+    for (path, count) in top(&report.paths, top_n) {
+        println!("{count:>10}  {path}");
+    }
+    println!("\nStatus codes:");
+    let mut statuses: Vec<_> = report.statuses.iter().collect();
+    statuses.sort();
+    for (status, count) in statuses {
+        println!("{status:>10}  {count}");
+    }
+    println!("\nBandwidth by prefix:");
+    for (prefix, bytes) in top(&report.prefixes, top_n) {
+        println!("{:>10}  {prefix}", format_bytes(bytes as f64));
+    }
+}
+
+fn report(
+    api: &BunnyApiClient,
+    pullzone: u64,
+    date: Option<NaiveDate>,
+    top_n: usize,
+    prefix_depth: usize,
+) -> anyhow::Result<()> {
+    let date = date.unwrap_or_else(|| Utc::now().date_naive());
+    let log = download(api, pullzone, date)?;
+    let mut report = TrafficReport::default();
+    for line in log.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_line(line) {
+            Ok(line) => report.add(&line, prefix_depth),
+            Err(err) => eprintln!("WARNING: Skipping line: {err}"),
+        }
+    }
+    print_report(&report, top_n);
+    Ok(())
+}
+
+pub fn run(api: &BunnyApiClient, action: LogsAction) -> anyhow::Result<()> {
+    match action {
+        LogsAction::Report {
+            pullzone,
+            date,
+            top,
+            prefix_depth,
+        } => report(api, pullzone, date, top, prefix_depth),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "HIT|404|1507167062000|4382|12345|192.0.2.1|-|https://example.b-cdn.net/assets/img/logo.png?v=2|DE|Mozilla/5.0|a1b2c3|DE";
+
+    #[test]
+    fn parses_log_lines() {
+        assert_eq!(
+            parse_line(LINE).unwrap(),
+            LogLine {
+                cache_status: "HIT",
+                status: 404,
+                bytes: 4382,
+                path: "/assets/img/logo.png",
+            }
+        );
+        assert!(parse_line("HIT|200").is_err());
+    }
+
+    #[test]
+    fn aggregates_by_prefix() {
+        assert_eq!(prefix("/assets/img/logo.png", 1), "/assets/");
+        assert_eq!(prefix("/assets/img/logo.png", 2), "/assets/img/");
+        assert_eq!(prefix("/index.html", 1), "/");
+
+        let mut report = TrafficReport::default();
+        let line = parse_line(LINE).unwrap();
+        report.add(&line, 1);
+        report.add(&line, 1);
+        assert_eq!(report.requests, 2);
+        assert_eq!(report.hits, 2);
+        assert_eq!(report.statuses[&404], 2);
+        assert_eq!(
+            top(&report.prefixes, 5),
+            vec![("/assets/".to_string(), 8764)]
+        );
+    }
+}
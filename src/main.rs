@@ -1,194 +1,2119 @@
-use crate::api::StorageZoneClient;
-use crate::cli::{Action, Cli, SyncArgs};
-use crate::planning::{Execution, SyncAction, SyncPlan, plan_execution, plan_sync};
 use anyhow::{Context, anyhow};
-use chrono::Local;
 use clap::{CommandFactory, Parser};
 use clap_complete::Shell::{Bash, Elvish, Fish, PowerShell, Zsh};
 use clap_complete::generate;
 use crossbeam::channel::unbounded;
 use fxhash::FxHashMap;
-use std::{env, fs, io, thread};
+use reqwest::Method;
+use reqwest::blocking::Client;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{env, io, thread};
+use std::{fmt, fs};
+use thumper::adaptive::Limiter;
+use thumper::api::{AsyncStorageZoneClient, DEFAULT_ENDPOINT, StorageZoneClient};
+use thumper::bench::{BenchPlan, format_bytes, parse_size};
+use thumper::bunny_api::BunnyApiClient;
+use thumper::cli::{
+    Action, BenchArgs, Cli, Concurrency, HttpArgs, InventoryArgs, OutputFormat, PreviewCommand,
+    PreviewRmArgs, PruneArgs, PullArgs, RmArgs, SyncArgs, VerifyManifestArgs,
+    VerifyReplicationArgs,
+};
+use thumper::config::{Config, DEFAULT_CONFIG};
+use thumper::deadline::{DeadlineExceeded, ResumeState};
+use thumper::deploy_meta::DeployMeta;
+use thumper::dir_summary::ByDir;
+use thumper::encryption::Cipher;
+use thumper::error::{TaskPanicked, ThumperError, contain_panic};
+use thumper::health::Expectation;
+use thumper::history::DeployRecord;
+use thumper::http::{HttpOptions, HttpTrace, build_async_client, build_client};
+use thumper::local_cache::LocalCache;
+use thumper::local_history::RunRecord;
+use thumper::local_path::{listed_remote_names, read_input};
+use thumper::pacing::Pacer;
+use thumper::permacache::PermaCache;
+use thumper::planning::{
+    Execution, PathRules, PlanOptions, SyncAction, SyncPlan, SystemObjects, UpdateMode,
+    detect_file_mime_type, is_page, merge_plans, plan, plan_execution, sha256, sha256_file,
+    without_ignored,
+};
+use thumper::preflight::Checklist;
+use thumper::progress::SyncProgress;
+use thumper::purge::PurgePacing;
+use thumper::remote_cache::{ListingKey, RemoteCache};
+use thumper::retry::RetryPolicy;
+use thumper::saved_plan::SavedPlan;
+use thumper::signing::DeployManifest;
+use thumper::source::{DigestMemo, FileSource, Placeholders};
+use thumper::sync_job::{SyncJob, normalize_path, normalize_root};
+use thumper::transform::Transforms;
+use thumper::{
+    bench, blue_green, canary, deadline, dns, edge_script, encryption, generator, health, history,
+    inventory, keys, lint, local_history, lock, logs, output, pacing, preflight, preview, prune,
+    pull, pullzone, purge, release, replication, rm, signing, source, spot_check, stats, stream,
+    validation, versions, zones,
+};
+use tokio::sync::{Semaphore, mpsc};
+use tokio::task::{self, JoinError, JoinSet};
 
-mod api;
-mod cli;
-mod local_path;
-mod planning;
+/// Whether a sync may change the storage zone. A dry run holds no client that can upload or
+/// delete, so it has no way to change the zone.
+#[derive(Clone, Copy)]
+enum RunMode<'a> {
+    DryRun,
+    Live(&'a StorageZoneClient),
+}
 
-fn execute_job(
-    client: &StorageZoneClient,
-    job: SyncPlan,
-    dry_run: bool,
-    lockfile: &str,
-) -> anyhow::Result<(String, &'static str)> {
-    let Execution { remote, action } = plan_execution(&job, fs::read)?;
+impl RunMode<'_> {
+    fn is_dry_run(self) -> bool {
+        matches!(self, RunMode::DryRun)
+    }
+}
+
+/// Everything the workers need to carry out a sync plan
+struct SyncContext<'a> {
+    run: RunMode<'a>,
+    source: &'a dyn FileSource,
+    local_cache: Option<&'a LocalCache>,
+    digests: &'a DigestMemo,
+    lockfiles: &'a [String],
+    retry: RetryPolicy,
+    limiter: &'a Limiter,
+    cipher: Option<&'a Cipher>,
+    /// Plaintext checksums of encrypted files, by remote name
+    manifest: &'a Mutex<FxHashMap<String, String>>,
+    /// Checksums of every synced file, when signing a deploy manifest
+    signed: Option<&'a Mutex<BTreeMap<String, String>>>,
+    /// When to stop starting new tasks
+    deadline: Option<Instant>,
+    /// How many previous versions of replaced files to keep
+    keep_versions: usize,
+    /// How long an upload may make no progress before it is retried on a fresh connection
+    stall_timeout: Option<Duration>,
+    http: &'a HttpOptions,
+    /// Files larger than this are hashed and uploaded without reading them into memory
+    stream_over: u64,
+    /// How to print each finished task, if at all
+    report: Option<OutputFormat>,
+    /// Spaces out uploads and deletes, with --request-rate
+    pacer: Option<&'a Pacer>,
+    progress: &'a SyncProgress,
+    /// The async client and how many requests it may have in flight, with --in-flight
+    in_flight: Option<(&'a AsyncStorageZoneClient, usize)>,
+}
+
+impl SyncContext<'_> {
+    /// Wait until --request-rate allows another request
+    fn pace(&self) {
+        if let Some(pacer) = self.pacer {
+            pacer.acquire();
+        }
+    }
+}
+
+fn local_digest(
+    source: &dyn FileSource,
+    digests: &DigestMemo,
+    local_cache: Option<&LocalCache>,
+    local: &Path,
+    content: &[u8],
+) -> [u8; 32] {
+    digests.get_or_compute(source.identity(local), || match local_cache {
+        Some(cache) => cache.sha256(local, content),
+        None => sha256(local, content),
+    })
+}
 
-    let event = match &action {
-        SyncAction::Put { .. } => "put",
-        SyncAction::Ignore => "unchanged",
-        SyncAction::Delete => "delete",
+/// Hash local files into `digests` until `stop` is set, so syncing finds most of them already
+/// hashed. Only sources that can identify files remember digests, so others are left alone.
+fn prehash(
+    source: &dyn FileSource,
+    digests: &DigestMemo,
+    local_cache: Option<&LocalCache>,
+    locals: &[FxHashMap<String, PathBuf>],
+    stream_over: u64,
+    stop: &AtomicBool,
+) {
+    for local in locals.iter().flat_map(|files| files.values()) {
+        if stop.load(Ordering::Relaxed) || source.identity(local).is_none() {
+            return;
+        }
+        // Large files are hashed in chunks when syncing instead
+        if streamable(source, local, stream_over).is_some() {
+            continue;
+        }
+        // Failures to read show up again when syncing, with better context
+        if let Ok(content) = source.read(local) {
+            local_digest(source, digests, local_cache, local, &content);
+        }
+    }
+}
+
+/// What a worker did with one task
+struct TaskResult {
+    remote: String,
+    event: &'static str,
+    /// Bytes uploaded
+    size: u64,
+    /// Whether the upload stalled and was retried on a fresh connection
+    stalled: bool,
+    /// From picking up the task until its request was done
+    duration: Duration,
+}
+
+/// Context of the error of a task, so it can be reported like the tasks that succeeded
+#[derive(Debug)]
+struct TaskFailed {
+    remote: String,
+    event: &'static str,
+    duration: Duration,
+}
+
+impl fmt::Display for TaskFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} failed after {:?}",
+            self.event, self.remote, self.duration
+        )
+    }
+}
+
+impl TaskFailed {
+    /// Context for errors of `job`, picked up at `started`
+    fn of(job: &SyncPlan, started: Instant) -> impl FnOnce() -> TaskFailed + use<> {
+        let remote = job.remote().to_string();
+        let event = match job {
+            SyncPlan::Delete { .. } => "delete",
+            _ => "put",
+        };
+        move || TaskFailed {
+            remote,
+            event,
+            duration: started.elapsed(),
+        }
+    }
+}
+
+/// What an upload sends
+enum Payload {
+    Bytes(Vec<u8>),
+    /// A file larger than --stream-over, read as it is sent
+    File(PathBuf),
+}
+
+/// A request a task still has to send to the storage zone
+enum Pending {
+    Put {
+        content: Payload,
+        mime_type: Option<&'static str>,
+        /// Whether the upload replaces a file, which may need its previous version kept
+        replace: bool,
+    },
+    Delete,
+}
+
+/// A task with its file read, hashed and encrypted, ready to send
+struct Prepared {
+    remote: String,
+    event: &'static str,
+    size: u64,
+    /// Nothing to send in dry runs, for unchanged files and for skipped tasks
+    pending: Option<Pending>,
+    /// When a worker picked up the task
+    started: Instant,
+}
+
+/// Warn that `event` of `remote` failed and will be attempted again
+fn retry_warning(
+    retry: RetryPolicy,
+    event: &str,
+    remote: &str,
+) -> impl Fn(usize, &anyhow::Error) + use<> {
+    let (event, remote) = (event.to_string(), remote.to_string());
+    move |attempt, err| {
+        eprintln!(
+            "WARNING: {event} {remote} failed on attempt {attempt}/{}, retrying: {err}",
+            retry.retries + 1
+        );
+    }
+}
+
+fn prepare_job(ctx: &SyncContext, job: SyncPlan, started: Instant) -> anyhow::Result<Prepared> {
+    let SyncContext {
+        run,
+        source,
+        local_cache,
+        digests,
+        lockfiles,
+        cipher,
+        manifest,
+        signed,
+        deadline,
+        ..
+    } = *ctx;
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return Ok(Prepared {
+            remote: job.remote().to_string(),
+            event: "skipped",
+            size: 0,
+            pending: None,
+            started,
+        });
+    }
+    if let SyncPlan::Put { local, remote } | SyncPlan::Replace { local, remote, .. } = &job
+        && cipher.is_none()
+        && let Some(file) = streamable(source, local, ctx.stream_over)
+    {
+        return prepare_streamed(ctx, &job, local, remote, file, started);
+    }
+    let checksum =
+        |local: &Path, content: &[u8]| local_digest(source, digests, local_cache, local, content);
+    let Execution { remote, action } = plan_execution(&job, |path| source.read(path), checksum)?;
+
+    let (event, size) = match &action {
+        SyncAction::Put { content, .. } => ("put", content.len() as u64),
+        SyncAction::Ignore => ("unchanged", 0),
+        SyncAction::Delete => ("delete", 0),
     };
-    if !dry_run {
-        match action {
-            SyncAction::Put { content, mime_type } => {
-                client.put_file(remote, content, mime_type)?;
-            }
-            SyncAction::Delete if remote != lockfile => {
-                client.delete_file(remote)?;
-            }
-            _ => {}
+    if let (Some(signed), false) = (signed, run.is_dry_run()) {
+        let checksum = match (&action, &job) {
+            (SyncAction::Put { content, .. }, _) => Some(Sha256::digest(content).into()),
+            (
+                SyncAction::Ignore,
+                SyncPlan::Replace {
+                    remote_checksum, ..
+                },
+            ) => *remote_checksum,
+            _ => None,
+        };
+        if let Some(checksum) = checksum {
+            signed
+                .lock()
+                .expect("signed manifest lock poisoned")
+                .insert(remote.to_string(), hex::encode::<[u8; 32]>(checksum));
         }
     }
+    let pending = match action {
+        _ if run.is_dry_run() => None,
+        SyncAction::Put { content, mime_type } => {
+            let (content, mime_type) = match cipher {
+                Some(cipher) => {
+                    manifest
+                        .lock()
+                        .expect("manifest lock poisoned")
+                        .insert(remote.to_string(), hex::encode(Sha256::digest(&content)));
+                    (cipher.encrypt(&content)?, Some("application/octet-stream"))
+                }
+                None => (content, mime_type),
+            };
+            Some(Pending::Put {
+                content: Payload::Bytes(content),
+                mime_type,
+                replace: matches!(job, SyncPlan::Replace { .. }),
+            })
+        }
+        SyncAction::Delete if !lockfiles.iter().any(|lockfile| lockfile == remote) => {
+            Some(Pending::Delete)
+        }
+        _ => None,
+    };
 
-    Ok((remote.to_string(), event))
+    Ok(Prepared {
+        remote: remote.to_string(),
+        event,
+        size,
+        pending,
+        started,
+    })
+}
+
+/// The file on disk to stream `local` from, if it is larger than `stream_over`
+fn streamable(source: &dyn FileSource, local: &Path, stream_over: u64) -> Option<PathBuf> {
+    source
+        .on_disk(local)
+        .filter(|file| fs::metadata(file).is_ok_and(|meta| meta.len() > stream_over))
+}
+
+/// Like [`prepare_job`] for a large file on disk, hashing it a chunk at a time and leaving it
+/// to be read while it is uploaded
+fn prepare_streamed(
+    ctx: &SyncContext,
+    job: &SyncPlan,
+    local: &Path,
+    remote: &str,
+    file: PathBuf,
+    started: Instant,
+) -> anyhow::Result<Prepared> {
+    let remote_checksum = match job {
+        SyncPlan::Replace {
+            remote_checksum, ..
+        } => *remote_checksum,
+        _ => None,
+    };
+    let digest = if remote_checksum.is_some() || ctx.signed.is_some() {
+        Some(sha256_file(&file).with_context(|| format!("Unable to read {}", file.display()))?)
+    } else {
+        None
+    };
+    if let (Some(signed), Some(digest), false) = (ctx.signed, digest, ctx.run.is_dry_run()) {
+        signed
+            .lock()
+            .expect("signed manifest lock poisoned")
+            .insert(remote.to_string(), hex::encode(digest));
+    }
+    if remote_checksum.is_some() && digest == remote_checksum {
+        return Ok(Prepared {
+            remote: remote.to_string(),
+            event: "unchanged",
+            size: 0,
+            pending: None,
+            started,
+        });
+    }
+    let size = fs::metadata(&file)?.len();
+    let pending = if ctx.run.is_dry_run() {
+        None
+    } else {
+        Some(Pending::Put {
+            mime_type: detect_file_mime_type(local, &file)?,
+            content: Payload::File(file),
+            replace: matches!(job, SyncPlan::Replace { .. }),
+        })
+    };
+    Ok(Prepared {
+        remote: remote.to_string(),
+        event: "put",
+        size,
+        pending,
+        started,
+    })
 }
 
-fn execute_sync(
-    verbose: bool,
-    dry_run: bool,
-    job: Vec<SyncPlan>,
+/// Send the request of a prepared task, returning whether the upload stalled
+fn send_job(
+    ctx: &SyncContext,
     client: &StorageZoneClient,
-    lockfile: &str,
-    concurrency: usize,
+    remote: &str,
+    event: &str,
+    pending: Pending,
+) -> anyhow::Result<bool> {
+    let SyncContext {
+        retry,
+        limiter,
+        manifest,
+        keep_versions,
+        stall_timeout,
+        http,
+        ..
+    } = *ctx;
+    let on_retry = retry_warning(retry, event, remote);
+    let mut stalled = false;
+    match pending {
+        Pending::Put {
+            content,
+            mime_type,
+            replace,
+        } => {
+            if keep_versions > 0 && replace {
+                retry.run(
+                    || {
+                        ctx.pace();
+                        limiter.run(|| versions::keep_previous(client, remote, keep_versions))
+                    },
+                    &on_retry,
+                )?;
+            }
+            let mut fresh: Option<StorageZoneClient> = None;
+            retry.run(
+                || {
+                    let client = fresh.as_ref().unwrap_or(client);
+                    ctx.pace();
+                    let result = limiter.run(|| match (&content, stall_timeout) {
+                        (Payload::Bytes(bytes), Some(stall)) => {
+                            client.put_file_watched(remote, bytes.clone(), mime_type, stall)
+                        }
+                        (Payload::Bytes(bytes), None) => {
+                            client.put_file(remote, bytes.clone(), mime_type)
+                        }
+                        (Payload::File(file), stall) => {
+                            client.put_local_file(remote, file, mime_type, stall)
+                        }
+                    });
+                    if result.as_ref().is_err_and(ThumperError::is_stalled) {
+                        // The stuck request keeps its connection, retry on a new one
+                        let renewed = client.clone().with_client(build_client(http)?);
+                        fresh = Some(renewed);
+                        stalled = true;
+                    }
+                    Ok(result?)
+                },
+                &on_retry,
+            )?;
+        }
+        Pending::Delete => {
+            retry.run(
+                || {
+                    ctx.pace();
+                    Ok(limiter.run(|| client.delete_file(remote))?)
+                },
+                &on_retry,
+            )?;
+            manifest
+                .lock()
+                .expect("manifest lock poisoned")
+                .remove(remote);
+        }
+    }
+    Ok(stalled)
+}
+
+/// Like [`send_job`], on the async client. Keeping versions and watching for stalls need the
+/// blocking client, so they can't be combined with --in-flight. The async client can't stream
+/// request bodies, so large files are read into memory just before they are sent.
+async fn send_job_async(
+    client: &AsyncStorageZoneClient,
+    retry: RetryPolicy,
+    remote: &str,
+    event: &str,
+    pending: Pending,
 ) -> anyhow::Result<()> {
+    let on_retry = retry_warning(retry, event, remote);
+    match pending {
+        Pending::Put {
+            content, mime_type, ..
+        } => {
+            retry
+                .run_async(
+                    || async {
+                        let content = match &content {
+                            Payload::Bytes(bytes) => bytes.clone(),
+                            Payload::File(file) => tokio::fs::read(file)
+                                .await
+                                .with_context(|| format!("Unable to read {}", file.display()))?,
+                        };
+                        Ok(client.put_file(remote, content, mime_type).await?)
+                    },
+                    on_retry,
+                )
+                .await
+        }
+        Pending::Delete => {
+            retry
+                .run_async(|| async { Ok(client.delete_file(remote).await?) }, on_retry)
+                .await
+        }
+    }
+}
+
+fn execute_job(ctx: &SyncContext, job: SyncPlan) -> anyhow::Result<TaskResult> {
+    let started = Instant::now();
+    let failed = TaskFailed::of(&job, started);
+    contain_panic(|| {
+        let Prepared {
+            remote,
+            event,
+            size,
+            pending,
+            started,
+        } = prepare_job(ctx, job, started)?;
+        let stalled = match (ctx.run, pending) {
+            (RunMode::Live(client), Some(pending)) => {
+                ctx.progress.sending(&remote);
+                let stalled = send_job(ctx, client, &remote, event, pending);
+                ctx.progress.sent(size);
+                stalled?
+            }
+            _ => false,
+        };
+        Ok::<_, anyhow::Error>(TaskResult {
+            remote,
+            event,
+            size,
+            stalled,
+            duration: started.elapsed(),
+        })
+    })
+    .with_context(failed)
+}
+
+/// What a sync did, or would have done in a dry run
+#[derive(Default)]
+struct SyncSummary {
+    uploaded: Vec<String>,
+    deleted: Vec<String>,
+    unchanged: usize,
+    /// Size of the uploaded files
+    upload_bytes: u64,
+    /// Tasks never started because the deadline passed
+    pending: Vec<String>,
+    /// Uploads that stalled and were retried on a fresh connection
+    stalled: Vec<String>,
+    by_dir: ByDir,
+    /// How long carrying out the plan took
+    elapsed: Duration,
+}
+
+impl SyncSummary {
+    fn extend(&mut self, other: SyncSummary) {
+        self.uploaded.extend(other.uploaded);
+        self.deleted.extend(other.deleted);
+        self.unchanged += other.unchanged;
+        self.upload_bytes += other.upload_bytes;
+        self.pending.extend(other.pending);
+        self.stalled.extend(other.stalled);
+        self.by_dir.extend(other.by_dir);
+        self.elapsed += other.elapsed;
+    }
+
+    /// Uploads and deletes sent per second while carrying out the plan
+    fn requests_per_second(&self) -> Option<f64> {
+        let requests = self.uploaded.len() + self.deleted.len();
+        (requests > 0 && !self.elapsed.is_zero())
+            .then(|| requests as f64 / self.elapsed.as_secs_f64())
+    }
+
+    /// Count a finished task, printing it in the `report` format if set
+    fn record(
+        &mut self,
+        result: TaskResult,
+        progress: &SyncProgress,
+        report: Option<OutputFormat>,
+    ) {
+        progress.task_done(report.map(|format| describe_task(&result, format)));
+        let TaskResult {
+            remote,
+            event,
+            size,
+            stalled,
+            ..
+        } = result;
+        if stalled {
+            self.stalled.push(remote.clone());
+        }
+        self.by_dir.count(&remote, event);
+        match event {
+            "put" => {
+                self.uploaded.push(remote);
+                self.upload_bytes += size;
+            }
+            "delete" => self.deleted.push(remote),
+            "skipped" => self.pending.push(remote),
+            _ => self.unchanged += 1,
+        }
+    }
+}
+
+/// One line about a finished task, like `docs/index.html: put` or a JSON object
+fn describe_task(result: &TaskResult, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => format!("{}: {}", result.remote, result.event),
+        OutputFormat::Json => serde_json::json!({
+            "type": "task",
+            "remote": result.remote,
+            "action": result.event,
+            "bytes": result.size,
+            "duration_ms": result.duration.as_millis() as u64,
+            "stalled": result.stalled,
+        })
+        .to_string(),
+    }
+}
+
+/// Report the task behind `err` in JSON output, which otherwise only shows the error at the end
+fn report_failure(ctx: &SyncContext, err: anyhow::Error) -> anyhow::Error {
+    if ctx.report == Some(OutputFormat::Json)
+        && let Some(failed) = err.downcast_ref::<TaskFailed>()
+    {
+        let cause: Vec<_> = err.chain().skip(1).map(|cause| cause.to_string()).collect();
+        let line = serde_json::json!({
+            "type": "task",
+            "remote": failed.remote,
+            "action": failed.event,
+            "duration_ms": failed.duration.as_millis() as u64,
+            "error": cause.join(": "),
+        });
+        ctx.progress.task_done(Some(line.to_string()));
+    }
+    err
+}
+
+/// Take down the locks when a live sync fails before running any task, so the next one doesn't
+/// find them in its way
+fn release_before_tasks(run: RunMode, lockfiles: &[String], err: anyhow::Error) -> anyhow::Error {
+    if let RunMode::Live(client) = run
+        && let Err(lock_err) = lock::remove_all(client, lockfiles)
+    {
+        eprintln!("WARNING: Unable to remove the lock: {lock_err:#}");
+    }
+    err
+}
+
+/// Take down the locks after a task panicked. Locks stay behind when a task fails, so a retry
+/// by the same owner picks up where the sync stopped, but a bug won't go away by retrying.
+fn release_after_panic(ctx: &SyncContext, err: anyhow::Error) -> anyhow::Error {
+    let panicked = err.chain().any(|cause| cause.is::<TaskPanicked>());
+    if let (RunMode::Live(client), true) = (ctx.run, panicked)
+        && let Err(lock_err) = lock::remove_all(client, ctx.lockfiles)
+    {
+        eprintln!("WARNING: Unable to remove the lock after the panic: {lock_err:#}");
+    }
+    err
+}
+
+/// Run the stages one after the other, timing how long it takes
+fn execute_sync(ctx: &SyncContext, stages: Vec<Vec<SyncPlan>>) -> anyhow::Result<SyncSummary> {
+    let started = Instant::now();
+    let mut summary = match ctx.in_flight {
+        Some((client, in_flight)) => execute_sync_in_flight(ctx, client, in_flight, stages),
+        None => execute_sync_threads(ctx, stages),
+    }
+    .map_err(|err| release_after_panic(ctx, report_failure(ctx, err)))?;
+    summary.elapsed = started.elapsed();
+    Ok(summary)
+}
+
+/// Run the stages one after the other, each spread over the worker pool
+fn execute_sync_threads(
+    ctx: &SyncContext,
+    stages: Vec<Vec<SyncPlan>>,
+) -> anyhow::Result<SyncSummary> {
     let (send_work, receive_work) = unbounded();
     let (send_result, receive_result) = unbounded();
-    let expected = job.len();
 
     thread::scope(move |scope| {
-        for action in job {
-            send_work.send(action)?;
-        }
-
-        for _ in 0..concurrency {
+        for _ in 0..ctx.limiter.workers() {
             let receive_work = receive_work.clone();
             let send_result = send_result.clone();
 
             scope.spawn(move || {
                 while let Ok(action) = receive_work.recv() {
-                    let r = execute_job(client, action, dry_run, lockfile);
+                    let r = execute_job(ctx, action);
                     send_result.send(r)?;
                 }
                 Ok::<(), anyhow::Error>(())
             });
         }
 
-        for _ in 0..expected {
-            let (remote, event) = receive_result.recv()??;
-            if verbose || dry_run {
-                println!("{remote}: {event}");
+        let mut summary = SyncSummary::default();
+        for stage in stages {
+            let expected = stage.len();
+            for action in stage {
+                send_work.send(action)?;
+            }
+            for _ in 0..expected {
+                summary.record(receive_result.recv()??, ctx.progress, ctx.report);
             }
         }
 
         drop(send_work);
 
-        Ok::<_, anyhow::Error>(())
+        Ok::<_, anyhow::Error>(summary)
     })
 }
 
-fn take_lock(client: &StorageZoneClient, lockfile: &str, force: bool) -> anyhow::Result<()> {
-    if let Ok(sync_time) = client.read_file(lockfile) {
-        eprintln!("WARNING: Remote is locked since {sync_time}");
-        if !force {
-            return Err(anyhow!("Dangling lock in {lockfile} prevents sync"));
+/// Like [`execute_sync_threads`], but the workers only read, hash and encrypt files, while a tokio
+/// runtime sends the uploads and deletes with up to `in_flight` requests at a time
+fn execute_sync_in_flight(
+    ctx: &SyncContext,
+    client: &AsyncStorageZoneClient,
+    in_flight: usize,
+    stages: Vec<Vec<SyncPlan>>,
+) -> anyhow::Result<SyncSummary> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let requests = Arc::new(Semaphore::new(in_flight));
+    let (send_work, receive_work) = unbounded();
+    let (send_prepared, mut receive_prepared) = mpsc::channel(in_flight);
+    let finish = |summary: &mut SyncSummary, result: TaskResult| {
+        if result.event == "delete" {
+            ctx.manifest
+                .lock()
+                .expect("manifest lock poisoned")
+                .remove(&result.remote);
         }
-    }
-    let now = Local::now();
-    let ts = now.to_rfc3339();
-    client.put_file(lockfile, ts.bytes().collect(), Some("text/plain"))
-}
+        ctx.progress.sent(result.size);
+        summary.record(result, ctx.progress, ctx.report);
+    };
+    // The outcome of a request, failing the task it was for if it panicked
+    let joined =
+        |sending: &mut FxHashMap<task::Id, (TaskFailed, Instant)>,
+         sent: Result<(task::Id, anyhow::Result<TaskResult>), JoinError>| {
+            let id = match &sent {
+                Ok((id, _)) => *id,
+                Err(err) => err.id(),
+            };
+            let (failed, started) = sending.remove(&id).expect("every request has a task");
+            match sent {
+                Ok((_, result)) => result,
+                Err(err) => {
+                    let cause = match err.try_into_panic() {
+                        Ok(payload) => anyhow::Error::new(TaskPanicked::from_payload(payload)),
+                        Err(err) => anyhow::Error::new(err),
+                    };
+                    Err(cause.context(TaskFailed {
+                        duration: started.elapsed(),
+                        ..failed
+                    }))
+                }
+            }
+        };
+
+    thread::scope(move |scope| {
+        for _ in 0..ctx.limiter.workers() {
+            let receive_work = receive_work.clone();
+            let send_prepared = send_prepared.clone();
 
-fn remove_lock(client: &StorageZoneClient, lockfile: &str) -> anyhow::Result<()> {
-    client.delete_file(lockfile)
+            scope.spawn(move || {
+                while let Ok(action) = receive_work.recv() {
+                    let started = Instant::now();
+                    let failed = TaskFailed::of(&action, started);
+                    let prepared =
+                        contain_panic(|| prepare_job(ctx, action, started)).with_context(failed);
+                    // Nobody receives once the sync has failed
+                    if send_prepared.blocking_send(prepared).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(send_prepared);
+
+        let mut summary = SyncSummary::default();
+        for stage in stages {
+            let expected = stage.len();
+            for action in stage {
+                send_work.send(action)?;
+            }
+            runtime.block_on(async {
+                let mut tasks = JoinSet::new();
+                // Which task each request is for, to name the file when one panics
+                let mut sending = FxHashMap::default();
+                for _ in 0..expected {
+                    let Prepared {
+                        remote,
+                        event,
+                        size,
+                        pending,
+                        started,
+                    } = receive_prepared
+                        .recv()
+                        .await
+                        .context("Sync workers stopped early")??;
+                    let Some(pending) = pending else {
+                        let result = TaskResult {
+                            remote,
+                            event,
+                            size,
+                            stalled: false,
+                            duration: started.elapsed(),
+                        };
+                        summary.record(result, ctx.progress, ctx.report);
+                        continue;
+                    };
+                    let permit = requests.clone().acquire_owned().await?;
+                    // Retries go unpaced, only the dispatch of each request waits its turn
+                    ctx.pace();
+                    ctx.progress.sending(&remote);
+                    let (client, retry) = (client.clone(), ctx.retry);
+                    let failed = TaskFailed {
+                        remote: remote.clone(),
+                        event,
+                        duration: Duration::ZERO,
+                    };
+                    let handle = tasks.spawn(async move {
+                        let _permit = permit;
+                        send_job_async(&client, retry, &remote, event, pending)
+                            .await
+                            .with_context(|| TaskFailed {
+                                remote: remote.clone(),
+                                event,
+                                duration: started.elapsed(),
+                            })?;
+                        Ok::<_, anyhow::Error>(TaskResult {
+                            remote,
+                            event,
+                            size,
+                            stalled: false,
+                            duration: started.elapsed(),
+                        })
+                    });
+                    sending.insert(handle.id(), (failed, started));
+                    while let Some(sent) = tasks.try_join_next_with_id() {
+                        finish(&mut summary, joined(&mut sending, sent)?);
+                    }
+                }
+                while let Some(sent) = tasks.join_next_with_id().await {
+                    finish(&mut summary, joined(&mut sending, sent)?);
+                }
+                Ok::<_, anyhow::Error>(())
+            })?;
+        }
+
+        drop(send_work);
+
+        Ok::<_, anyhow::Error>(summary)
+    })
 }
 
-struct SyncJob {
-    client: StorageZoneClient,
-    path: String,
-    local_path: String,
+/// The storage zone password, and whether it came from the flag or the environment
+fn use_access_key(access_key: Option<String>) -> anyhow::Result<(String, &'static str)> {
+    keys::resolve(access_key, "--access-key", &keys::STORAGE_KEY_VARS, |var| {
+        env::var(var).ok()
+    })
+    .context(
+        "No storage zone password provided with --storage-key, THUMPER_STORAGE_KEY or THUMPER_KEY",
+    )
 }
 
-fn normalize_path(mut path: String) -> String {
-    if path.ends_with("/") {
-        path
-    } else {
-        path.push('/');
-        path
+/// Bytes per second from sizes like 10m or 500k/s
+fn parse_bandwidth(bandwidth: &str) -> anyhow::Result<f64> {
+    let bytes = parse_size(bandwidth.trim_end_matches("/s"))?;
+    if bytes == 0 {
+        return Err(anyhow!("--bandwidth must be more than 0"));
     }
+    Ok(bytes as f64)
 }
 
-fn init_sync(
-    access_key: Option<String>,
-    local_path: String,
-    path: String,
-    storage_zone: String,
-    endpoint: String,
-) -> anyhow::Result<SyncJob> {
-    let access_key = access_key
-        .or_else(|| env::var("THUMPER_KEY").ok())
-        .context("No API key provided with --access-key or THUMPER_KEY")?;
-    let client = StorageZoneClient::new(access_key, endpoint, storage_zone);
+/// Load the configuration of a sync, filling in what the flags leave out from its [sync] section
+/// or the profile picked with --profile
+fn configure_sync(args: &mut SyncArgs) -> anyhow::Result<(Config, String)> {
+    let config = Config::load(args.config.as_deref())?;
+    let defaults = config.sync_defaults(args.profile.as_deref())?;
+    if let (Some(profile), Some(given), Some(configured)) =
+        (&args.profile, &args.storage_zone, &defaults.storage_zone)
+        && given != configured
+    {
+        return Err(anyhow!(
+            "Profile {profile} syncs to storage zone {configured}, not {given}. Leave out the \
+             storage zone to use the profile's"
+        ));
+    }
+    let storage_zone = args
+        .storage_zone
+        .clone()
+        .or_else(|| defaults.storage_zone.clone())
+        .context(
+            "No storage zone given, pass it after the local path or set storage_zone in the \
+             [sync] section of thumper.toml",
+        )?;
+    args.storage_zone = Some(storage_zone.clone());
+    args.endpoint = args.endpoint.take().or_else(|| defaults.endpoint.clone());
+    if args.path.is_empty() {
+        args.path = vec![
+            defaults
+                .remote_path
+                .clone()
+                .unwrap_or_else(|| "/".to_string()),
+        ];
+    }
+    if args.ignore.is_empty() {
+        args.ignore = defaults.ignore.clone();
+    }
+    args.concurrency = args
+        .concurrency
+        .or(defaults.concurrency.map(Concurrency::Fixed));
+    args.lockfile = args.lockfile.take().or_else(|| defaults.lockfile.clone());
+    args.system_prefix
+        .extend(defaults.system_prefixes.iter().cloned());
+    Ok((config, storage_zone))
+}
 
-    Ok(SyncJob {
-        client,
-        path: normalize_path(path),
-        local_path: normalize_path(local_path),
-    })
+/// Sync, and remember how it went in the local history unless it was a dry run
+fn sync_and_record(
+    mut args: SyncArgs,
+    output: OutputFormat,
+    env: Option<&str>,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let (config, storage_zone) = configure_sync(&mut args)?;
+    let (paths, dry_run) = (args.path.clone(), args.dry_run);
+    let result = do_sync(args, config, &storage_zone, output);
+    if !dry_run {
+        let outcome = result.as_ref().map(|summary| {
+            (
+                summary.uploaded.len(),
+                summary.deleted.len(),
+                summary.unchanged,
+                summary.upload_bytes,
+            )
+        });
+        let record = RunRecord::new(env, &storage_zone, &paths, started.elapsed(), outcome);
+        if let Err(err) = local_history::append(&record) {
+            eprintln!("WARNING: Unable to record the run in the local history: {err:#}");
+        }
+    }
+    result.map(|_| ())
 }
 
-fn do_sync(args: SyncArgs) -> anyhow::Result<()> {
+/// Sync with `args` that [`configure_sync`] filled in from `config`
+fn do_sync(
+    args: SyncArgs,
+    config: Config,
+    storage_zone: &str,
+    output: OutputFormat,
+) -> anyhow::Result<SyncSummary> {
+    let started = Instant::now();
     let SyncArgs {
         endpoint,
         access_key,
+        fallback_access_key,
         local_path,
-        storage_zone,
         path,
         dry_run,
+        max_files,
         force,
+        lock_owner,
         lockfile,
+        zone_lock,
+        no_lock,
         ignore,
+        protect,
+        system_prefix,
         verbose,
         concurrency,
+        in_flight,
+        stream_over,
+        request_rate,
+        request_burst,
+        no_progress,
+        default_content_type,
+        lint,
+        strict,
+        summary_by_dir,
+        remote_cache,
+        remote_cache_ttl,
+        retries,
+        retry_delay,
+        max_retry_delay,
+        stall_timeout,
+        http,
+        wait_replicated,
+        replication_timeout,
+        files_from,
+        deploy_meta,
+        local_cache,
+        local_cache_hash,
+        skip_existing,
+        existing_only,
+        no_history,
+        spot_check,
+        blue_green,
+        health_check,
+        expect_status,
+        expect_contains,
+        health_timeout,
+        rollback_on_failure,
+        canary,
+        canary_check,
+        canary_timeout,
+        sign_manifest,
+        encrypt,
+        keep_marker,
+        purge_html,
+        purge_base_url,
+        purge_permacache,
+        purge_if_pages_changed,
+        preview,
+        keep_versions,
+        api_key,
+        pacing,
+        bandwidth,
+        deadline,
+        state_file,
+        ..
     } = args;
 
-    let concurrency = concurrency.unwrap_or_else(num_cpus::get);
+    validation::check(validation::validate(&local_path, &path, &ignore, &protect)?)?;
+    let endpoint = endpoint.unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+    let lockfile = lockfile.unwrap_or_else(|| lock::DEFAULT_LOCKFILE.to_string());
+    let tiers = config.upload.tiers()?;
+    let bandwidth = bandwidth.as_deref().map(parse_bandwidth).transpose()?;
+    let stream_over = parse_size(&stream_over)? as u64;
+    let pacer = request_rate.map(|rate| Pacer::new(request_burst, rate));
+    let cipher = encrypt.as_deref().map(Cipher::parse).transpose()?;
+    let signing_key = sign_manifest
+        .as_deref()
+        .map(signing::load_signing_key)
+        .transpose()?;
+    let adaptive = concurrency == Some(Concurrency::Auto);
+    if in_flight == Some(0) {
+        return Err(anyhow!("--in-flight must be more than 0"));
+    }
+    if adaptive && in_flight.is_some() {
+        return Err(anyhow!(
+            "--in-flight bounds the requests in flight, so it can't be combined with --concurrency auto"
+        ));
+    }
+    let (concurrency, limiter) = match concurrency {
+        Some(Concurrency::Fixed(concurrency)) => (concurrency, Limiter::fixed(concurrency)),
+        Some(Concurrency::Auto) => (num_cpus::get(), Limiter::adaptive()),
+        None => (num_cpus::get(), Limiter::fixed(num_cpus::get())),
+    };
+    let retry = RetryPolicy {
+        retries,
+        delay: retry_delay,
+        max_delay: max_retry_delay,
+    };
+    let cache = remote_cache.map(|dir| RemoteCache::new(dir, remote_cache_ttl));
+    let mode = if skip_existing {
+        UpdateMode::SkipExisting
+    } else if existing_only {
+        UpdateMode::ExistingOnly
+    } else {
+        UpdateMode::All
+    };
+    let http: HttpOptions = http.into();
+    let shared = build_client(&http)?;
+    let account_key = if purge_html
+        || purge_permacache.is_some()
+        || purge_if_pages_changed.is_some()
+        || blue_green.is_some()
+    {
+        Some(use_account_key(api_key).context(
+            "No account API key provided with --account-key, THUMPER_ACCOUNT_KEY or \
+             THUMPER_API_KEY, it is needed to purge and to switch blue-green slots",
+        )?)
+    } else {
+        None
+    };
+    let account = account_key.as_ref().map(|(key, _)| {
+        BunnyApiClient::new(key.clone())
+            .with_client(shared.clone())
+            .with_trace(http.trace)
+    });
+    if blue_green.is_some() && path.len() > 1 {
+        return Err(anyhow!(
+            "--blue-green syncs a whole slot, pass at most one --path"
+        ));
+    }
+    if let Some(id) = &preview {
+        preview::check_id(id)?;
+    }
+    let path = match &preview {
+        Some(id) => path
+            .iter()
+            .map(|path| preview::preview_path(id, path))
+            .collect(),
+        None => path,
+    };
+    let slots = match (&account, blue_green) {
+        (Some(api), Some(pullzone)) => Some(blue_green::slots(api, pullzone)?),
+        _ => None,
+    };
+    let path = match &slots {
+        Some(slots) => path.iter().map(|path| slots.inactive_path(path)).collect(),
+        None => path,
+    };
+
+    let meta_dir = Path::new(&local_path);
+    let meta_dir = if meta_dir.is_dir() {
+        meta_dir
+    } else {
+        Path::new(".")
+    };
+    let meta = DeployMeta::collect(meta_dir, deploy_meta);
+    let (source, local_root) = source::open(&local_path)?;
+    let source: Box<dyn FileSource> = if config.transform.is_empty() {
+        source
+    } else {
+        Box::new(Transforms::new(source, &config.transform)?)
+    };
+    let source = match &keep_marker {
+        Some(marker) => Box::new(Placeholders::new(source, marker.clone())),
+        None => source,
+    };
+    let listed = files_from
+        .map(|from| read_input(&from))
+        .transpose()?
+        .map(|input| input.lines().map(str::to_string).collect::<Vec<_>>());
+    let root = normalize_root(local_root.clone());
+    let (access_key, origin) = use_access_key(access_key)?;
+    if let Some((account_key, account_origin)) = &account_key {
+        keys::check_distinct((&access_key, origin), (account_key, account_origin))?;
+    }
+    let mut builder = SyncJob::builder(storage_zone, &access_key)
+        .endpoint(&endpoint)
+        .key_origin(origin)
+        .fallback_keys(fallback_access_key)
+        .local_path(&local_root);
+    for path in &path {
+        builder = builder.path(path);
+    }
+    let SyncJob { client, scopes } = builder.build();
+    let client = client
+        .with_client(shared.clone())
+        .with_trace(http.trace)
+        .with_chaos(http.chaos)
+        .with_retry(retry)
+        .with_default_content_type(default_content_type.clone());
+    // Everything is read through a read-only client, only a live run keeps the one that can write
+    let reader = client.clone().read_only();
+    let writer = (!dry_run).then_some(client);
+    let run = match &writer {
+        Some(client) => RunMode::Live(client),
+        None => RunMode::DryRun,
+    };
+    let async_client = match (&writer, in_flight) {
+        (Some(client), Some(_)) => Some(client.to_async(build_async_client(&http)?)),
+        _ => None,
+    };
+    let mut checklist = Checklist::default();
+    let paths: Vec<_> = scopes.iter().map(|scope| scope.path.as_str()).collect();
+    preflight::check_settings(&mut checklist, concurrency, &ignore, &protect, &paths);
+    // Discover local files while checking the remote, they don't depend on each other
+    let locals: Vec<_> = thread::scope(|scope| {
+        let discovery = scope.spawn(|| {
+            scopes
+                .iter()
+                .map(|scope| {
+                    source.files_by_remote_name(scope.local_path.as_str(), scope.path.as_str())
+                })
+                .collect()
+        });
+        preflight::check_remote(&mut checklist, &reader, storage_zone, &paths);
+        discovery.join().expect("local discovery panicked")
+    });
+    for (scope, local) in scopes.iter().zip(&locals) {
+        let files = local
+            .as_ref()
+            .map(|names| names.files.len())
+            .map_err(|err| anyhow!("{err:#}"));
+        // Inside archives, the scopes are relative to the top of the archive
+        let label = if root.is_empty() {
+            format!("{local_path}/{}", scope.local_path)
+        } else {
+            scope.local_path.clone()
+        };
+        preflight::check_local(&mut checklist, &label, files);
+    }
+    if let Some(max_files) = max_files {
+        let files = locals.iter().flatten().map(|names| names.files.len()).sum();
+        preflight::check_file_count(&mut checklist, files, max_files);
+    }
+    checklist.finish()?;
+
+    let protected: Vec<_> = ignore.iter().chain(&protect).cloned().collect();
+    let plan_options = PlanOptions {
+        protected: protected.clone(),
+        mode,
+        keep_marker: keep_marker.clone(),
+        system: SystemObjects::new(&lockfile, &system_prefix),
+    };
+    let ignored = PathRules::new(&ignore);
+    // Only files the sync may touch can go missing, and a file list syncs only what it lists
+    let required: Vec<_> = match listed {
+        Some(_) => vec![],
+        None => config.lint.required_files(),
+    }
+    .into_iter()
+    .filter(|name| !ignored.matches(name))
+    .collect();
+    let listing_keys: Vec<_> = scopes
+        .iter()
+        .map(|scope| ListingKey {
+            endpoint: endpoint.as_str(),
+            storage_zone,
+            path: scope.path.as_str(),
+            skip: &ignore,
+        })
+        .collect();
+
+    if let Some(state) = ResumeState::load(&state_file, storage_zone) {
+        eprintln!(
+            "Resuming the sync stopped at its deadline at {}, {} tasks were left",
+            state.stopped_at,
+            state.pending.len()
+        );
+    }
+    let lock_owner = lock_owner.or_else(|| env::var("GITHUB_RUN_ID").ok());
+    let lockfiles = if no_lock || !config.lock {
+        vec![]
+    } else {
+        lock::lockfiles(&paths, &lockfile, zone_lock)
+    };
+    // Fail on duplicate names before taking the lock, there is nothing to clean up yet
+    let locals = locals
+        .into_iter()
+        .map(|local| Ok(without_ignored(local?.unique(strict)?, &ignore)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if let RunMode::Live(client) = run {
+        lock::take_all(client, &lockfiles, force, lock_owner.as_deref(), &meta)?;
+    }
+    let manifest = match &cipher {
+        Some(cipher) => encryption::load_manifest(&reader, cipher)
+            .map_err(|err| release_before_tasks(run, &lockfiles, err))?,
+        None => FxHashMap::default(),
+    };
+    let digests = DigestMemo::default();
+    let local_cache = local_cache.map(|path| LocalCache::load(path, local_cache_hash));
+    let planning_done = AtomicBool::new(false);
+    let plans = thread::scope(|scope| {
+        // Hash while the remote is listed, the listing mostly waits for the network
+        scope.spawn(|| {
+            prehash(
+                source.as_ref(),
+                &digests,
+                local_cache.as_ref(),
+                &locals,
+                stream_over,
+                &planning_done,
+            )
+        });
+        let plans = (|| {
+            let mut plans = Vec::with_capacity(scopes.len());
+            for ((scope, listing_key), local) in scopes.iter().zip(&listing_keys).zip(&locals) {
+                // Only dry runs may use a cached listing, a real sync must plan from the actual remote state
+                let mut remote = match cache.as_ref().filter(|_| dry_run) {
+                    Some(cache) => match cache.load(listing_key) {
+                        Some((remote, age)) => {
+                            eprintln!("Using cached remote listing from {}s ago", age.as_secs());
+                            remote
+                        }
+                        None => {
+                            let remote =
+                                reader.list_files(scope.path.as_str(), &ignore, concurrency)?;
+                            cache.store(listing_key, &remote)?;
+                            remote
+                        }
+                    },
+                    None => reader.list_files(scope.path.as_str(), &ignore, concurrency)?,
+                };
+                if keep_versions > 0 {
+                    // Previous versions have no local counterpart, but must not be deleted
+                    remote.retain(|name, _| !versions::is_version(name));
+                }
+                if cipher.is_some() {
+                    encryption::use_plaintext_checksums(&mut remote, &manifest);
+                }
+                let filtered;
+                let local = match &listed {
+                    Some(listed) => {
+                        let names =
+                            listed_remote_names(listed, &root, &scope.local_path, &scope.path);
+                        remote.retain(|name, _| names.contains(name));
+                        filtered = local
+                            .iter()
+                            .filter(|(name, _)| names.contains(*name))
+                            .map(|(name, path)| (name.clone(), path.clone()))
+                            .collect();
+                        &filtered
+                    }
+                    None => local,
+                };
+                let plan = plan(local, &remote, &plan_options).tasks;
+                for problem in lint::check_required(&required, &scope.path, &remote, &plan) {
+                    eprintln!("WARNING: {problem}");
+                }
+                plans.push(plan);
+            }
+            Ok::<_, anyhow::Error>(plans)
+        })();
+        planning_done.store(true, Ordering::Relaxed);
+        plans
+    })
+    .map_err(|err| release_before_tasks(run, &lockfiles, err))?;
+    let manifest = Mutex::new(manifest);
+    let signed = Mutex::new(BTreeMap::new());
+    let job = merge_plans(plans, &tiers);
+    let progress = SyncProgress::new(
+        job.iter().map(Vec::len).sum(),
+        !no_progress && output == OutputFormat::Text,
+    );
+    let ctx = SyncContext {
+        run,
+        source: source.as_ref(),
+        local_cache: local_cache.as_ref(),
+        digests: &digests,
+        lockfiles: &lockfiles,
+        retry,
+        limiter: &limiter,
+        cipher: cipher.as_ref(),
+        manifest: &manifest,
+        signed: signing_key.as_ref().map(|_| &signed),
+        deadline: deadline
+            .map(|deadline| started + deadline)
+            .filter(|_| !dry_run),
+        keep_versions,
+        stall_timeout,
+        http: &http,
+        stream_over,
+        report: match output {
+            OutputFormat::Json => Some(OutputFormat::Json),
+            OutputFormat::Text => (verbose || dry_run).then_some(OutputFormat::Text),
+        },
+        pacer: pacer.as_ref(),
+        progress: &progress,
+        in_flight: async_client.as_ref().zip(in_flight),
+    };
+    if lint && let Err(err) = lint::check(source.as_ref(), &job, &default_content_type) {
+        if let RunMode::Live(client) = run {
+            lock::remove_all(client, &lockfiles)?;
+        }
+        return Err(err);
+    }
+    let sent: FxHashMap<_, _> = if spot_check > 0 {
+        job.iter()
+            .flatten()
+            .filter_map(|plan| match plan {
+                SyncPlan::Put { local, remote } | SyncPlan::Replace { local, remote, .. } => {
+                    Some((remote.clone(), local.clone()))
+                }
+                SyncPlan::Delete { .. } => None,
+            })
+            .collect()
+    } else {
+        FxHashMap::default()
+    };
+    // The zone changes as soon as the first task runs, even if the sync fails after it
+    if let (RunMode::Live(_), Some(cache)) = (run, &cache) {
+        cache.invalidate(&endpoint, storage_zone);
+    }
+    let summary = match run {
+        RunMode::Live(client) if !canary.is_empty() => {
+            let (canary_plans, rest) = canary::split(job, &canary);
+            let backups = canary::backup(client, &canary_plans)
+                .map_err(|err| release_before_tasks(run, &lockfiles, err))?;
+            let mut summary = execute_sync(&ctx, vec![canary_plans])?;
+            if let Err(err) =
+                health::run_checks(&shared, &canary_check, &Expectation::ok(canary_timeout))
+            {
+                let reverted = canary::revert(client, backups, &summary.uploaded)?;
+                lock::remove_all(client, &lockfiles)?;
+                return Err(err.context(format!("Canary failed, reverted {reverted} files")));
+            }
+            summary.extend(execute_sync(&ctx, rest)?);
+            summary
+        }
+        _ => execute_sync(&ctx, job)?,
+    };
+    progress.finish();
+    if adaptive && verbose {
+        eprintln!("Concurrency settled at {}", limiter.limit());
+    }
+    let hint = pacing::request_bound_hint(
+        summary.uploaded.len(),
+        summary.upload_bytes,
+        in_flight.is_some(),
+    );
+    if let (Some(hint), false) = (hint, dry_run) {
+        eprintln!("{hint}");
+    }
+    if let Some(cache) = local_cache {
+        cache.save()?;
+    }
+    let estimate = bandwidth
+        .map(|bandwidth| (bandwidth, "from --bandwidth"))
+        .or_else(|| {
+            local_history::bandwidth_to(storage_zone)
+                .map(|bandwidth| (bandwidth, "as measured in earlier syncs"))
+        })
+        .filter(|_| dry_run)
+        .map(|(bandwidth, origin)| (summary.upload_bytes as f64 / bandwidth, bandwidth, origin));
+    match output {
+        OutputFormat::Text => {
+            let verb = if dry_run { "Would sync" } else { "Synced" };
+            let described = if meta.entries.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", meta.describe())
+            };
+            println!(
+                "{verb}: {} uploaded, {} deleted, {} unchanged{described}",
+                summary.uploaded.len(),
+                summary.deleted.len(),
+                summary.unchanged
+            );
+            if summary_by_dir {
+                for line in summary.by_dir.lines() {
+                    println!("{line}");
+                }
+            }
+            if let (Some(rate), false) = (summary.requests_per_second(), dry_run) {
+                println!(
+                    "Sent {} requests in {:.1}s, {rate:.1} requests/s",
+                    summary.uploaded.len() + summary.deleted.len(),
+                    summary.elapsed.as_secs_f64()
+                );
+            }
+            if !summary.stalled.is_empty() {
+                println!(
+                    "Retried {} stalled uploads on a fresh connection: {}",
+                    summary.stalled.len(),
+                    summary.stalled.join(", ")
+                );
+            }
+            if dry_run {
+                let size = format_bytes(summary.upload_bytes as f64);
+                match &estimate {
+                    Some((seconds, bandwidth, origin)) => println!(
+                        "Would upload {size}, about {} at {}/s {origin}",
+                        humantime::format_duration(Duration::from_secs(seconds.ceil() as u64)),
+                        format_bytes(*bandwidth)
+                    ),
+                    None => println!(
+                        "Would upload {size}, pass --bandwidth to estimate how long it takes"
+                    ),
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let meta: FxHashMap<_, _> = meta.entries.iter().cloned().collect();
+            let summary = serde_json::json!({
+                "type": "summary",
+                "dry_run": dry_run,
+                "uploaded": summary.uploaded.len(),
+                "deleted": summary.deleted.len(),
+                "unchanged": summary.unchanged,
+                "stalled": summary.stalled,
+                "by_dir": summary_by_dir.then_some(&summary.by_dir),
+                "upload_bytes": summary.upload_bytes,
+                "requests_per_second": summary.requests_per_second().filter(|_| !dry_run),
+                "estimated_seconds": estimate.as_ref().map(|(seconds, _, _)| seconds),
+                "meta": meta,
+            });
+            println!("{summary}");
+        }
+    }
+    let synced: Vec<String> = scopes.iter().map(|scope| scope.path.clone()).collect();
+    // Syncs of other paths share .thumper/, a lock at the top of the zone already covers it
+    let meta_lockfile = (!lockfiles.is_empty() && !zone_lock).then_some(lockfile.as_str());
+    let with_meta_lock = |client, update: &mut dyn FnMut() -> anyhow::Result<()>| {
+        lock::with_meta_lock(
+            client,
+            meta_lockfile,
+            force,
+            lock_owner.as_deref(),
+            &meta,
+            update,
+        )
+    };
+    if let (RunMode::Live(client), false) = (run, summary.pending.is_empty()) {
+        if let Some(cipher) = &cipher {
+            let manifest = manifest.into_inner().expect("manifest lock poisoned");
+            with_meta_lock(client, &mut || {
+                encryption::save_manifest(client, cipher, &manifest, &synced)
+            })?;
+        }
+        lock::remove_all(client, &lockfiles)?;
+        let state = ResumeState {
+            storage_zone: storage_zone.to_string(),
+            paths: synced,
+            stopped_at: chrono::Local::now().to_rfc3339(),
+            pending: summary.pending,
+        };
+        state.save(&state_file)?;
+        return Err(DeadlineExceeded {
+            pending: state.pending.len(),
+            state_file,
+        }
+        .into());
+    }
+    if let RunMode::Live(client) = run {
+        let record = (!no_history).then(|| {
+            DeployRecord::new(
+                synced.clone(),
+                (
+                    summary.uploaded.len(),
+                    summary.deleted.len(),
+                    summary.unchanged,
+                ),
+                started.elapsed(),
+                &meta,
+            )
+        });
+        let manifest = manifest.into_inner().expect("manifest lock poisoned");
+        let mut signed = Some(DeployManifest {
+            paths: synced.clone(),
+            unmanaged: protected.clone(),
+            files: signed.into_inner().expect("signed manifest lock poisoned"),
+        });
+        with_meta_lock(client, &mut || {
+            if let Some(record) = &record {
+                history::append(client, record)?;
+            }
+            if let Some(cipher) = &cipher {
+                encryption::save_manifest(client, cipher, &manifest, &synced)?;
+            }
+            if let (Some(key), Some(signed)) = (&signing_key, signed.take()) {
+                signing::publish(client, signed, key)?;
+            }
+            Ok(())
+        })?;
+        lock::remove_all(client, &lockfiles)?;
+        ResumeState::clear(&state_file)?;
+        if let (Some(id), Some(base_url)) = (&preview, &purge_base_url) {
+            println!("Preview {id} is at {}", preview::url(base_url, id));
+        }
+        if spot_check > 0 {
+            spot_check::spot_check(
+                client,
+                source.as_ref(),
+                &sent,
+                &summary.uploaded,
+                spot_check,
+            )?;
+        }
+        if let Some(regions) = wait_replicated {
+            replication::wait_for_replication(
+                client,
+                &summary.uploaded,
+                &regions,
+                replication_timeout,
+            )?;
+        }
+        if let (Some(api), Some(pullzone), Some(slots)) = (&account, blue_green, &slots) {
+            blue_green::flip(api, pullzone, slots)?;
+        }
+        if let (Some(api), Some(base_url), true) = (&account, &purge_base_url, purge_html) {
+            let pages = purge::html_paths(summary.uploaded.iter().chain(&summary.deleted));
+            let urls = pages
+                .iter()
+                .map(|page| purge::url_for(base_url, page))
+                .collect();
+            purge::purge_urls(api, urls, pacing.into())?;
+        }
+        if let (Some(api), Some(pullzone)) = (&account, purge_if_pages_changed) {
+            let pages = config.upload.page_extensions();
+            let changed = summary.uploaded.iter().chain(&summary.deleted);
+            if changed.clone().any(|name| is_page(name, &pages)) {
+                purge::purge_pull_zone(api, pullzone)?;
+                println!("Purged pull zone {pullzone}");
+            } else {
+                println!("No pages changed, left the cache of pull zone {pullzone} alone");
+            }
+        }
+        if let (Some(api), Some(pullzone)) = (&account, purge_permacache) {
+            let changed: Vec<_> = summary
+                .uploaded
+                .iter()
+                .chain(&summary.deleted)
+                .cloned()
+                .collect();
+            let purged = PermaCache::open(api, pullzone)?.purge_paths(&changed, concurrency)?;
+            println!("Deleted {purged} files from the Perma-Cache of pull zone {pullzone}");
+        }
+        if !health_check.is_empty() {
+            let expect = Expectation {
+                status: expect_status,
+                contains: expect_contains,
+                timeout: health_timeout,
+            };
+            if let Err(err) = health::run_checks(&shared, &health_check, &expect) {
+                if let (true, Some(api), Some(pullzone), Some(slots)) =
+                    (rollback_on_failure, &account, blue_green, &slots)
+                {
+                    blue_green::flip(api, pullzone, &slots.flipped())?;
+                    return Err(err.context("Rolled back after failing health checks"));
+                }
+                return Err(err);
+            }
+        }
+    }
+    Ok(summary)
+}
 
-    let SyncJob {
-        client,
+fn do_rm(args: RmArgs) -> anyhow::Result<()> {
+    let RmArgs {
+        endpoint,
+        access_key,
+        storage_zone,
         path,
-        local_path,
-    } = init_sync(access_key, local_path, path, storage_zone, endpoint)?;
-    if !dry_run {
-        take_lock(&client, lockfile.as_str(), force)?;
+        recursive,
+        concurrency,
+        http,
+    } = args;
+
+    let http: HttpOptions = http.into();
+    let (access_key, origin) = use_access_key(access_key)?;
+    let client = StorageZoneClient::new(access_key, endpoint, storage_zone)
+        .with_key_origin(origin)
+        .with_client(build_client(&http)?)
+        .with_trace(http.trace)
+        .with_chaos(http.chaos);
+    let path = path.trim_start_matches('/');
+    if path.is_empty() && recursive {
+        return Err(anyhow!("Refusing to delete the whole storage zone"));
     }
-    let local = local_path::files_by_remote_name(local_path.as_str(), path.as_str())?;
-    let remote = client.list_files(path.as_str(), &ignore, concurrency)?;
-    let job = plan_sync(&local, &remote, &ignore);
-    execute_sync(
-        verbose,
+    let path = if recursive {
+        normalize_path(path.to_string())
+    } else {
+        path.to_string()
+    };
+    rm::remove(
+        &client,
+        &path,
+        recursive,
+        concurrency.unwrap_or_else(num_cpus::get),
+    )
+}
+
+fn do_verify_manifest(args: VerifyManifestArgs) -> anyhow::Result<()> {
+    let VerifyManifestArgs {
+        endpoint,
+        access_key,
+        storage_zone,
+        public_key,
+        concurrency,
+        http,
+    } = args;
+
+    let http: HttpOptions = http.into();
+    let (access_key, origin) = use_access_key(access_key)?;
+    let client = StorageZoneClient::new(access_key, endpoint, storage_zone)
+        .with_key_origin(origin)
+        .with_client(build_client(&http)?)
+        .with_trace(http.trace)
+        .with_chaos(http.chaos)
+        .read_only();
+    signing::verify(
+        &client,
+        &public_key,
+        concurrency.unwrap_or_else(num_cpus::get),
+    )
+}
+
+fn do_preview_rm(args: PreviewRmArgs) -> anyhow::Result<()> {
+    let PreviewRmArgs {
+        endpoint,
+        access_key,
+        storage_zone,
+        id,
+        concurrency,
+        http,
+    } = args;
+
+    let http: HttpOptions = http.into();
+    let (access_key, origin) = use_access_key(access_key)?;
+    let client = StorageZoneClient::new(access_key, endpoint, storage_zone)
+        .with_key_origin(origin)
+        .with_client(build_client(&http)?)
+        .with_trace(http.trace)
+        .with_chaos(http.chaos);
+    preview::remove(&client, &id, concurrency.unwrap_or_else(num_cpus::get))
+}
+
+fn do_prune(args: PruneArgs) -> anyhow::Result<()> {
+    let PruneArgs {
+        endpoint,
+        access_key,
+        storage_zone,
+        prefix,
+        older_than,
         dry_run,
-        job,
+        concurrency,
+        http,
+    } = args;
+
+    let prefix = normalize_path(prefix.trim_start_matches('/').to_string());
+    if prefix == "/" {
+        return Err(anyhow!(
+            "Refusing to prune the whole storage zone, pass a --prefix"
+        ));
+    }
+    let http: HttpOptions = http.into();
+    let (access_key, origin) = use_access_key(access_key)?;
+    let client = StorageZoneClient::new(access_key, endpoint, storage_zone)
+        .with_key_origin(origin)
+        .with_client(build_client(&http)?)
+        .with_trace(http.trace)
+        .with_chaos(http.chaos);
+    prune::prune(
         &client,
-        lockfile.as_str(),
+        &prefix,
+        older_than,
+        dry_run,
+        concurrency.unwrap_or_else(num_cpus::get),
+    )
+}
+
+fn do_inventory(args: InventoryArgs) -> anyhow::Result<()> {
+    let InventoryArgs {
+        endpoint,
+        access_key,
+        storage_zone,
+        path,
+        out,
+        compare,
         concurrency,
+        http,
+    } = args;
+
+    let http: HttpOptions = http.into();
+    let (access_key, origin) = use_access_key(access_key)?;
+    let client = StorageZoneClient::new(access_key, endpoint, storage_zone)
+        .with_key_origin(origin)
+        .with_client(build_client(&http)?)
+        .with_trace(http.trace)
+        .with_chaos(http.chaos)
+        .read_only();
+    let items = inventory::collect(
+        &client,
+        &normalize_path(path),
+        concurrency.unwrap_or_else(num_cpus::get),
     )?;
-    if !dry_run {
-        remove_lock(&client, lockfile.as_str())?;
+    let csv = inventory::to_csv(&items);
+    match &out {
+        Some(out) => {
+            fs::write(out, csv)?;
+            eprintln!("Wrote {} files to {}", items.len(), out.display());
+        }
+        None => print!("{csv}"),
+    }
+    if let Some(previous) = compare {
+        let previous_items = inventory::parse_csv(&read_input(&previous)?)?;
+        let changes = inventory::changes_since(&previous_items, &items);
+        eprintln!("{} changes since {}", changes.len(), previous.display());
+        for change in changes {
+            eprintln!("  {change}");
+        }
+    }
+    Ok(())
+}
+
+fn do_verify_replication(args: VerifyReplicationArgs) -> anyhow::Result<()> {
+    let VerifyReplicationArgs {
+        endpoint,
+        access_key,
+        storage_zone,
+        path,
+        regions,
+        concurrency,
+        http,
+    } = args;
+
+    let http: HttpOptions = http.into();
+    let (access_key, origin) = use_access_key(access_key)?;
+    let client = StorageZoneClient::new(access_key, endpoint, storage_zone)
+        .with_key_origin(origin)
+        .with_client(build_client(&http)?)
+        .with_trace(http.trace)
+        .with_chaos(http.chaos)
+        .read_only();
+    let items = inventory::collect(
+        &client,
+        &normalize_path(path),
+        concurrency.unwrap_or_else(num_cpus::get),
+    )?;
+    let unreplicated = replication::unreplicated(&items, &regions);
+    for (path, missing) in &unreplicated {
+        println!("{path}: missing {}", missing.join(","));
+    }
+    if unreplicated.is_empty() {
+        println!("All {} files are replicated", items.len());
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} of {} files are missing regions",
+            unreplicated.len(),
+            items.len()
+        ))
+    }
+}
+
+fn do_pull(args: PullArgs) -> anyhow::Result<()> {
+    let PullArgs {
+        endpoint,
+        access_key,
+        storage_zone,
+        local_path,
+        path,
+        delete,
+        dry_run,
+        concurrency,
+        encrypt,
+        http,
+    } = args;
+
+    let cipher = encrypt.as_deref().map(Cipher::parse).transpose()?;
+    let http: HttpOptions = http.into();
+    let (access_key, origin) = use_access_key(access_key)?;
+    let client = StorageZoneClient::new(access_key, endpoint, storage_zone)
+        .with_key_origin(origin)
+        .with_client(build_client(&http)?)
+        .with_trace(http.trace)
+        .with_chaos(http.chaos)
+        .read_only();
+    pull::pull(
+        &client,
+        &normalize_path(path),
+        &local_path,
+        delete,
+        dry_run,
+        concurrency.unwrap_or_else(num_cpus::get),
+        cipher.as_ref(),
+    )
+}
+
+fn do_plan(
+    mut args: SyncArgs,
+    out: Option<PathBuf>,
+    compare: Option<PathBuf>,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    args.dry_run = true;
+    let (config, storage_zone) = configure_sync(&mut args)?;
+    let paths = args.path.clone();
+    let summary = do_sync(args, config, &storage_zone, output)?;
+    let plan = SavedPlan {
+        storage_zone,
+        paths,
+        put: summary.uploaded.into_iter().collect(),
+        delete: summary.deleted.into_iter().collect(),
+    };
+    if let Some(previous) = compare {
+        let changes = plan.changes_since(&SavedPlan::load(&previous)?);
+        if changes.is_empty() {
+            println!("No changes to the plan since {}", previous.display());
+        } else {
+            println!("Changes to the plan since {}:", previous.display());
+            for change in changes {
+                println!("  {change}");
+            }
+        }
+    }
+    if let Some(out) = out {
+        plan.save(&out)?;
     }
     Ok(())
 }
 
+fn do_bench(args: BenchArgs) -> anyhow::Result<()> {
+    let BenchArgs {
+        endpoint,
+        access_key,
+        storage_zone,
+        path,
+        sizes,
+        count,
+        concurrency,
+        http,
+    } = args;
+
+    let http: HttpOptions = http.into();
+    let (access_key, origin) = use_access_key(access_key)?;
+    let client = StorageZoneClient::new(access_key, endpoint, storage_zone)
+        .with_key_origin(origin)
+        .with_client(build_client(&http)?)
+        .with_trace(http.trace)
+        .with_chaos(http.chaos);
+    let sizes = sizes
+        .iter()
+        .map(|size| bench::parse_size(size))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if concurrency.contains(&0) {
+        return Err(anyhow!("Concurrency levels must be at least 1"));
+    }
+    let plan = BenchPlan {
+        path: normalize_path(path),
+        sizes,
+        count,
+        concurrency,
+    };
+    bench::run(&client, &plan)
+}
+
+/// The HTTP client the account API commands share, configured like the one sync uses
+struct AccountApi {
+    client: Client,
+    trace: HttpTrace,
+}
+
+impl AccountApi {
+    /// A client for the account API, or another bunny.net API, authenticating with `key`
+    fn api(&self, key: String) -> BunnyApiClient {
+        BunnyApiClient::new(key)
+            .with_client(self.client.clone())
+            .with_trace(self.trace)
+    }
+}
+
+fn connect_account(http: HttpArgs) -> anyhow::Result<AccountApi> {
+    let http: HttpOptions = http.into();
+    Ok(AccountApi {
+        client: build_client(&http)?,
+        trace: http.trace,
+    })
+}
+
+/// The account API key, and whether it came from the flag or the environment
+fn use_account_key(api_key: Option<String>) -> Option<(String, &'static str)> {
+    keys::resolve(api_key, "--api-key", &keys::ACCOUNT_KEY_VARS, |var| {
+        env::var(var).ok()
+    })
+}
+
 fn use_api_key(api_key: Option<String>) -> anyhow::Result<String> {
-    api_key
-        .or_else(|| env::var("THUMPER_API_KEY").ok())
-        .context("No API key provided with --api-key or thumper_API_KEY")
+    use_account_key(api_key)
+        .map(|(key, _)| key)
+        .context("No API key provided with --account-key, THUMPER_ACCOUNT_KEY or THUMPER_API_KEY")
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let output = cli.output;
+    match run(cli.command, output) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            output::report_error(&err, output);
+            if err.downcast_ref::<DeadlineExceeded>().is_some() {
+                ExitCode::from(deadline::EXIT_CODE)
+            } else if let Some(err) = ThumperError::find(&err) {
+                ExitCode::from(err.exit_code())
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
 
-    match args.command {
-        Action::Sync { args } => do_sync(args),
+fn run(command: Action, output: OutputFormat) -> anyhow::Result<()> {
+    match command {
+        Action::Sync { args } => sync_and_record(*args, output, None),
+        Action::History {
+            storage_zone,
+            limit,
+        } => local_history::show(storage_zone.as_deref(), limit),
+        Action::Plan { args, out, compare } => do_plan(*args, out, compare, output),
+        Action::Bench { args } => do_bench(args),
+        Action::Rm { args } => do_rm(args),
+        Action::VerifyManifest { args } => do_verify_manifest(args),
+        Action::Inventory { args } => do_inventory(args),
+        Action::VerifyReplication { args } => do_verify_replication(args),
+        Action::Pull { args } => do_pull(args),
+        Action::Prune { args } => do_prune(args),
+        Action::Preview {
+            command: PreviewCommand::Rm { args },
+        } => do_preview_rm(args),
+        Action::Init {
+            storage_zone,
+            env,
+            local_path,
+        } => {
+            if Path::new(DEFAULT_CONFIG).exists() {
+                return Err(anyhow!("{DEFAULT_CONFIG} already exists"));
+            }
+            let local_path = match local_path {
+                Some(local_path) => local_path,
+                None => generator::publish_dir(Path::new("."))?,
+            };
+            fs::write(
+                DEFAULT_CONFIG,
+                generator::config_template(&env, &local_path, &storage_zone),
+            )?;
+            println!("Wrote {DEFAULT_CONFIG}, release with thumper release --env {env}");
+            Ok(())
+        }
+        Action::Release {
+            env,
+            config,
+            dry_run,
+            api_key,
+            http,
+        } => {
+            let connect = connect_account(http)?;
+            let mut loaded = Config::load(config.as_deref())?;
+            let environment = loaded
+                .env
+                .remove(&env)
+                .ok_or_else(|| anyhow!("No [env.{env}] in the configuration"))?;
+            let api = if environment.purge_pullzones.is_empty() {
+                None
+            } else {
+                Some(connect.api(use_api_key(api_key)?))
+            };
+            release::run(
+                &env,
+                &environment,
+                api.as_ref(),
+                &connect.client,
+                dry_run,
+                |mut args| {
+                    // The syncs read the same configuration file as the release
+                    args.config = args.config.or_else(|| config.clone());
+                    sync_and_record(args, output, Some(&env))
+                },
+            )
+        }
+        Action::EdgeScript {
+            api_key,
+            command,
+            http,
+        } => {
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
+            edge_script::run(&api, command)
+        }
+        Action::Dns {
+            api_key,
+            command,
+            http,
+        } => {
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
+            dns::run(&api, command)
+        }
+        Action::Pullzone {
+            api_key,
+            command,
+            http,
+        } => {
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
+            pullzone::run(&api, command)
+        }
+        Action::Keys {
+            api_key,
+            command,
+            http,
+        } => {
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
+            keys::run(&api, command)
+        }
+        Action::Logs {
+            api_key,
+            command,
+            http,
+        } => {
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
+            logs::run(&api, command)
+        }
+        Action::Stats {
+            api_key,
+            command,
+            http,
+        } => {
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
+            stats::run(&api, command)
+        }
+        Action::Stream {
+            api_key,
+            library_key,
+            command,
+            http,
+        } => {
+            let connect = connect_account(http)?;
+            let account = use_account_key(api_key).map(|(key, _)| connect.api(key));
+            let library_key = library_key.or_else(|| env::var("THUMPER_STREAM_KEY").ok());
+            stream::run(
+                account.as_ref(),
+                library_key,
+                |key| connect.api(key),
+                command,
+            )
+        }
+        Action::Zones {
+            api_key,
+            command,
+            http,
+        } => {
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
+            zones::run(&api, command)
+        }
         Action::Completions { shell } => {
             let sh = match shell.as_str() {
                 "bash" => Ok(Bash),
@@ -202,41 +2127,63 @@ fn main() -> anyhow::Result<()> {
             generate(sh, &mut com, "thumper", &mut io::stdout());
             Ok(())
         }
-        Action::PurgeUrl { url, api_key } => {
-            let key = use_api_key(api_key)?;
-            let client = reqwest::blocking::Client::new();
+        Action::PurgeUrl { url, api_key, http } => {
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
             let encoded = urlencoding::encode(url.as_str());
-            let response = client
-                .post("https://api.bunny.net/purge")
-                .query(&[("url", encoded.as_ref())])
-                .header("AccessKey", key.as_str())
-                .send()?;
-            Ok(response
-                .error_for_status()
-                .map(|_| println!("Purged {url}"))?)
+            api.send(
+                api.request(Method::POST, "purge")
+                    .query(&[("url", encoded.as_ref())]),
+            )?;
+            println!("Purged {url}");
+            Ok(())
+        }
+        Action::PurgePaths {
+            base_url,
+            paths,
+            from_sync_output,
+            api_key,
+            pacing,
+            purge_permacache,
+            http,
+        } => {
+            if paths.is_empty() && from_sync_output.is_none() {
+                return Err(anyhow!("Pass paths to purge or --from-sync-output"));
+            }
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
+            let pacing: PurgePacing = pacing.into();
+            let paths =
+                purge::purge_paths(&api, &base_url, paths, from_sync_output.as_deref(), pacing)?;
+            if let Some(pullzone) = purge_permacache {
+                let purged =
+                    PermaCache::open(&api, pullzone)?.purge_paths(&paths, pacing.concurrency)?;
+                println!("Deleted {purged} files from the Perma-Cache of pull zone {pullzone}");
+            }
+            Ok(())
         }
         Action::PurgeZone {
             pullzone,
             api_key,
             cache_tag,
+            purge_permacache,
+            http,
         } => {
-            let key = use_api_key(api_key)?;
-            let client = reqwest::blocking::Client::new();
-            let request = client
-                .post(format!(
-                    "https://api.bunny.net/pullzone/{pullzone}/purgeCache"
-                ))
-                .header("AccessKey", key);
-            let response = if let Some(tag) = cache_tag {
-                let mut form = FxHashMap::default();
-                form.insert("CacheTag", tag);
-                request.form(&form).send()
-            } else {
-                request.send()
-            }?;
-            Ok(response
-                .error_for_status()
-                .map(|_| println!("Purged {pullzone}"))?)
+            let api = connect_account(http)?.api(use_api_key(api_key)?);
+            let request = api.request(Method::POST, &format!("pullzone/{pullzone}/purgeCache"));
+            let request = match cache_tag {
+                Some(tag) => {
+                    let mut form = FxHashMap::default();
+                    form.insert("CacheTag", tag);
+                    request.form(&form)
+                }
+                None => request,
+            };
+            api.send(request)?;
+            println!("Purged {pullzone}");
+            if purge_permacache {
+                let purged = PermaCache::open(&api, pullzone)?.purge_all(num_cpus::get())?;
+                println!("Deleted {purged} files from the Perma-Cache of pull zone {pullzone}");
+            }
+            Ok(())
         }
     }
 }
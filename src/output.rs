@@ -0,0 +1,176 @@
+use crate::api::HttpError;
+use crate::cli::OutputFormat;
+use crate::deadline::DeadlineExceeded;
+use crate::error::ThumperError;
+use serde::Serialize;
+use std::io;
+
+/// An error as reported with `--output json`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ErrorReport {
+    pub category: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Whether running the same command again may succeed
+    pub retryable: bool,
+}
+
+fn http_category(status: u16) -> &'static str {
+    match status {
+        401 | 403 => "auth",
+        404 => "not_found",
+        429 => "rate_limited",
+        500.. => "server",
+        _ => "http",
+    }
+}
+
+/// Classify the error by the most specific cause thumper knows about
+pub fn describe_error(err: &anyhow::Error) -> ErrorReport {
+    let message = format!("{err:#}");
+    for cause in err.chain() {
+        if cause.downcast_ref::<DeadlineExceeded>().is_some() {
+            return ErrorReport {
+                category: "deadline",
+                message,
+                path: None,
+                status: None,
+                retryable: true,
+            };
+        }
+        if let Some(thumper) = cause.downcast_ref::<ThumperError>() {
+            let http = thumper.http();
+            let network = match thumper {
+                ThumperError::Transport(error) => error.downcast_ref::<reqwest::Error>(),
+                _ => None,
+            };
+            let category = match (thumper, http) {
+                (ThumperError::Integrity(_), _) => "integrity",
+                (ThumperError::Conflict(_), _) => "conflict",
+                (ThumperError::Io(_), _) => "io",
+                (_, Some(http)) => http_category(http.status.as_u16()),
+                _ => "network",
+            };
+            return ErrorReport {
+                category,
+                message,
+                path: http
+                    .map(|http| http.path.clone())
+                    .or_else(|| network?.url().map(|url| url.path().to_string())),
+                status: http
+                    .map(|http| http.status)
+                    .or_else(|| network?.status())
+                    .map(|status| status.as_u16()),
+                retryable: thumper.is_retryable(),
+            };
+        }
+        if let Some(http) = cause.downcast_ref::<HttpError>() {
+            let status = http.status.as_u16();
+            return ErrorReport {
+                category: http_category(status),
+                message,
+                path: Some(http.path.clone()),
+                status: Some(status),
+                retryable: status == 429 || status >= 500,
+            };
+        }
+        if let Some(network) = cause.downcast_ref::<reqwest::Error>() {
+            return ErrorReport {
+                category: "network",
+                message,
+                path: network.url().map(|url| url.path().to_string()),
+                status: network.status().map(|status| status.as_u16()),
+                retryable: network.is_timeout() || network.is_connect() || network.is_request(),
+            };
+        }
+        if cause.downcast_ref::<io::Error>().is_some() {
+            return ErrorReport {
+                category: "io",
+                message,
+                path: None,
+                status: None,
+                retryable: false,
+            };
+        }
+    }
+    ErrorReport {
+        category: "other",
+        message,
+        path: None,
+        status: None,
+        retryable: false,
+    }
+}
+
+pub fn report_error(err: &anyhow::Error, output: OutputFormat) {
+    match output {
+        OutputFormat::Text => eprintln!("Error: {err:?}"),
+        OutputFormat::Json => {
+            let report = serde_json::json!({ "error": describe_error(err) });
+            eprintln!("{report}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use reqwest::{Method, StatusCode};
+
+    #[test]
+    fn classifies_http_errors() {
+        let err = Err::<(), _>(anyhow::Error::from(HttpError {
+            method: Method::PUT,
+            path: "/docs/index.html".to_string(),
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            retry_after: None,
+        }))
+        .context("Upload failed")
+        .unwrap_err();
+        assert_eq!(
+            describe_error(&err),
+            ErrorReport {
+                category: "server",
+                message: "Upload failed: PUT /docs/index.html failed: 503 Service Unavailable"
+                    .to_string(),
+                path: Some("/docs/index.html".to_string()),
+                status: Some(503),
+                retryable: true,
+            }
+        );
+    }
+
+    #[test]
+    fn classifies_other_errors() {
+        let err = anyhow::Error::from(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(describe_error(&err).category, "io");
+        assert_eq!(
+            describe_error(&anyhow::anyhow!("Dangling lock")).category,
+            "other"
+        );
+        let conflict = anyhow::Error::from(ThumperError::Conflict("Dangling lock".to_string()));
+        let report = describe_error(&conflict);
+        assert_eq!((report.category, report.retryable), ("conflict", true));
+        let not_found = anyhow::Error::from(ThumperError::from(HttpError {
+            method: Method::GET,
+            path: "/docs/.thumper.lock".to_string(),
+            status: StatusCode::NOT_FOUND,
+            retry_after: None,
+        }));
+        let report = describe_error(&not_found);
+        assert_eq!(
+            (report.category, report.status, report.retryable),
+            ("not_found", Some(404), false)
+        );
+        let deadline = anyhow::Error::from(DeadlineExceeded {
+            pending: 3,
+            state_file: ".thumper-state.json".into(),
+        });
+        let report = describe_error(&deadline);
+        assert_eq!((report.category, report.retryable), ("deadline", true));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::bench::format_bytes;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Token bucket shared between workers: allows `burst` requests at once, then `rate` per second
+pub struct Pacer {
+    burst: f64,
+    rate: f64,
+    bucket: Mutex<(f64, Instant)>,
+}
+
+/// Tokens in the bucket after `elapsed` has passed since it held `tokens`
+fn refill(tokens: f64, elapsed: Duration, rate: f64, burst: f64) -> f64 {
+    (tokens + elapsed.as_secs_f64() * rate).min(burst)
+}
+
+impl Pacer {
+    pub fn new(burst: usize, rate: f64) -> Self {
+        let burst = burst.max(1) as f64;
+        Pacer {
+            burst,
+            rate,
+            bucket: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Block until a request may be sent
+    pub fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().expect("pacer lock poisoned");
+                let now = Instant::now();
+                let tokens = refill(bucket.0, now - bucket.1, self.rate, self.burst);
+                if tokens >= 1.0 {
+                    *bucket = (tokens - 1.0, now);
+                    return;
+                }
+                *bucket = (tokens, now);
+                Duration::from_secs_f64((1.0 - tokens) / self.rate)
+            };
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// How long to hold off after a 429 Too Many Requests without a Retry-After header
+pub const DEFAULT_RATE_LIMIT_WAIT: Duration = Duration::from_secs(1);
+/// The longest Retry-After honored, anything longer is more likely a mistake than a plan
+pub const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(300);
+
+/// Holds back every worker sharing it once one of them is rate limited, until the wait
+/// bunny.net asked for is over
+#[derive(Debug, Default)]
+pub struct Throttle {
+    resume_at: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    /// Hold off requests for `wait`, unless they already are for longer
+    pub fn pause(&self, wait: Duration) {
+        let until = Instant::now() + wait.min(MAX_RATE_LIMIT_WAIT);
+        let mut resume_at = self.resume_at.lock().expect("throttle lock poisoned");
+        if resume_at.is_none_or(|at| at < until) {
+            *resume_at = Some(until);
+        }
+    }
+
+    /// How long until requests may be sent again, if they are held off
+    pub fn remaining(&self) -> Option<Duration> {
+        let resume_at = self.resume_at.lock().expect("throttle lock poisoned");
+        resume_at
+            .and_then(|at| at.checked_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Block until requests may be sent again
+    pub fn wait(&self) {
+        if let Some(remaining) = self.remaining() {
+            thread::sleep(remaining);
+        }
+    }
+}
+
+/// Uploads smaller than this on average cost more in round trips than in transfer
+const SMALL_UPLOAD: u64 = 64 * 1024;
+
+/// Advice for syncs of so many small files that the number of requests limits them more than
+/// bandwidth, if this was one
+pub fn request_bound_hint(uploads: usize, upload_bytes: u64, in_flight: bool) -> Option<String> {
+    if uploads < 1000 || upload_bytes / uploads as u64 >= SMALL_UPLOAD {
+        return None;
+    }
+    let advice = if in_flight {
+        "a higher --in-flight"
+    } else {
+        "--in-flight 64 or a higher --concurrency"
+    };
+    Some(format!(
+        "Uploaded {uploads} files of {} on average, so the number of requests rather than \
+         bandwidth limits this sync. More requests in flight help, try {advice}",
+        format_bytes((upload_bytes / uploads as u64) as f64)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_for_the_longest_wait_asked_for() {
+        let throttle = Throttle::default();
+        assert_eq!(throttle.remaining(), None);
+        throttle.pause(Duration::from_secs(60));
+        throttle.pause(Duration::from_secs(1));
+        assert!(throttle.remaining().unwrap() > Duration::from_secs(50));
+        throttle.pause(Duration::from_secs(3600));
+        assert!(throttle.remaining().unwrap() <= MAX_RATE_LIMIT_WAIT);
+    }
+
+    #[test]
+    fn refills_up_to_burst() {
+        assert_eq!(refill(0.0, Duration::from_millis(500), 4.0, 10.0), 2.0);
+        assert_eq!(refill(9.5, Duration::from_secs(5), 4.0, 10.0), 10.0);
+    }
+
+    #[test]
+    fn allows_burst_without_waiting() {
+        let pacer = Pacer::new(5, 0.001);
+        let started = Instant::now();
+        for _ in 0..5 {
+            pacer.acquire();
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn hints_when_small_files_bound_the_sync() {
+        assert!(request_bound_hint(999, 1024, false).is_none());
+        assert!(request_bound_hint(5000, 5000 * SMALL_UPLOAD, false).is_none());
+        let hint = request_bound_hint(5000, 5000 * 2048, false).unwrap();
+        assert!(hint.contains("2.0 KiB on average"), "{hint}");
+        assert!(hint.contains("--in-flight 64"));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::api::StorageZoneClient;
+use crate::bunny_api::BunnyApiClient;
+use crate::error::ThumperError;
+use crate::pullzone::get_pull_zone;
+use crate::rm;
+use crate::zones::{get_zone, storage_client};
+use anyhow::Context;
+use crossbeam::channel::unbounded;
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Where bunny.net keeps Perma-Cache objects in the storage zone backing it
+const PERMA_CACHE_DIR: &str = "__bcdn_perma_cache__";
+
+/// The Perma-Cache of a pull zone, which a normal cache purge leaves alone
+pub struct PermaCache {
+    client: StorageZoneClient,
+    pullzone_name: String,
+}
+
+/// Directories holding the cached objects of the pull zone, bunny.net starts a new one for each
+/// cache version, named like `pullzone__<name>__<version>`
+fn cache_dirs<'a>(listed: impl IntoIterator<Item = &'a str>, pullzone_name: &str) -> Vec<String> {
+    let prefix = format!("pullzone__{pullzone_name}__");
+    listed
+        .into_iter()
+        .filter(|name| name.starts_with(prefix.as_str()))
+        .map(|name| format!("{PERMA_CACHE_DIR}/{name}/"))
+        .collect()
+}
+
+impl PermaCache {
+    /// Look up the storage zone backing the Perma-Cache of the pull zone
+    pub fn open(api: &BunnyApiClient, pullzone: u64) -> anyhow::Result<Self> {
+        let zone = get_pull_zone(api, pullzone)?;
+        let pullzone_name = zone
+            .get("Name")
+            .and_then(Value::as_str)
+            .with_context(|| format!("Pull zone {pullzone} has no name"))?
+            .to_string();
+        let storage_zone = zone
+            .get("PermaCacheStorageZoneId")
+            .and_then(Value::as_u64)
+            .filter(|id| *id != 0)
+            .with_context(|| format!("Pull zone {pullzone} does not use Perma-Cache"))?;
+        let client = storage_client(get_zone(api, &storage_zone.to_string())?)?
+            .with_key_origin("the Perma-Cache storage zone");
+        Ok(PermaCache {
+            client,
+            pullzone_name,
+        })
+    }
+
+    fn dirs(&self) -> anyhow::Result<Vec<String>> {
+        let listed = match self.client.ls_dir(&format!("{PERMA_CACHE_DIR}/")) {
+            Ok(listed) => listed,
+            // Nothing was cached yet
+            Err(ThumperError::NotFound(_)) => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(cache_dirs(
+            listed
+                .iter()
+                .filter(|fi| fi.is_directory)
+                .map(|fi| fi.object_name.as_str()),
+            &self.pullzone_name,
+        ))
+    }
+
+    /// Delete the cached copies of paths in the origin storage zone, returning how many there were
+    pub fn purge_paths(&self, paths: &[String], concurrency: usize) -> anyhow::Result<usize> {
+        let (send_work, receive_work) = unbounded();
+        for dir in self.dirs()? {
+            for path in paths {
+                send_work.send(format!("{dir}{}", path.trim_start_matches('/')))?;
+            }
+        }
+        drop(send_work);
+        let purged = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..concurrency.max(1))
+                .map(|_| {
+                    let receive_work = receive_work.clone();
+                    let purged = &purged;
+                    scope.spawn(move || {
+                        while let Ok(name) = receive_work.recv() {
+                            match self.client.delete_file(&name) {
+                                Ok(()) => {
+                                    purged.fetch_add(1, Ordering::Relaxed);
+                                }
+                                // Most paths were never cached, or not since the last version
+                                Err(ThumperError::NotFound(_)) => {}
+                                Err(err) => return Err(err),
+                            }
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            for worker in workers {
+                worker.join().expect("purge worker panicked")?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })?;
+        Ok(purged.into_inner())
+    }
+
+    /// Delete everything in the Perma-Cache of the pull zone
+    pub fn purge_all(&self, concurrency: usize) -> anyhow::Result<usize> {
+        let mut purged = 0;
+        for dir in self.dirs()? {
+            purged += rm::remove_tree(&self.client, &dir, concurrency)?;
+        }
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_cache_directories_of_the_pull_zone() {
+        let listed = [
+            "pullzone__docs__1042",
+            "pullzone__docs-staging__77",
+            "pullzone__docs__1043",
+        ];
+        assert_eq!(
+            cache_dirs(listed, "docs"),
+            vec![
+                "__bcdn_perma_cache__/pullzone__docs__1042/",
+                "__bcdn_perma_cache__/pullzone__docs__1043/",
+            ]
+        );
+    }
+}
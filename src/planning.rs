@@ -1,23 +1,68 @@
 use crate::api::FileMeta;
+use crate::history::THUMPER_DIR;
+use crate::lock::DEFAULT_LOCKFILE;
 use fxhash::{FxHashMap, FxHashSet};
+use globset::{Glob, GlobMatcher};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::path::PathBuf;
-use std::{fs, io};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// How much of a file content sniffing looks at
+const SNIFF_BYTES: usize = 8192;
+
+/// Whether an ignore or protect rule is a glob pattern rather than a plain prefix
+pub fn is_glob(rule: &str) -> bool {
+    rule.contains(['*', '?', '[', '{'])
+}
+
+/// Ignore and protect rules. Rules with `*`, `?`, `[` or `{` are glob patterns matched like
+/// upload tiers, the rest are plain prefixes of names in the storage zone.
+pub struct PathRules {
+    prefixes: Vec<String>,
+    globs: Vec<GlobMatcher>,
+}
+
+impl PathRules {
+    /// Rules that aren't valid glob patterns are treated as prefixes, preflight reports them
+    pub fn new(rules: &[String]) -> Self {
+        let mut prefixes = vec![];
+        let mut globs = vec![];
+        for rule in rules {
+            match glob(rule) {
+                Ok(pattern) if is_glob(rule) => globs.push(pattern),
+                _ => prefixes.push(rule.clone()),
+            }
+        }
+        PathRules { prefixes, globs }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.prefixes
+            .iter()
+            .any(|prefix| name.starts_with(prefix.as_str()))
+            || self.globs.iter().any(|pattern| matches_glob(pattern, name))
+    }
+}
 
 fn must_remove<'a>(
     local_files: &'a FxHashMap<String, PathBuf>,
     remote_files: &'a FxHashMap<String, FileMeta>,
-    ignored_prefix: &[String],
+    ignored: &[String],
 ) -> FxHashSet<&'a str> {
+    let ignored = PathRules::new(ignored);
     remote_files
         .keys()
         .filter(|p| !local_files.contains_key(p.as_str()))
-        .filter(|p| !ignored_prefix.iter().any(|prefix| p.starts_with(prefix)))
+        .filter(|p| !ignored.matches(p))
         .map(|s| s.as_str())
         .collect()
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// One task of a sync, serialized with an `action` of put, replace or delete
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
 pub enum SyncPlan {
     Put {
         local: PathBuf,
@@ -26,6 +71,7 @@ pub enum SyncPlan {
     Replace {
         local: PathBuf,
         remote: String,
+        #[serde(default, with = "hex_checksum")]
         remote_checksum: Option<[u8; 32]>,
     },
     Delete {
@@ -33,9 +79,8 @@ pub enum SyncPlan {
     },
 }
 
-#[cfg(test)]
 impl SyncPlan {
-    fn remote(&self) -> &str {
+    pub fn remote(&self) -> &str {
         match self {
             SyncPlan::Put { local: _, remote } => remote.as_str(),
             SyncPlan::Replace {
@@ -48,6 +93,31 @@ impl SyncPlan {
     }
 }
 
+/// Checksums as hex strings in serialized plans
+mod hex_checksum {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        checksum: &Option<[u8; 32]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        checksum.map(hex::encode).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<[u8; 32]>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|checksum| {
+                let mut digest = [0; 32];
+                hex::decode_to_slice(&checksum, &mut digest).map_err(D::Error::custom)?;
+                Ok(digest)
+            })
+            .transpose()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum SyncAction {
     Put {
@@ -99,14 +169,313 @@ pub fn plan_sync<'a>(
     job
 }
 
-pub fn plan_execution<'a, F>(plan: &'a SyncPlan, read: F) -> anyhow::Result<Execution<'a>>
+/// Drop local files that match an ignore rule, so they are never uploaded
+pub fn without_ignored(
+    mut local: FxHashMap<String, PathBuf>,
+    ignore: &[String],
+) -> FxHashMap<String, PathBuf> {
+    let ignore = PathRules::new(ignore);
+    local.retain(|name, _| !ignore.matches(name));
+    local
+}
+
+/// Which remote files a sync may touch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpdateMode {
+    /// Upload, replace and delete until the remote matches
+    #[default]
+    All,
+    /// Only upload files the remote lacks, never replace or delete anything
+    SkipExisting,
+    /// Only replace files the remote already has, never create or delete anything
+    ExistingOnly,
+}
+
+pub fn restrict_plan(mut job: Vec<SyncPlan>, mode: UpdateMode) -> Vec<SyncPlan> {
+    match mode {
+        UpdateMode::All => {}
+        UpdateMode::SkipExisting => job.retain(|plan| matches!(plan, SyncPlan::Put { .. })),
+        UpdateMode::ExistingOnly => job.retain(|plan| matches!(plan, SyncPlan::Replace { .. })),
+    }
+    job
+}
+
+/// Never delete remote placeholder files named `marker`, which keep directories around
+pub fn preserve_placeholders(mut job: Vec<SyncPlan>, marker: &str) -> Vec<SyncPlan> {
+    job.retain(|plan| match plan {
+        SyncPlan::Delete { remote } => remote.rsplit('/').next() != Some(marker),
+        _ => true,
+    });
+    job
+}
+
+/// Objects thumper and other tools keep in the storage zone for themselves, which a sync never
+/// deletes whatever its ignore rules say
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemObjects {
+    /// Name of the lockfile, in any directory since other syncs lock their own subtrees
+    pub lockfile: String,
+    /// Prefixes or glob patterns, .thumper/ and any given with --system-prefix
+    pub prefixes: Vec<String>,
+}
+
+impl Default for SystemObjects {
+    fn default() -> Self {
+        SystemObjects::new(DEFAULT_LOCKFILE, &[])
+    }
+}
+
+impl SystemObjects {
+    /// The lockfile named `lockfile` and .thumper/, along with the `extra` prefixes
+    pub fn new(lockfile: &str, extra: &[String]) -> Self {
+        SystemObjects {
+            lockfile: lockfile.to_string(),
+            prefixes: [THUMPER_DIR.to_string()]
+                .into_iter()
+                .chain(extra.iter().cloned())
+                .collect(),
+        }
+    }
+
+    /// Tells whether a remote name is one of these objects
+    pub fn matcher(&self) -> impl Fn(&str) -> bool + '_ {
+        let rules = PathRules::new(&self.prefixes);
+        move |name| name.rsplit('/').next() == Some(self.lockfile.as_str()) || rules.matches(name)
+    }
+}
+
+/// Never delete system objects, even when no ignore or protect rule covers them
+pub fn preserve_system_objects(mut job: Vec<SyncPlan>, system: &SystemObjects) -> Vec<SyncPlan> {
+    let is_system = system.matcher();
+    job.retain(|plan| match plan {
+        SyncPlan::Delete { remote } => !is_system(remote),
+        _ => true,
+    });
+    job
+}
+
+/// What a sync leaves alone, for [`plan`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanOptions {
+    /// Ignore and protect rules, remote files matching them are never deleted
+    #[serde(default)]
+    pub protected: Vec<String>,
+    #[serde(default)]
+    pub mode: UpdateMode,
+    /// Name of placeholder files that keep remote directories around
+    #[serde(default)]
+    pub keep_marker: Option<String>,
+    #[serde(default)]
+    pub system: SystemObjects,
+}
+
+/// The tasks that make a storage zone match the local files, which can be stored, inspected and
+/// carried out later
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub tasks: Vec<SyncPlan>,
+}
+
+impl Plan {
+    /// How many tasks upload or replace a file, and how many delete one
+    pub fn counts(&self) -> (usize, usize) {
+        let deletes = self
+            .tasks
+            .iter()
+            .filter(|task| matches!(task, SyncPlan::Delete { .. }))
+            .count();
+        (self.tasks.len() - deletes, deletes)
+    }
+}
+
+/// Plan a sync of `local`, files by remote name, onto the `remote` listing. Only looks at what
+/// it is given, reading neither the local files nor the storage zone, so the same state always
+/// gives the same plan.
+pub fn plan(
+    local: &FxHashMap<String, PathBuf>,
+    remote: &FxHashMap<String, FileMeta>,
+    options: &PlanOptions,
+) -> Plan {
+    let tasks = restrict_plan(plan_sync(local, remote, &options.protected), options.mode);
+    let tasks = match &options.keep_marker {
+        Some(marker) => preserve_placeholders(tasks, marker),
+        None => tasks,
+    };
+    Plan {
+        tasks: preserve_system_objects(tasks, &options.system),
+    }
+}
+
+/// Extensions of the pages uploaded after other files unless configured otherwise
+pub const DEFAULT_PAGES: [&str; 2] = [".html", ".htm"];
+
+/// Whether `remote` is a page by its extension, where "" matches files without one
+pub fn is_page<S: AsRef<str>>(remote: &str, extensions: &[S]) -> bool {
+    let extension = Path::new(remote)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    extensions.iter().any(|page| {
+        page.as_ref()
+            .trim_start_matches('.')
+            .eq_ignore_ascii_case(extension)
+    })
+}
+
+/// Uploaded after the pages by default: service workers and manifests that may reference both
+/// pages and assets
+const WORKER_TIER: [&str; 4] = [
+    "sw.js",
+    "service-worker.js",
+    "*.webmanifest",
+    "manifest.json",
+];
+
+/// Compile a glob pattern, which may use `**` for any number of directories and `{a,b}` for
+/// alternatives
+pub fn glob(pattern: &str) -> Result<GlobMatcher, globset::Error> {
+    Ok(Glob::new(pattern)?.compile_matcher())
+}
+
+/// Patterns without a `/` match the file name, others the whole path
+pub fn matches_glob(pattern: &GlobMatcher, path: &str) -> bool {
+    if pattern.glob().glob().contains('/') {
+        pattern.is_match(path)
+    } else {
+        pattern.is_match(path.rsplit('/').next().unwrap_or(path))
+    }
+}
+
+/// What puts a file in an upload tier
+#[derive(Debug)]
+enum TierRule {
+    Glob(GlobMatcher),
+    /// Files without an extension, like pages published under clean URLs
+    NoExtension,
+}
+
+impl TierRule {
+    fn matches(&self, remote: &str) -> bool {
+        match self {
+            TierRule::Glob(pattern) => matches_glob(pattern, remote),
+            TierRule::NoExtension => Path::new(remote).extension().is_none(),
+        }
+    }
+}
+
+/// Ordered tiers of glob patterns. Files matching no tier are uploaded first, then each tier
+/// once the one before it is done. Patterns without a `/` match the file name, others the
+/// whole path in the storage zone.
+#[derive(Debug)]
+pub struct UploadTiers {
+    tiers: Vec<Vec<TierRule>>,
+}
+
+impl UploadTiers {
+    pub fn new<S: AsRef<str>>(tiers: &[impl AsRef<[S]>]) -> anyhow::Result<Self> {
+        let tiers = tiers
+            .iter()
+            .map(|tier| {
+                tier.as_ref()
+                    .iter()
+                    .map(|pattern| glob(pattern.as_ref()).map(TierRule::Glob))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(UploadTiers { tiers })
+    }
+
+    /// The default tiers, with pages told apart by these extensions instead of .html and .htm
+    pub fn with_pages<S: AsRef<str>>(extensions: &[S]) -> anyhow::Result<Self> {
+        let pages = extensions
+            .iter()
+            .map(
+                |extension| match extension.as_ref().trim_start_matches('.') {
+                    "" => Ok(TierRule::NoExtension),
+                    extension => {
+                        let pattern = format!("*.{}", globset::escape(extension));
+                        Ok(TierRule::Glob(glob(&pattern)?))
+                    }
+                },
+            )
+            .collect::<anyhow::Result<_>>()?;
+        let mut tiers = UploadTiers::new(&[WORKER_TIER])?;
+        tiers.tiers.insert(0, pages);
+        Ok(tiers)
+    }
+
+    /// 0 for files in no tier, otherwise the first matching tier counting from 1
+    fn tier_of(&self, remote: &str) -> usize {
+        self.tiers
+            .iter()
+            .position(|tier| tier.iter().any(|rule| rule.matches(remote)))
+            .map_or(0, |tier| tier + 1)
+    }
+}
+
+impl Default for UploadTiers {
+    fn default() -> Self {
+        UploadTiers::with_pages(&DEFAULT_PAGES).expect("default tiers are valid globs")
+    }
+}
+
+/// Combine plans for several scopes into stages to run one after the other: files in no tier,
+/// then each upload tier, and deletes last
+pub fn merge_plans(plans: Vec<Vec<SyncPlan>>, tiers: &UploadTiers) -> Vec<Vec<SyncPlan>> {
+    let deletes = tiers.tiers.len() + 1;
+    let mut stages = vec![vec![]; deletes + 1];
+    for plan in plans.into_iter().flatten() {
+        let stage = match plan {
+            SyncPlan::Delete { .. } => deletes,
+            _ => tiers.tier_of(plan.remote()),
+        };
+        stages[stage].push(plan);
+    }
+    stages.retain(|stage| !stage.is_empty());
+    stages
+}
+
+/// Sniff the content type from the content, falling back to guessing from the extension
+pub fn detect_mime_type(local: &Path, content: &[u8]) -> Option<&'static str> {
+    infer::get(content)
+        .map(|t| t.mime_type())
+        .or_else(|| mime_guess::from_path(local).first_raw())
+}
+
+pub fn sha256(_local: &Path, content: &[u8]) -> [u8; 32] {
+    Sha256::digest(content).into()
+}
+
+/// Like [`sha256`], reading the file a chunk at a time instead of all at once
+pub fn sha256_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+/// Like [`detect_mime_type`], sniffing only the start of the file at `path`
+pub fn detect_file_mime_type(local: &Path, path: &Path) -> io::Result<Option<&'static str>> {
+    let mut start = Vec::with_capacity(SNIFF_BYTES);
+    File::open(path)?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut start)?;
+    Ok(detect_mime_type(local, &start))
+}
+
+pub fn plan_execution<'a, F, C>(
+    plan: &'a SyncPlan,
+    read: F,
+    checksum: C,
+) -> anyhow::Result<Execution<'a>>
 where
     F: Fn(&'a PathBuf) -> io::Result<Vec<u8>>,
+    C: Fn(&Path, &[u8]) -> [u8; 32],
 {
     match plan {
         SyncPlan::Put { local, remote } => {
-            let content = fs::read(local)?;
-            let mime_type = infer::get_from_path(local)?.map(|t| t.mime_type());
+            let content = read(local)?;
+            let mime_type = detect_mime_type(local, &content);
             Ok(Execution {
                 remote,
                 action: SyncAction::Put { content, mime_type },
@@ -118,8 +487,8 @@ where
             remote_checksum,
         } => {
             let content = read(local)?;
-            let mime_type = infer::get_from_path(local)?.map(|t| t.mime_type());
-            let digest: [u8; 32] = Sha256::digest(&content).into();
+            let mime_type = detect_mime_type(local, &content);
+            let digest = checksum(local, &content);
             if &Some(digest) != remote_checksum {
                 Ok(Execution {
                     remote,
@@ -141,7 +510,12 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{Execution, SyncAction, SyncPlan, plan_execution, plan_sync};
+    use super::{
+        DEFAULT_PAGES, Execution, PathRules, Plan, PlanOptions, SNIFF_BYTES, SyncAction, SyncPlan,
+        SystemObjects, UpdateMode, UploadTiers, detect_file_mime_type, is_page, merge_plans, plan,
+        plan_execution, plan_sync, preserve_placeholders, restrict_plan, sha256, sha256_file,
+        without_ignored,
+    };
     use crate::api::FileMeta;
     use fxhash::FxHashMap;
     use sha2::{Digest, Sha256};
@@ -159,12 +533,12 @@ mod tests {
             remote_checksum: Some(remote_checksum),
         };
         let Execution { remote: _, action } =
-            plan_execution(&plan, |_| Ok(local_content.as_bytes().to_vec())).unwrap();
+            plan_execution(&plan, |_| Ok(local_content.as_bytes().to_vec()), sha256).unwrap();
         assert_eq!(
             action,
             SyncAction::Put {
                 content: local_content.as_bytes().to_vec(),
-                mime_type: None
+                mime_type: Some("text/markdown")
             }
         );
     }
@@ -181,7 +555,7 @@ mod tests {
             remote_checksum: Some(remote_checksum),
         };
         let Execution { remote: _, action } =
-            plan_execution(&plan, |_| Ok(local_content.as_bytes().to_vec())).unwrap();
+            plan_execution(&plan, |_| Ok(local_content.as_bytes().to_vec()), sha256).unwrap();
         assert_eq!(action, SyncAction::Ignore);
     }
 
@@ -268,6 +642,281 @@ mod tests {
         assert!(job[3].remote() == "a.html" || job[3].remote() == "b.htm");
     }
 
+    #[test]
+    fn skip_existing_only_uploads_missing_files() {
+        let mut local = FxHashMap::default();
+        local.insert("new.html".to_string(), PathBuf::from("new.html"));
+        local.insert("both.html".to_string(), PathBuf::from("both.html"));
+        let mut remote = FxHashMap::default();
+        remote.insert("both.html".to_string(), FileMeta { checksum: None });
+        remote.insert("gone.html".to_string(), FileMeta { checksum: None });
+
+        let job = restrict_plan(plan_sync(&local, &remote, &[]), UpdateMode::SkipExisting);
+        assert_eq!(
+            job,
+            vec![SyncPlan::Put {
+                local: PathBuf::from("new.html"),
+                remote: "new.html".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn existing_only_replaces_present_files() {
+        let mut local = FxHashMap::default();
+        local.insert("new.html".to_string(), PathBuf::from("new.html"));
+        local.insert("both.html".to_string(), PathBuf::from("both.html"));
+        let mut remote = FxHashMap::default();
+        remote.insert("both.html".to_string(), FileMeta { checksum: None });
+        remote.insert("gone.html".to_string(), FileMeta { checksum: None });
+
+        let job = restrict_plan(plan_sync(&local, &remote, &[]), UpdateMode::ExistingOnly);
+        assert_eq!(
+            job,
+            vec![SyncPlan::Replace {
+                local: PathBuf::from("both.html"),
+                remote: "both.html".to_string(),
+                remote_checksum: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn protects_glob_patterns_and_prefixes() {
+        let mut remote = FxHashMap::default();
+        for name in [
+            "uploads/2024/cat.jpg",
+            "report.pdf",
+            "docs/manual.pdf",
+            "docs/old.html",
+            "assets/app.js",
+        ] {
+            remote.insert(name.to_string(), FileMeta { checksum: None });
+        }
+        let protected = vec![
+            "uploads/**".to_string(),
+            "*.pdf".to_string(),
+            "assets/".to_string(),
+        ];
+        let job = plan_sync(&FxHashMap::default(), &remote, &protected);
+        assert_eq!(
+            job,
+            vec![SyncPlan::Delete {
+                remote: "docs/old.html".to_string()
+            }]
+        );
+        let rules = PathRules::new(&["**/*.pdf".to_string()]);
+        assert!(rules.matches("report.pdf") && rules.matches("docs/manual.pdf"));
+        assert!(!rules.matches("docs/old.html"));
+    }
+
+    #[test]
+    fn matches_globset_patterns() {
+        let rules = PathRules::new(&["**/drafts/**".to_string(), "*.{pdf,zip}".to_string()]);
+        assert!(rules.matches("drafts/post.html"));
+        assert!(rules.matches("blog/drafts/2024/post.html"));
+        assert!(!rules.matches("blog/published/post.html"));
+        assert!(rules.matches("files/report.pdf") && rules.matches("archive.zip"));
+        assert!(!rules.matches("report.pdf.html"));
+        let rules = PathRules::new(&["uploads/**".to_string()]);
+        assert!(rules.matches("uploads/cat.jpg") && rules.matches("uploads/2024/cat.jpg"));
+        assert!(!rules.matches("blog/uploads/cat.jpg"));
+    }
+
+    #[test]
+    fn prunes_directories_matching_prefixes_and_globs() {
+        let rules = PathRules::new(&["**/node_modules/**".to_string(), "drafts/".to_string()]);
+        let descend =
+            |subdirectory| crate::listing::descend(subdirectory, |name| rules.matches(name));
+        assert!(!descend("web/node_modules/"));
+        assert!(!descend("/node_modules/"));
+        assert!(!descend("/drafts/"));
+        assert!(descend("web/src/"));
+        assert!(descend("/docs/"));
+    }
+
+    #[test]
+    fn ignore_skips_uploads_but_protect_does_not() {
+        let mut local = FxHashMap::default();
+        local.insert(
+            "drafts/post.html".to_string(),
+            PathBuf::from("drafts/post.html"),
+        );
+        local.insert(
+            "uploads/cat.jpg".to_string(),
+            PathBuf::from("uploads/cat.jpg"),
+        );
+        let mut remote = FxHashMap::default();
+        remote.insert("drafts/old.html".to_string(), FileMeta { checksum: None });
+        remote.insert("uploads/dog.jpg".to_string(), FileMeta { checksum: None });
+
+        let ignore = vec!["drafts/".to_string()];
+        let protected = vec!["drafts/".to_string(), "uploads/".to_string()];
+        let job = plan_sync(&without_ignored(local, &ignore), &remote, &protected);
+        assert_eq!(
+            job,
+            vec![SyncPlan::Put {
+                local: PathBuf::from("uploads/cat.jpg"),
+                remote: "uploads/cat.jpg".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn preserves_remote_placeholders() {
+        let local = FxHashMap::default();
+        let mut remote = FxHashMap::default();
+        remote.insert("uploads/.keep".to_string(), FileMeta { checksum: None });
+        remote.insert("uploads/old.keep".to_string(), FileMeta { checksum: None });
+
+        let job = preserve_placeholders(plan_sync(&local, &remote, &[]), ".keep");
+        assert_eq!(
+            job,
+            vec![SyncPlan::Delete {
+                remote: "uploads/old.keep".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn plans_are_pure_and_serializable() {
+        let mut local = FxHashMap::default();
+        local.insert("index.html".to_string(), PathBuf::from("site/index.html"));
+        let mut remote = FxHashMap::default();
+        remote.insert(
+            "index.html".to_string(),
+            FileMeta {
+                checksum: Some([0xab; 32]),
+            },
+        );
+        for name in ["old.html", "uploads/.keep", "media/cat.jpg"] {
+            remote.insert(name.to_string(), FileMeta { checksum: None });
+        }
+        let options = PlanOptions {
+            protected: vec!["media/".to_string()],
+            mode: UpdateMode::All,
+            keep_marker: Some(".keep".to_string()),
+            system: SystemObjects::default(),
+        };
+        let planned = plan(&local, &remote, &options);
+        assert_eq!(planned, plan(&local, &remote, &options));
+        assert_eq!(planned.counts(), (1, 1));
+
+        let json = serde_json::to_string(&planned).unwrap();
+        assert!(json.contains(r#""action":"replace""#));
+        assert!(json.contains(&"ab".repeat(32)));
+        assert_eq!(serde_json::from_str::<Plan>(&json).unwrap(), planned);
+        let options: PlanOptions = serde_json::from_str(r#"{"mode": "skip-existing"}"#).unwrap();
+        assert!(plan(&local, &remote, &options).tasks.is_empty());
+    }
+
+    #[test]
+    fn keeps_thumper_dir_without_ignore_rules() {
+        let mut local = FxHashMap::default();
+        local.insert("index.html".to_string(), PathBuf::from("public/index.html"));
+        let mut remote = FxHashMap::default();
+        for name in [
+            "index.html",
+            ".thumper/history.jsonl",
+            ".thumper/manifest.sig",
+        ] {
+            remote.insert(name.to_string(), FileMeta { checksum: None });
+        }
+        let options = PlanOptions {
+            protected: vec!["drafts/".to_string()],
+            ..PlanOptions::default()
+        };
+        assert!(
+            plan(&local, &remote, &options)
+                .tasks
+                .iter()
+                .all(|task| !matches!(task, SyncPlan::Delete { .. }))
+        );
+    }
+
+    #[test]
+    fn never_deletes_system_objects() {
+        let mut remote = FxHashMap::default();
+        for name in [
+            ".thumper.lock",
+            "docs/.thumper.lock",
+            ".thumper/history.jsonl",
+            ".well-known/security.txt",
+            "old.html",
+        ] {
+            remote.insert(name.to_string(), FileMeta { checksum: None });
+        }
+        let options = PlanOptions {
+            system: SystemObjects::new(".thumper.lock", &[".well-known/".to_string()]),
+            ..PlanOptions::default()
+        };
+        assert_eq!(
+            plan(&FxHashMap::default(), &remote, &options).tasks,
+            vec![SyncPlan::Delete {
+                remote: "old.html".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn merges_plans_into_tiers_with_deletes_last() {
+        let put = |remote: &str| SyncPlan::Put {
+            local: PathBuf::from(remote),
+            remote: remote.to_string(),
+        };
+        let stages = merge_plans(
+            vec![
+                vec![
+                    put("docs/style.css"),
+                    put("docs/index.html"),
+                    SyncPlan::Delete {
+                        remote: "docs/old.html".to_string(),
+                    },
+                ],
+                vec![
+                    put("app/sw.js"),
+                    put("blog/post.css"),
+                    put("manifest.webmanifest"),
+                    put("blog/index.html"),
+                ],
+            ],
+            &UploadTiers::default(),
+        );
+        let remotes: Vec<Vec<_>> = stages
+            .iter()
+            .map(|stage| stage.iter().map(|plan| plan.remote()).collect())
+            .collect();
+        assert_eq!(
+            remotes,
+            vec![
+                vec!["docs/style.css", "blog/post.css"],
+                vec!["docs/index.html", "blog/index.html"],
+                vec!["app/sw.js", "manifest.webmanifest"],
+                vec!["docs/old.html"],
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_tier_patterns_by_name_or_path() {
+        let tiers = UploadTiers::new(&[vec!["sw.js"], vec!["static/*.json"]]).unwrap();
+        assert_eq!(tiers.tier_of("app/sw.js"), 1);
+        assert_eq!(tiers.tier_of("static/data.json"), 2);
+        assert_eq!(tiers.tier_of("other/data.json"), 0);
+        assert!(UploadTiers::new(&[vec!["[oops"]]).is_err());
+
+        assert!(is_page("docs/index.HTML", &DEFAULT_PAGES));
+        assert!(!is_page("docs/app.3f9a.js", &DEFAULT_PAGES));
+        assert!(is_page("blog/hello-world", &[".html", ""]));
+
+        let tiers = UploadTiers::with_pages(&[".html", "xml", ""]).unwrap();
+        assert_eq!(tiers.tier_of("docs/index.html"), 1);
+        assert_eq!(tiers.tier_of("sitemap.xml"), 1);
+        assert_eq!(tiers.tier_of("blog/hello-world"), 1);
+        assert_eq!(tiers.tier_of("docs/page.htm"), 0);
+        assert_eq!(tiers.tier_of("sw.js"), 2);
+    }
+
     #[test]
     fn replaces_when_remote_checksum_is_none() {
         let local_content = "content";
@@ -277,16 +926,33 @@ mod tests {
             remote: "remote".to_string(),
             remote_checksum: None,
         };
-        let execution = plan_execution(&plan, |_| Ok(local_content.as_bytes().to_vec())).unwrap();
+        let execution =
+            plan_execution(&plan, |_| Ok(local_content.as_bytes().to_vec()), sha256).unwrap();
         assert_eq!(
             execution.action,
             SyncAction::Put {
                 content: local_content.as_bytes().to_vec(),
-                mime_type: None
+                mime_type: Some("text/markdown")
             }
         );
     }
 
+    #[test]
+    fn leaves_mime_type_undetected_without_extension_or_magic() {
+        let plan = SyncPlan::Put {
+            local: PathBuf::new().join("Dockerfile"),
+            remote: "Dockerfile".to_string(),
+        };
+        let execution = plan_execution(&plan, |_| Ok(vec![]), sha256).unwrap();
+        assert!(matches!(
+            execution.action,
+            SyncAction::Put {
+                mime_type: None,
+                ..
+            }
+        ));
+    }
+
     #[test]
     fn test_must_remove() {
         let mut local = FxHashMap::default();
@@ -303,4 +969,18 @@ mod tests {
         assert_eq!(to_remove.len(), 1);
         assert!(to_remove.contains("file3.txt"));
     }
+
+    #[test]
+    fn hashes_and_sniffs_files_in_chunks() {
+        let path = std::env::temp_dir().join(format!("thumper-chunks-{}.png", std::process::id()));
+        let mut content = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        content.resize(3 * SNIFF_BYTES, 7);
+        std::fs::write(&path, &content).unwrap();
+        assert_eq!(sha256_file(&path).unwrap(), sha256(&path, &content));
+        assert_eq!(
+            detect_file_mime_type(std::path::Path::new("logo.bin"), &path).unwrap(),
+            Some("image/png")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -0,0 +1,181 @@
+use crate::api::{ReadOnly, StorageZoneClient};
+use crate::error::ThumperError;
+use crate::planning::{glob, is_glob};
+use anyhow::anyhow;
+
+/// Results of the checks that run before a sync takes the lock
+#[derive(Default)]
+pub struct Checklist {
+    checks: Vec<(String, Result<(), String>)>,
+}
+
+impl Checklist {
+    pub fn check(&mut self, name: impl Into<String>, result: Result<(), String>) {
+        self.checks.push((name.into(), result));
+    }
+
+    fn passed(&self) -> bool {
+        self.checks.iter().all(|(_, result)| result.is_ok())
+    }
+
+    fn render(&self) -> String {
+        self.checks
+            .iter()
+            .map(|(name, result)| match result {
+                Ok(()) => format!("  [ok]   {name}"),
+                Err(problem) => format!("  [FAIL] {name}: {problem}"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Fail with the whole checklist if any check failed
+    pub fn finish(self) -> anyhow::Result<()> {
+        if self.passed() {
+            Ok(())
+        } else {
+            Err(anyhow!("Preflight checks failed:\n{}", self.render()))
+        }
+    }
+}
+
+pub fn check_settings(
+    list: &mut Checklist,
+    concurrency: usize,
+    ignore: &[String],
+    protect: &[String],
+    paths: &[&str],
+) {
+    list.check(
+        "concurrency is at least 1",
+        if concurrency == 0 {
+            Err("--concurrency 0 would never sync anything".to_string())
+        } else {
+            Ok(())
+        },
+    );
+    let prefixes = ignore
+        .iter()
+        .map(|prefix| ("ignore", prefix))
+        .chain(protect.iter().map(|prefix| ("protect", prefix)));
+    for (kind, prefix) in prefixes {
+        let result = if prefix.starts_with('/') {
+            Err(format!(
+                "{kind} prefixes are relative to the zone root, drop the leading /"
+            ))
+        } else if is_glob(prefix) {
+            glob(prefix)
+                .map(|_| ())
+                .map_err(|err| format!("invalid glob pattern: {err}"))
+        } else if kind == "ignore"
+            // Protecting all of a path only stops deletes, which is how upload-only syncs work
+            && let Some(path) = paths
+                .iter()
+                .find(|path| path.trim_start_matches('/').starts_with(prefix.as_str()))
+        {
+            Err(format!("it covers all of --path {path}"))
+        } else {
+            Ok(())
+        };
+        list.check(format!("--{kind} {prefix} is coherent"), result);
+    }
+}
+
+pub fn check_remote(
+    list: &mut Checklist,
+    client: &StorageZoneClient<ReadOnly>,
+    storage_zone: &str,
+    paths: &[&str],
+) {
+    let zone = match client.ls_dir("") {
+        Ok(_) => Ok(()),
+        Err(ThumperError::NotFound(_)) => Err(format!(
+            "storage zone {storage_zone} does not exist on this endpoint"
+        )),
+        Err(err) => Err(format!("{err:#}")),
+    };
+    let reachable = zone.is_ok();
+    list.check(
+        format!("credentials for storage zone {storage_zone} work"),
+        zone,
+    );
+    if !reachable {
+        return;
+    }
+    for path in paths {
+        let result = match client.ls_dir(path) {
+            // Syncing to a new directory creates it
+            Ok(_) => Ok(()),
+            Err(ThumperError::NotFound(_)) => Ok(()),
+            Err(err) => Err(format!("{err:#}")),
+        };
+        list.check(format!("remote path {path} is listable"), result);
+    }
+}
+
+pub fn check_local(list: &mut Checklist, local_path: &str, files: anyhow::Result<usize>) {
+    let result = match files {
+        Ok(0) => Err("there are no files to sync".to_string()),
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("{err:#}")),
+    };
+    list.check(
+        format!("local path {local_path} is readable and not empty"),
+        result,
+    );
+}
+
+/// Guard against syncing far more than expected, like a build that emitted node_modules
+pub fn check_file_count(list: &mut Checklist, files: usize, max_files: usize) {
+    let result = if files > max_files {
+        Err(format!("found {files} files to sync"))
+    } else {
+        Ok(())
+    };
+    list.check(format!("at most {max_files} local files"), result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_every_failed_check() {
+        let mut list = Checklist::default();
+        check_settings(
+            &mut list,
+            0,
+            &[
+                "/assets".to_string(),
+                "docs/".to_string(),
+                "tmp/".to_string(),
+            ],
+            &["/uploads".to_string(), "uploads/[".to_string()],
+            &["docs/api/"],
+        );
+        check_local(&mut list, "site/", Ok(0));
+        check_file_count(&mut list, 12000, 5000);
+        let err = list.finish().unwrap_err().to_string();
+        assert!(err.contains("[FAIL] concurrency is at least 1"));
+        assert!(err.contains("[FAIL] --ignore /assets is coherent: ignore prefixes are relative"));
+        assert!(
+            err.contains("[FAIL] --ignore docs/ is coherent: it covers all of --path docs/api/")
+        );
+        assert!(err.contains("[ok]   --ignore tmp/ is coherent"));
+        assert!(
+            err.contains("[FAIL] --protect /uploads is coherent: protect prefixes are relative")
+        );
+        assert!(err.contains("[FAIL] --protect uploads/[ is coherent: invalid glob pattern"));
+        assert!(err.contains("[FAIL] local path site/ is readable and not empty"));
+        assert!(err.contains("[FAIL] at most 5000 local files: found 12000 files to sync"));
+    }
+
+    #[test]
+    fn passes_when_all_checks_pass() {
+        let mut list = Checklist::default();
+        check_settings(&mut list, 4, &["tmp/".to_string()], &[], &["/"]);
+        check_settings(&mut list, 4, &[], &["docs/".to_string()], &["docs/"]);
+        check_local(&mut list, "site/", Ok(12));
+        assert!(list.finish().is_ok());
+    }
+}
@@ -0,0 +1,60 @@
+use crate::api::StorageZoneClient;
+use crate::rm::remove_tree;
+use anyhow::anyhow;
+
+/// Where previews live in the storage zone, each below its own ID
+pub const PREVIEWS: &str = "previews/";
+
+/// Preview IDs become a single path segment, like a PR number or a branch slug
+pub fn check_id(id: &str) -> anyhow::Result<()> {
+    let valid = !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid preview ID {id}, use letters, digits, -, _ and ."
+        ))
+    }
+}
+
+/// Where `path` ends up within the preview namespace of `id`
+pub fn preview_path(id: &str, path: &str) -> String {
+    format!("{PREVIEWS}{id}/{}", path.trim_start_matches('/'))
+}
+
+pub fn url(base_url: &str, id: &str) -> String {
+    format!("{}/{PREVIEWS}{id}/", base_url.trim_end_matches('/'))
+}
+
+/// Delete the namespace of the preview `id`
+pub fn remove(client: &StorageZoneClient, id: &str, concurrency: usize) -> anyhow::Result<()> {
+    check_id(id)?;
+    let path = preview_path(id, "");
+    let deleted = remove_tree(client, &path, concurrency)?;
+    println!("Deleted preview {id} with {deleted} files");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces_previews() {
+        assert!(check_id("pr-123").is_ok());
+        assert!(check_id("feature_x.2").is_ok());
+        for bad in ["", "..", "a/b", "pr 1"] {
+            assert!(check_id(bad).is_err(), "{bad}");
+        }
+        assert_eq!(preview_path("42", "/"), "previews/42/");
+        assert_eq!(preview_path("42", "/docs/"), "previews/42/docs/");
+        assert_eq!(
+            url("https://example.com/", "42"),
+            "https://example.com/previews/42/"
+        );
+    }
+}
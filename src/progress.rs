@@ -0,0 +1,114 @@
+use crate::bench::format_bytes;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const TEMPLATE: &str =
+    "{spinner} [{elapsed_precise}] {bar:30} {pos}/{len} tasks, {msg} (ETA {eta})";
+
+#[derive(Default)]
+struct Counts {
+    uploaded: u64,
+    in_flight: usize,
+    /// The request most recently sent
+    current: Option<String>,
+}
+
+/// Progress of a sync on stderr: tasks done out of all of them, bytes uploaded, requests in
+/// flight and how long the rest will take. Draws nothing when stderr isn't a terminal.
+pub struct SyncProgress {
+    bar: ProgressBar,
+    counts: Mutex<Counts>,
+}
+
+/// What the progress bar says besides the number of tasks
+fn describe(counts: &Counts) -> String {
+    let mut message = format!(
+        "{} uploaded, {} in flight",
+        format_bytes(counts.uploaded as f64),
+        counts.in_flight
+    );
+    if let (Some(current), true) = (&counts.current, counts.in_flight > 0) {
+        message.push_str(&format!(": {current}"));
+    }
+    message
+}
+
+impl SyncProgress {
+    /// Progress of `tasks` tasks, hidden unless `enabled`
+    pub fn new(tasks: usize, enabled: bool) -> Self {
+        let target = if enabled {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+        // Clear the bar even when a failing sync drops it without finishing
+        let bar = ProgressBar::with_draw_target(Some(tasks as u64), target)
+            .with_finish(ProgressFinish::AndClear);
+        if let Ok(style) = ProgressStyle::with_template(TEMPLATE) {
+            bar.set_style(style);
+        }
+        bar.enable_steady_tick(Duration::from_millis(200));
+        SyncProgress {
+            bar,
+            counts: Mutex::default(),
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Counts)) {
+        let mut counts = self.counts.lock().expect("progress lock poisoned");
+        change(&mut counts);
+        self.bar.set_message(describe(&counts));
+    }
+
+    /// A request for `remote` is on its way
+    pub fn sending(&self, remote: &str) {
+        self.update(|counts| {
+            counts.in_flight += 1;
+            counts.current = Some(remote.to_string());
+        });
+    }
+
+    /// A request is done, after uploading `bytes`
+    pub fn sent(&self, bytes: u64) {
+        self.update(|counts| {
+            counts.in_flight -= 1;
+            counts.uploaded += bytes;
+        });
+    }
+
+    /// A task is done, print `line` on stdout without garbling the bar if there is one
+    pub fn task_done(&self, line: Option<String>) {
+        if let Some(line) = line {
+            self.bar.suspend(|| println!("{line}"));
+        }
+        self.bar.inc(1);
+    }
+
+    /// Remove the bar before the summary is printed
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_bytes_and_requests_in_flight() {
+        let progress = SyncProgress::new(3, false);
+        progress.sending("docs/index.html");
+        assert_eq!(
+            describe(&progress.counts.lock().unwrap()),
+            "0.0 B uploaded, 1 in flight: docs/index.html"
+        );
+        progress.sent(2048);
+        progress.task_done(None);
+        assert_eq!(
+            describe(&progress.counts.lock().unwrap()),
+            "2.0 KiB uploaded, 0 in flight"
+        );
+        assert_eq!(progress.bar.position(), 1);
+    }
+}
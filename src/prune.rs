@@ -0,0 +1,62 @@
+use crate::api::StorageZoneClient;
+use crate::rm::delete_streamed;
+use chrono::{NaiveDateTime, Utc};
+use std::time::Duration;
+
+/// Whether a LastChanged timestamp, in UTC without an offset, is before `cutoff`
+fn changed_before(last_changed: &str, cutoff: NaiveDateTime) -> bool {
+    NaiveDateTime::parse_from_str(last_changed, "%Y-%m-%dT%H:%M:%S%.f")
+        .is_ok_and(|changed| changed < cutoff)
+}
+
+/// Delete every file below `prefix` last changed longer than `older_than` ago
+pub fn prune(
+    client: &StorageZoneClient,
+    prefix: &str,
+    older_than: Duration,
+    dry_run: bool,
+    concurrency: usize,
+) -> anyhow::Result<()> {
+    let cutoff = (Utc::now() - chrono::Duration::from_std(older_than)?).naive_utc();
+    let mut stale = vec![];
+    let mut kept = 0;
+    client.for_each_file(prefix, &[], concurrency, |fi| {
+        if changed_before(&fi.last_changed, cutoff) {
+            stale.push(client.remote_name(&fi));
+        } else {
+            kept += 1;
+        }
+        Ok(())
+    })?;
+    stale.sort();
+    if dry_run {
+        for name in &stale {
+            println!("{name}: delete");
+        }
+        println!("Would prune {} files, keeping {kept}", stale.len());
+        return Ok(());
+    }
+    let deleted = delete_streamed(client, concurrency, |send_work| {
+        for name in stale {
+            send_work.send(name)?;
+        }
+        Ok(())
+    })?;
+    println!("Pruned {deleted} files, kept {kept}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_listing_timestamps() {
+        let cutoff =
+            NaiveDateTime::parse_from_str("2025-04-01T00:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        assert!(changed_before("2025-03-31T23:59:59.999", cutoff));
+        assert!(changed_before("2025-03-01T12:00:00", cutoff));
+        assert!(!changed_before("2025-04-15T16:52:33.824", cutoff));
+        assert!(!changed_before("", cutoff));
+    }
+}
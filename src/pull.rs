@@ -0,0 +1,331 @@
+use crate::api::{FileMeta, ReadOnly, StorageZoneClient};
+use crate::encryption::{self, Cipher};
+use crate::error::{TaskPanicked, contain_panic};
+use crate::local_path;
+use crate::planning::{SystemObjects, sha256};
+use anyhow::Context;
+use crossbeam::channel::unbounded;
+use fxhash::FxHashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// What pulling does with one file
+#[derive(Debug, PartialEq, Eq)]
+pub enum PullPlan {
+    Download {
+        remote: String,
+        local: PathBuf,
+    },
+    /// A local file the storage zone doesn't have
+    Delete {
+        local: PathBuf,
+    },
+}
+
+impl PullPlan {
+    /// The file the task writes or deletes
+    fn name(&self) -> String {
+        match self {
+            PullPlan::Download { remote, .. } => remote.clone(),
+            PullPlan::Delete { local } => local.display().to_string(),
+        }
+    }
+}
+
+/// Leave out the lockfiles and .thumper/ a sync keeps in the zone, which don't belong in a mirror
+fn without_system_objects<T>(files: &mut FxHashMap<String, T>) {
+    let system = SystemObjects::default();
+    let is_system = system.matcher();
+    files.retain(|name, _| !is_system(name));
+}
+
+/// The name of a remote file relative to `remote_root`
+fn relative_name<'a>(remote_root: &str, name: &'a str) -> &'a str {
+    let remote_root = remote_root.trim_start_matches('/').trim_end_matches('/');
+    if remote_root.is_empty() {
+        name
+    } else {
+        name.strip_prefix(remote_root)
+            .and_then(|name| name.strip_prefix('/'))
+            .unwrap_or(name)
+    }
+}
+
+/// Download remote files missing locally or with another checksum, and delete local files the
+/// remote lacks if `delete` is set. `checksum` hashes a local file.
+pub fn plan_pull(
+    remote: &FxHashMap<String, FileMeta>,
+    local: &FxHashMap<String, PathBuf>,
+    local_root: &Path,
+    remote_root: &str,
+    delete: bool,
+    checksum: impl Fn(&Path) -> anyhow::Result<[u8; 32]>,
+) -> anyhow::Result<Vec<PullPlan>> {
+    let mut names: Vec<_> = remote.keys().collect();
+    names.sort();
+    let mut job = vec![];
+    for name in names {
+        let unchanged = match (local.get(name), remote[name].checksum) {
+            (Some(path), Some(remote_checksum)) => checksum(path)? == remote_checksum,
+            _ => false,
+        };
+        if !unchanged {
+            job.push(PullPlan::Download {
+                remote: name.clone(),
+                local: local_root.join(relative_name(remote_root, name)),
+            });
+        }
+    }
+    if delete {
+        let mut gone: Vec<_> = local
+            .iter()
+            .filter(|(name, _)| !remote.contains_key(*name))
+            .map(|(_, path)| path.clone())
+            .collect();
+        gone.sort();
+        job.extend(gone.into_iter().map(|local| PullPlan::Delete { local }));
+    }
+    Ok(job)
+}
+
+/// Write downloaded `content` to `local`, decrypting it first if the zone is encrypted
+fn save(local: &Path, content: Vec<u8>, cipher: Option<&Cipher>) -> anyhow::Result<()> {
+    let content = match cipher {
+        Some(cipher) => cipher
+            .decrypt(&content)
+            .with_context(|| format!("Unable to decrypt {}", local.display()))?,
+        None => content,
+    };
+    if let Some(parent) = local.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(local, content).with_context(|| format!("Unable to write {}", local.display()))
+}
+
+fn execute(
+    client: &StorageZoneClient<ReadOnly>,
+    plan: &PullPlan,
+    cipher: Option<&Cipher>,
+) -> anyhow::Result<()> {
+    match plan {
+        PullPlan::Download { remote, local } => {
+            let content = client
+                .read_bytes(remote)
+                .with_context(|| format!("Unable to download {remote}"))?;
+            save(local, content, cipher)
+        }
+        PullPlan::Delete { local } => {
+            fs::remove_file(local).with_context(|| format!("Unable to delete {}", local.display()))
+        }
+    }
+}
+
+/// Make `local_root` match everything below `remote_root` in the storage zone, decrypting files
+/// with `cipher` if they were synced with --encrypt
+pub fn pull(
+    client: &StorageZoneClient<ReadOnly>,
+    remote_root: &str,
+    local_root: &Path,
+    delete: bool,
+    dry_run: bool,
+    concurrency: usize,
+    cipher: Option<&Cipher>,
+) -> anyhow::Result<()> {
+    let mut remote = client.list_files(remote_root, &[], concurrency)?;
+    if let Some(cipher) = cipher {
+        let manifest = encryption::load_manifest(client, cipher)?;
+        encryption::use_plaintext_checksums(&mut remote, &manifest);
+    }
+    let mut local = if local_root.is_dir() {
+        let root = local_root.to_str().context("Invalid utf8")?;
+        local_path::files_by_remote_name(root, remote_root)?.files
+    } else {
+        FxHashMap::default()
+    };
+    without_system_objects(&mut remote);
+    without_system_objects(&mut local);
+    let job = plan_pull(&remote, &local, local_root, remote_root, delete, |path| {
+        Ok(sha256(path, &fs::read(path)?))
+    })?;
+    let downloads = job
+        .iter()
+        .filter(|plan| matches!(plan, PullPlan::Download { .. }))
+        .count();
+    let (deletes, unchanged) = (job.len() - downloads, remote.len() - downloads);
+    if dry_run {
+        for plan in &job {
+            match plan {
+                PullPlan::Download { remote, .. } => println!("{remote}: download"),
+                PullPlan::Delete { local } => println!("{}: delete", local.display()),
+            }
+        }
+        println!("Would pull: {downloads} downloaded, {deletes} deleted, {unchanged} unchanged");
+        return Ok(());
+    }
+
+    let (send_work, receive_work) = unbounded();
+    for plan in job {
+        send_work.send(plan)?;
+    }
+    drop(send_work);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..concurrency.max(1))
+            .map(|_| {
+                let receive_work = receive_work.clone();
+                scope.spawn(move || {
+                    while let Ok(plan) = receive_work.recv() {
+                        contain_panic(|| execute(client, &plan, cipher)).map_err(|err| {
+                            // Unlike other errors, a panic doesn't say which file it was pulling
+                            if err.is::<TaskPanicked>() {
+                                err.context(format!("Unable to pull {}", plan.name()))
+                            } else {
+                                err
+                            }
+                        })?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker
+                .join()
+                .unwrap_or_else(|payload| Err(TaskPanicked::from_payload(payload).into()))?;
+        }
+        Ok::<_, anyhow::Error>(())
+    })?;
+    println!("Pulled: {downloads} downloaded, {deletes} deleted, {unchanged} unchanged");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_downloads_of_new_and_changed_files() {
+        let mut remote = FxHashMap::default();
+        for (name, checksum) in [
+            ("docs/index.html", [1; 32]),
+            ("docs/guide/intro.html", [2; 32]),
+            ("docs/style.css", [3; 32]),
+        ] {
+            remote.insert(
+                name.to_string(),
+                FileMeta {
+                    checksum: Some(checksum),
+                },
+            );
+        }
+        let mut local = FxHashMap::default();
+        for name in ["index.html", "style.css", "old.html"] {
+            local.insert(format!("docs/{name}"), PathBuf::from("site").join(name));
+        }
+        let checksum = |path: &Path| {
+            Ok(if path.ends_with("style.css") {
+                [3; 32]
+            } else {
+                [9; 32]
+            })
+        };
+        let job = plan_pull(&remote, &local, Path::new("site"), "/docs/", true, checksum).unwrap();
+        assert_eq!(
+            job,
+            vec![
+                PullPlan::Download {
+                    remote: "docs/guide/intro.html".to_string(),
+                    local: PathBuf::from("site/guide/intro.html"),
+                },
+                PullPlan::Download {
+                    remote: "docs/index.html".to_string(),
+                    local: PathBuf::from("site/index.html"),
+                },
+                PullPlan::Delete {
+                    local: PathBuf::from("site/old.html"),
+                },
+            ]
+        );
+        let job = plan_pull(
+            &remote,
+            &local,
+            Path::new("site"),
+            "/docs/",
+            false,
+            checksum,
+        )
+        .unwrap();
+        assert_eq!(job.len(), 2);
+    }
+
+    #[test]
+    fn leaves_out_system_objects() {
+        let mut remote = FxHashMap::default();
+        for name in [
+            "index.html",
+            ".thumper.lock",
+            "docs/.thumper.lock",
+            ".thumper/history.jsonl",
+            ".thumper/manifest.enc",
+        ] {
+            remote.insert(name.to_string(), FileMeta { checksum: None });
+        }
+        let mut local = FxHashMap::default();
+        local.insert(
+            ".thumper.lock".to_string(),
+            PathBuf::from("site/.thumper.lock"),
+        );
+        without_system_objects(&mut remote);
+        without_system_objects(&mut local);
+        let job = plan_pull(&remote, &local, Path::new("site"), "/", true, |_| {
+            Ok([0; 32])
+        })
+        .unwrap();
+        assert_eq!(
+            job,
+            vec![PullPlan::Download {
+                remote: "index.html".to_string(),
+                local: PathBuf::from("site/index.html"),
+            }]
+        );
+    }
+
+    #[test]
+    fn decrypts_downloads_and_compares_plaintext_checksums() {
+        let dir = std::env::temp_dir().join(format!("thumper-pull-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = dir.join("key");
+        fs::write(&key, hex::encode([7; 32])).unwrap();
+        let cipher = Cipher::parse(&format!("aes-gcm:{}", key.display())).unwrap();
+        let local = dir.join("site/index.html");
+        save(
+            &local,
+            cipher.encrypt(b"<h1>hi</h1>").unwrap(),
+            Some(&cipher),
+        )
+        .unwrap();
+        assert_eq!(fs::read(&local).unwrap(), b"<h1>hi</h1>");
+
+        let mut remote = FxHashMap::default();
+        remote.insert(
+            "index.html".to_string(),
+            FileMeta {
+                checksum: Some([9; 32]),
+            },
+        );
+        let mut manifest = FxHashMap::default();
+        manifest.insert(
+            "index.html".to_string(),
+            hex::encode(sha256(&local, b"<h1>hi</h1>")),
+        );
+        encryption::use_plaintext_checksums(&mut remote, &manifest);
+        let mut locals = FxHashMap::default();
+        locals.insert("index.html".to_string(), local.clone());
+        let job = plan_pull(&remote, &locals, &dir.join("site"), "/", false, |path| {
+            Ok(sha256(path, &fs::read(path)?))
+        })
+        .unwrap();
+        assert_eq!(job, vec![]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,597 @@
+use crate::bunny_api::BunnyApiClient;
+use crate::cli::{ExportFormat, HostnameAction, PullZoneAction};
+use anyhow::{Context, anyhow};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+/// Fields of the pull zone API that are not settings: identity, usage, secrets, and the parts
+/// managed through their own endpoints.
+const NOT_SETTINGS: [&str; 9] = [
+    "Id",
+    "Hostnames",
+    "EdgeRules",
+    "MonthlyBandwidthUsed",
+    "MonthlyCharges",
+    "ZoneSecurityKey",
+    "Suspended",
+    "Enabled",
+    "BunnyAiImageBlueprints",
+];
+
+/// Pull zone configuration as written in pullzone.toml. Keys are the field names of the pull zone
+/// API in snake_case, like `origin_url` for `OriginUrl`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PullZoneFile {
+    #[serde(default)]
+    pub settings: toml::Table,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hostnames: Vec<Hostname>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edge_rules: Vec<toml::Table>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hostname {
+    pub value: String,
+    #[serde(default)]
+    pub force_ssl: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiHostname {
+    value: String,
+    #[serde(rename = "ForceSSL", default)]
+    force_ssl: bool,
+    #[serde(default)]
+    is_system_hostname: bool,
+}
+
+pub fn snake_to_pascal(key: &str) -> String {
+    key.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Inverse of [`snake_to_pascal`], every uppercase letter starts a new word
+pub fn pascal_to_snake(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    for (i, ch) in key.chars().enumerate() {
+        if ch.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.extend(ch.to_lowercase());
+    }
+    snake
+}
+
+/// Convert file content to what the API expects, renaming keys of nested tables as well
+fn to_api_value(value: &toml::Value) -> anyhow::Result<Value> {
+    Ok(match value {
+        toml::Value::Table(table) => Value::Object(
+            table
+                .iter()
+                .map(|(k, v)| Ok((snake_to_pascal(k), to_api_value(v)?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        toml::Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(to_api_value)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        other => serde_json::to_value(other)?,
+    })
+}
+
+/// Convert API content to what goes in the file. TOML has no null, so those are left out.
+fn from_api_value(value: &Value) -> anyhow::Result<Option<toml::Value>> {
+    Ok(match value {
+        Value::Null => None,
+        Value::Object(object) => Some(toml::Value::Table(from_api_object(object)?)),
+        Value::Array(items) => Some(toml::Value::Array(
+            items
+                .iter()
+                .filter_map(|v| from_api_value(v).transpose())
+                .collect::<anyhow::Result<_>>()?,
+        )),
+        other => Some(toml::Value::try_from(other)?),
+    })
+}
+
+fn from_api_object(object: &Map<String, Value>) -> anyhow::Result<toml::Table> {
+    let mut table = toml::Table::new();
+    for (key, value) in object {
+        if let Some(value) = from_api_value(value)? {
+            table.insert(pascal_to_snake(key), value);
+        }
+    }
+    Ok(table)
+}
+
+fn api_hostnames(current: &Map<String, Value>) -> anyhow::Result<Vec<ApiHostname>> {
+    match current.get("Hostnames") {
+        Some(hostnames) => Ok(serde_json::from_value(hostnames.clone())?),
+        None => Ok(vec![]),
+    }
+}
+
+fn api_edge_rules(current: &Map<String, Value>) -> Vec<Map<String, Value>> {
+    current
+        .get("EdgeRules")
+        .and_then(|rules| rules.as_array())
+        .map(|rules| {
+            rules
+                .iter()
+                .filter_map(|rule| rule.as_object().cloned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Turn the current pull zone into a file that apply would leave unchanged
+pub fn export_file(current: &Map<String, Value>) -> anyhow::Result<PullZoneFile> {
+    let settings: Map<String, Value> = current
+        .iter()
+        .filter(|(key, _)| !NOT_SETTINGS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let hostnames = api_hostnames(current)?
+        .into_iter()
+        .filter(|h| !h.is_system_hostname)
+        .map(|h| Hostname {
+            value: h.value,
+            force_ssl: h.force_ssl,
+        })
+        .collect();
+    let edge_rules = api_edge_rules(current)
+        .iter()
+        .map(from_api_object)
+        .collect::<anyhow::Result<_>>()?;
+    Ok(PullZoneFile {
+        settings: from_api_object(&settings)?,
+        hostnames,
+        edge_rules,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PullZoneChange {
+    Setting {
+        key: String,
+        current: Value,
+        desired: Value,
+    },
+    AddHostname(Hostname),
+    ForceSsl(Hostname),
+    /// Create or update an edge rule, the rule carries its Guid when updating
+    EdgeRule {
+        description: String,
+        rule: Map<String, Value>,
+        update: bool,
+    },
+}
+
+fn rule_description(rule: &Map<String, Value>) -> String {
+    rule.get("Description")
+        .and_then(|d| d.as_str())
+        .unwrap_or("")
+        .to_string()
+}
+
+/// Compare the file to the current pull zone. Hostnames and edge rules missing from the file
+/// are left alone, settings the API doesn't know are refused.
+pub fn diff_pull_zone(
+    current: &Map<String, Value>,
+    wanted: &PullZoneFile,
+) -> anyhow::Result<Vec<PullZoneChange>> {
+    let mut changes = vec![];
+    for (key, value) in &wanted.settings {
+        let api_key = snake_to_pascal(key);
+        let current = current
+            .get(&api_key)
+            .filter(|_| !NOT_SETTINGS.contains(&api_key.as_str()))
+            .with_context(|| format!("Unknown pull zone setting: {key} ({api_key})"))?;
+        let desired = to_api_value(value)?;
+        if *current != desired {
+            changes.push(PullZoneChange::Setting {
+                key: key.clone(),
+                current: current.clone(),
+                desired,
+            });
+        }
+    }
+
+    let hostnames = api_hostnames(current)?;
+    for wanted in &wanted.hostnames {
+        match hostnames
+            .iter()
+            .find(|h| h.value.eq_ignore_ascii_case(&wanted.value))
+        {
+            None => {
+                changes.push(PullZoneChange::AddHostname(wanted.clone()));
+                if wanted.force_ssl {
+                    changes.push(PullZoneChange::ForceSsl(wanted.clone()));
+                }
+            }
+            Some(existing) if existing.force_ssl != wanted.force_ssl => {
+                changes.push(PullZoneChange::ForceSsl(wanted.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+
+    let rules = api_edge_rules(current);
+    for wanted in &wanted.edge_rules {
+        let Value::Object(mut rule) = to_api_value(&toml::Value::Table(wanted.clone()))? else {
+            unreachable!("tables convert to objects");
+        };
+        let description = rule_description(&rule);
+        let existing = match rule.get("Guid") {
+            Some(guid) => rules.iter().find(|r| r.get("Guid") == Some(guid)),
+            None => {
+                let same: Vec<_> = rules
+                    .iter()
+                    .filter(|r| rule_description(r) == description)
+                    .collect();
+                if same.len() == 1 { Some(same[0]) } else { None }
+            }
+        };
+        match existing {
+            Some(existing) => {
+                let differs = rule.iter().any(|(k, v)| existing.get(k) != Some(v));
+                if differs {
+                    if let Some(guid) = existing.get("Guid") {
+                        rule.insert("Guid".to_string(), guid.clone());
+                    }
+                    changes.push(PullZoneChange::EdgeRule {
+                        description,
+                        rule,
+                        update: true,
+                    });
+                }
+            }
+            None => {
+                rule.remove("Guid");
+                changes.push(PullZoneChange::EdgeRule {
+                    description,
+                    rule,
+                    update: false,
+                });
+            }
+        }
+    }
+    Ok(changes)
+}
+
+pub fn get_pull_zone(api: &BunnyApiClient, id: u64) -> anyhow::Result<Map<String, Value>> {
+    Ok(api.get(&format!("pullzone/{id}"))?)
+}
+
+pub fn add_hostname(api: &BunnyApiClient, id: u64, hostname: &str) -> anyhow::Result<()> {
+    let mut body = Map::new();
+    body.insert("Hostname".to_string(), hostname.into());
+    api.post(&format!("pullzone/{id}/addHostname"), &body)?;
+    Ok(())
+}
+
+pub fn remove_hostname(api: &BunnyApiClient, id: u64, hostname: &str) -> anyhow::Result<()> {
+    let mut body = Map::new();
+    body.insert("Hostname".to_string(), hostname.into());
+    api.send(
+        api.request(Method::DELETE, &format!("pullzone/{id}/removeHostname"))
+            .json(&body),
+    )?;
+    Ok(())
+}
+
+/// Ask bunny.net to issue a free certificate, the hostname must already point to the pull zone
+pub fn load_free_certificate(api: &BunnyApiClient, hostname: &str) -> anyhow::Result<()> {
+    api.send(
+        api.request(Method::GET, "pullzone/loadFreeCertificate")
+            .query(&[("hostname", hostname)]),
+    )?;
+    Ok(())
+}
+
+pub fn set_force_ssl(
+    api: &BunnyApiClient,
+    id: u64,
+    hostname: &str,
+    force_ssl: bool,
+) -> anyhow::Result<()> {
+    let mut body = Map::new();
+    body.insert("Hostname".to_string(), hostname.into());
+    body.insert("ForceSSL".to_string(), force_ssl.into());
+    api.post(&format!("pullzone/{id}/setForceSSL"), &body)?;
+    Ok(())
+}
+
+fn apply_changes(
+    api: &BunnyApiClient,
+    id: u64,
+    changes: Vec<PullZoneChange>,
+) -> anyhow::Result<()> {
+    let mut settings = Map::new();
+    for change in changes {
+        match change {
+            PullZoneChange::Setting { key, desired, .. } => {
+                settings.insert(snake_to_pascal(&key), desired);
+            }
+            PullZoneChange::AddHostname(hostname) => add_hostname(api, id, &hostname.value)?,
+            PullZoneChange::ForceSsl(hostname) => {
+                set_force_ssl(api, id, &hostname.value, hostname.force_ssl)?
+            }
+            PullZoneChange::EdgeRule { rule, .. } => {
+                api.post(&format!("pullzone/{id}/edgerules/addOrUpdate"), &rule)?;
+            }
+        }
+    }
+    if !settings.is_empty() {
+        api.post(&format!("pullzone/{id}"), &settings)?;
+    }
+    Ok(())
+}
+
+fn read_file(file: &Path) -> anyhow::Result<PullZoneFile> {
+    let content =
+        fs::read_to_string(file).with_context(|| format!("Unable to read {}", file.display()))?;
+    if file.extension().is_some_and(|ext| ext == "json") {
+        Ok(serde_json::from_str(&content)?)
+    } else {
+        toml::from_str(&content).map_err(|err| anyhow!("Invalid {}: {err}", file.display()))
+    }
+}
+
+fn apply(api: &BunnyApiClient, id: u64, file: &Path, dry_run: bool) -> anyhow::Result<()> {
+    let wanted = read_file(file)?;
+    let current = get_pull_zone(api, id)?;
+    let changes = diff_pull_zone(&current, &wanted)?;
+    if changes.is_empty() {
+        println!("Pull zone {id} is up to date");
+        return Ok(());
+    }
+    for change in &changes {
+        match change {
+            PullZoneChange::Setting {
+                key,
+                current,
+                desired,
+            } => println!("~ {key}: {current} -> {desired}"),
+            PullZoneChange::AddHostname(hostname) => println!("+ hostname {}", hostname.value),
+            PullZoneChange::ForceSsl(hostname) => println!(
+                "~ hostname {} force_ssl: {}",
+                hostname.value, hostname.force_ssl
+            ),
+            PullZoneChange::EdgeRule {
+                description,
+                update,
+                ..
+            } => println!(
+                "{} edge rule {description}",
+                if *update { "~" } else { "+" }
+            ),
+        }
+    }
+    if !dry_run {
+        let count = changes.len();
+        apply_changes(api, id, changes)?;
+        println!("Applied {count} changes to pull zone {id}");
+    }
+    Ok(())
+}
+
+fn export(api: &BunnyApiClient, id: u64, format: ExportFormat) -> anyhow::Result<()> {
+    let file = export_file(&get_pull_zone(api, id)?)?;
+    match format {
+        ExportFormat::Toml => print!("{}", toml::to_string(&file)?),
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&file)?),
+    }
+    Ok(())
+}
+
+pub fn run(api: &BunnyApiClient, action: PullZoneAction) -> anyhow::Result<()> {
+    match action {
+        PullZoneAction::Apply {
+            pullzone,
+            file,
+            dry_run,
+        } => apply(api, pullzone, &file, dry_run),
+        PullZoneAction::Export { pullzone, format } => export(api, pullzone, format),
+        PullZoneAction::Hostname { command } => run_hostname(api, command),
+    }
+}
+
+fn run_hostname(api: &BunnyApiClient, action: HostnameAction) -> anyhow::Result<()> {
+    match action {
+        HostnameAction::Add {
+            pullzone,
+            hostname,
+            load_free_cert,
+            force_ssl,
+        } => {
+            add_hostname(api, pullzone, &hostname)?;
+            println!("Added {hostname} to pull zone {pullzone}");
+            if load_free_cert {
+                load_free_certificate(api, &hostname)
+                    .with_context(|| format!("Unable to issue a certificate for {hostname}, does its DNS point to the pull zone?"))?;
+                println!("Loaded free certificate for {hostname}");
+            }
+            if force_ssl {
+                set_force_ssl(api, pullzone, &hostname, true)?;
+                println!("Forcing SSL for {hostname}");
+            }
+        }
+        HostnameAction::Remove { pullzone, hostname } => {
+            remove_hostname(api, pullzone, &hostname)?;
+            println!("Removed {hostname} from pull zone {pullzone}");
+        }
+        HostnameAction::ForceSsl {
+            pullzone,
+            hostname,
+            disable,
+        } => {
+            set_force_ssl(api, pullzone, &hostname, !disable)?;
+            println!(
+                "{} SSL for {hostname}",
+                if disable {
+                    "No longer forcing"
+                } else {
+                    "Forcing"
+                }
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pull_zone() -> Map<String, Value> {
+        json!({
+            "Id": 42,
+            "Name": "example",
+            "OriginUrl": "https://old.example.com",
+            "CacheControlMaxAgeOverride": 3600,
+            "BlockedCountries": [],
+            "IgnoreQueryStrings": true,
+            "ZoneSecurityKey": "secret",
+            "ErrorPageCustomCode": null,
+            "Hostnames": [
+                {"Id": 1, "Value": "example.b-cdn.net", "ForceSSL": false, "IsSystemHostname": true, "HasCertificate": true},
+                {"Id": 2, "Value": "www.example.com", "ForceSSL": true, "IsSystemHostname": false, "HasCertificate": true}
+            ],
+            "EdgeRules": [
+                {"Guid": "abc", "ActionType": 1, "ActionParameter1": "https://example.com", "Triggers": [
+                    {"Type": 0, "PatternMatches": ["*/old"], "PatternMatchingType": 0}
+                ], "TriggerMatchingType": 0, "Description": "redirect old", "Enabled": true}
+            ]
+        })
+        .as_object()
+        .unwrap()
+        .clone()
+    }
+
+    #[test]
+    fn converts_keys() {
+        assert_eq!(snake_to_pascal("origin_url"), "OriginUrl");
+        assert_eq!(snake_to_pascal("enable_tls1"), "EnableTls1");
+        assert_eq!(
+            snake_to_pascal("a_w_s_signing_enabled"),
+            "AWSSigningEnabled"
+        );
+        for key in [
+            "AWSSigningEnabled",
+            "EnableTLS1",
+            "ActionParameter1",
+            "OriginUrl",
+        ] {
+            assert_eq!(snake_to_pascal(&pascal_to_snake(key)), key);
+        }
+    }
+
+    #[test]
+    fn diffs_settings() {
+        let wanted: PullZoneFile = toml::from_str(
+            r#"
+            [settings]
+            origin_url = "https://new.example.com"
+            cache_control_max_age_override = 3600
+            blocked_countries = ["XX"]
+            "#,
+        )
+        .unwrap();
+        let changes = diff_pull_zone(&pull_zone(), &wanted).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                PullZoneChange::Setting {
+                    key: "blocked_countries".to_string(),
+                    current: json!([]),
+                    desired: json!(["XX"]),
+                },
+                PullZoneChange::Setting {
+                    key: "origin_url".to_string(),
+                    current: json!("https://old.example.com"),
+                    desired: json!("https://new.example.com"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_unknown_settings() {
+        let wanted: PullZoneFile = toml::from_str("[settings]\norigin_uri = \"x\"").unwrap();
+        assert!(diff_pull_zone(&pull_zone(), &wanted).is_err());
+        let wanted: PullZoneFile = toml::from_str("[settings]\nzone_security_key = \"x\"").unwrap();
+        assert!(diff_pull_zone(&pull_zone(), &wanted).is_err());
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let exported = export_file(&pull_zone()).unwrap();
+        assert!(!exported.settings.contains_key("zone_security_key"));
+        assert!(!exported.settings.contains_key("error_page_custom_code"));
+        assert_eq!(
+            exported.hostnames,
+            vec![Hostname {
+                value: "www.example.com".to_string(),
+                force_ssl: true
+            }]
+        );
+        let as_toml = toml::to_string(&exported).unwrap();
+        let parsed: PullZoneFile = toml::from_str(&as_toml).unwrap();
+        assert_eq!(diff_pull_zone(&pull_zone(), &parsed).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn diffs_hostnames_and_edge_rules() {
+        let wanted: PullZoneFile = toml::from_str(
+            r#"
+            [[hostnames]]
+            value = "www.example.com"
+            force_ssl = false
+
+            [[hostnames]]
+            value = "cdn.example.com"
+
+            [[edge_rules]]
+            description = "redirect old"
+            enabled = false
+            "#,
+        )
+        .unwrap();
+        let changes = diff_pull_zone(&pull_zone(), &wanted).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            changes[0],
+            PullZoneChange::ForceSsl(Hostname {
+                value: "www.example.com".to_string(),
+                force_ssl: false
+            })
+        );
+        assert!(
+            matches!(&changes[1], PullZoneChange::AddHostname(h) if h.value == "cdn.example.com")
+        );
+        match &changes[2] {
+            PullZoneChange::EdgeRule { rule, update, .. } => {
+                assert!(update);
+                assert_eq!(rule.get("Guid"), Some(&json!("abc")));
+                assert_eq!(rule.get("Enabled"), Some(&json!(false)));
+            }
+            other => panic!("Unexpected change {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::bunny_api::BunnyApiClient;
+use crate::error::ThumperError;
+use crate::local_path::read_input;
+use crate::pacing::{MAX_RATE_LIMIT_WAIT, Pacer};
+use crossbeam::channel::unbounded;
+use reqwest::Method;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// How many times a purge rejected with 429 Too Many Requests is attempted again
+const RATE_LIMIT_RETRIES: u32 = 5;
+
+/// How fast to send purge requests
+#[derive(Debug, Clone, Copy)]
+pub struct PurgePacing {
+    pub concurrency: usize,
+    pub burst: usize,
+    /// Steady requests per second once the burst is spent
+    pub rate: f64,
+}
+
+pub fn purge_url(api: &BunnyApiClient, url: &str) -> anyhow::Result<()> {
+    api.send(api.request(Method::POST, "purge").query(&[("url", url)]))?;
+    Ok(())
+}
+
+/// Purge everything cached by a pull zone
+pub fn purge_pull_zone(api: &BunnyApiClient, pullzone: u64) -> anyhow::Result<()> {
+    api.send(api.request(Method::POST, &format!("pullzone/{pullzone}/purgeCache")))?;
+    Ok(())
+}
+
+/// Turn a path relative to the storage zone into a URL below `base_url`
+pub fn url_for(base_url: &str, path: &str) -> String {
+    let encoded: Vec<_> = path
+        .trim_start_matches('/')
+        .split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect();
+    format!("{}/{}", base_url.trim_end_matches('/'), encoded.join("/"))
+}
+
+/// Paths that were uploaded or deleted according to `thumper sync --verbose` output
+pub fn changed_paths(sync_output: &str) -> Vec<String> {
+    sync_output
+        .lines()
+        .filter_map(|line| line.rsplit_once(": "))
+        .filter(|(_, event)| matches!(event.trim(), "put" | "delete"))
+        .map(|(path, _)| path.to_string())
+        .collect()
+}
+
+/// The HTML pages among `changed`, with the directory they are served as for index pages
+pub fn html_paths<'a>(changed: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    let mut paths = vec![];
+    for path in changed {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let lower = name.to_ascii_lowercase();
+        if !(lower.ends_with(".html") || lower.ends_with(".htm")) {
+            continue;
+        }
+        paths.push(path.clone());
+        if matches!(lower.as_str(), "index.html" | "index.htm") {
+            paths.push(path[..path.len() - name.len()].to_string());
+        }
+    }
+    paths
+}
+
+/// The Retry-After of a 429 Too Many Requests, or `backoff` if it didn't say
+fn rate_limit_wait(err: &anyhow::Error, backoff: Duration) -> Option<Duration> {
+    match ThumperError::find(err) {
+        Some(ThumperError::RateLimited(error)) => Some(
+            error
+                .retry_after
+                .unwrap_or(backoff)
+                .min(MAX_RATE_LIMIT_WAIT),
+        ),
+        _ => None,
+    }
+}
+
+/// Purge, waiting as long as bunny.net asks or backing off exponentially while it answers 429
+fn purge_paced(api: &BunnyApiClient, pacer: &Pacer, url: &str) -> anyhow::Result<()> {
+    let mut backoff = Duration::from_secs(1);
+    for attempt in 0.. {
+        pacer.acquire();
+        match purge_url(api, url) {
+            Err(err) if attempt < RATE_LIMIT_RETRIES => match rate_limit_wait(&err, backoff) {
+                Some(wait) => {
+                    eprintln!("WARNING: Rate limited purging {url}, retrying in {wait:?}");
+                    thread::sleep(wait);
+                    backoff *= 2;
+                }
+                None => return Err(err),
+            },
+            result => return result,
+        }
+    }
+    unreachable!("the loop only ends by returning")
+}
+
+/// Purge the URLs with a pool of workers sharing one pacer
+pub fn purge_urls(
+    api: &BunnyApiClient,
+    urls: Vec<String>,
+    pacing: PurgePacing,
+) -> anyhow::Result<()> {
+    let pacer = Pacer::new(pacing.burst, pacing.rate);
+    let (send_work, receive_work) = unbounded();
+    let (send_result, receive_result) = unbounded();
+    let expected = urls.len();
+    for url in urls {
+        send_work.send(url)?;
+    }
+    drop(send_work);
+
+    thread::scope(|scope| {
+        for _ in 0..pacing.concurrency.max(1) {
+            let receive_work = receive_work.clone();
+            let send_result = send_result.clone();
+            let pacer = &pacer;
+            scope.spawn(move || {
+                while let Ok(url) = receive_work.recv() {
+                    let r = purge_paced(api, pacer, &url).map(|_| url);
+                    send_result.send(r)?;
+                }
+                Ok::<(), anyhow::Error>(())
+            });
+        }
+
+        for _ in 0..expected {
+            let url = receive_result.recv()??;
+            println!("Purged {url}");
+        }
+        Ok(())
+    })
+}
+
+/// Purge the paths, along with those changed in the sync output, and return all of them
+pub fn purge_paths(
+    api: &BunnyApiClient,
+    base_url: &str,
+    mut paths: Vec<String>,
+    from_sync_output: Option<&Path>,
+    pacing: PurgePacing,
+) -> anyhow::Result<Vec<String>> {
+    if let Some(source) = from_sync_output {
+        paths.extend(changed_paths(&read_input(source)?));
+    }
+    let urls = paths.iter().map(|path| url_for(base_url, path)).collect();
+    purge_urls(api, urls, pacing)?;
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_paths_into_urls() {
+        assert_eq!(
+            url_for("https://example.com/", "/docs/my page.html"),
+            "https://example.com/docs/my%20page.html"
+        );
+        assert_eq!(
+            url_for("https://example.com", "index.html"),
+            "https://example.com/index.html"
+        );
+    }
+
+    #[test]
+    fn reads_changed_paths_from_sync_output() {
+        let output = "css/site.css: put\nindex.html: unchanged\nold.html: delete\nWARNING: Remote is locked\n";
+        assert_eq!(changed_paths(output), vec!["css/site.css", "old.html"]);
+    }
+
+    #[test]
+    fn picks_html_pages_and_their_directories() {
+        let changed: Vec<_> = [
+            "index.html",
+            "css/site.css",
+            "docs/Index.htm",
+            "docs/a.html",
+        ]
+        .map(String::from)
+        .into();
+        assert_eq!(
+            html_paths(&changed),
+            vec!["index.html", "", "docs/Index.htm", "docs/", "docs/a.html"]
+        );
+        assert_eq!(url_for("https://example.com", ""), "https://example.com/");
+    }
+}
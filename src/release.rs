@@ -0,0 +1,216 @@
+use crate::api::DEFAULT_ENDPOINT;
+use crate::bunny_api::BunnyApiClient;
+use crate::cli::{Concurrency, SyncArgs};
+use crate::config::{Environment, ZoneSync};
+use crate::generator;
+use crate::health::{self, Expectation};
+use crate::purge::purge_pull_zone;
+use anyhow::Context;
+use clap::Parser;
+use reqwest::blocking::Client;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long health checks may keep failing when the environment doesn't say
+const DEFAULT_HEALTH_TIMEOUT: Duration = Duration::from_secs(120);
+
+enum Step<'a> {
+    Sync(&'a ZoneSync),
+    ParallelSync(usize),
+    Purge(u64),
+    HealthChecks,
+}
+
+impl Step<'_> {
+    fn describe(&self) -> String {
+        match self {
+            Step::Sync(zone) => format!(
+                "sync {} to {}",
+                zone.local_path
+                    .as_deref()
+                    .unwrap_or("the detected publish directory"),
+                zone.storage_zone
+            ),
+            Step::ParallelSync(zones) => format!("sync {zones} zones in parallel"),
+            Step::Purge(pullzone) => format!("purge pull zone {pullzone}"),
+            Step::HealthChecks => "run health checks".to_string(),
+        }
+    }
+}
+
+/// Arguments to thumper sync for one zone of the release
+fn sync_args(zone: &ZoneSync, env: &Environment, dry_run: bool) -> anyhow::Result<SyncArgs> {
+    let local_path = match &zone.local_path {
+        Some(local_path) => local_path.clone(),
+        None => generator::publish_dir(Path::new("."))?,
+    };
+    let mut args = vec!["sync".to_string(), local_path, zone.storage_zone.clone()];
+    args.extend(zone.args.iter().cloned());
+    if let Some(regions) = &env.wait_replicated {
+        args.push(format!("--wait-replicated={}", regions.join(",")));
+    }
+    if dry_run {
+        args.push("--dry-run".to_string());
+    }
+    Ok(SyncArgs::try_parse_from(args)?)
+}
+
+/// Split the connection budget of parallel syncs, given the endpoint of each. Every endpoint gets
+/// an equal share of `global`, at most `per_endpoint`, split evenly between the syncs to it, so a
+/// slow region only slows down its own syncs.
+fn budgets(endpoints: &[&str], per_endpoint: usize, global: Option<usize>) -> Vec<usize> {
+    let mut distinct = endpoints.to_vec();
+    distinct.sort_unstable();
+    distinct.dedup();
+    let endpoint_share = match global {
+        Some(global) => per_endpoint.min(global / distinct.len().max(1)),
+        None => per_endpoint,
+    };
+    endpoints
+        .iter()
+        .map(|endpoint| {
+            let syncs = endpoints.iter().filter(|other| *other == endpoint).count();
+            (endpoint_share / syncs).max(1)
+        })
+        .collect()
+}
+
+/// Cap the concurrency of each sync at its share of the connection budget
+fn apply_budgets(syncs: &mut [SyncArgs], env: &Environment) {
+    let endpoints: Vec<_> = syncs
+        .iter()
+        .map(|args| args.endpoint.as_deref().unwrap_or(DEFAULT_ENDPOINT))
+        .collect();
+    let per_endpoint = env
+        .max_connections_per_endpoint
+        .unwrap_or_else(num_cpus::get);
+    let budgets = budgets(&endpoints, per_endpoint, env.max_connections);
+    for (args, budget) in syncs.iter_mut().zip(budgets) {
+        let concurrency = match args.concurrency {
+            Some(Concurrency::Fixed(concurrency)) => concurrency.min(budget),
+            _ => budget,
+        };
+        args.concurrency = Some(Concurrency::Fixed(concurrency));
+    }
+}
+
+/// Sync every zone, waiting for replication if asked, then purge the pull zones and run the
+/// health checks, stopping at the first step that fails
+pub fn run<F>(
+    name: &str,
+    env: &Environment,
+    api: Option<&BunnyApiClient>,
+    client: &Client,
+    dry_run: bool,
+    sync: F,
+) -> anyhow::Result<()>
+where
+    F: Fn(SyncArgs) -> anyhow::Result<()> + Sync,
+{
+    let health_timeout = env
+        .health_timeout
+        .as_deref()
+        .map(humantime::parse_duration)
+        .transpose()
+        .context("Invalid health_timeout")?
+        .unwrap_or(DEFAULT_HEALTH_TIMEOUT);
+    let mut steps: Vec<_> = if env.parallel {
+        vec![Step::ParallelSync(env.sync.len())]
+    } else {
+        env.sync.iter().map(Step::Sync).collect()
+    };
+    steps.extend(env.purge_pullzones.iter().copied().map(Step::Purge));
+    if !env.health_checks.is_empty() {
+        steps.push(Step::HealthChecks);
+    }
+    // Validate every sync before the first one starts
+    let mut syncs = env
+        .sync
+        .iter()
+        .map(|zone| sync_args(zone, env, dry_run))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if env.parallel {
+        apply_budgets(&mut syncs, env);
+    }
+    let mut syncs = syncs.into_iter();
+
+    let total = steps.len();
+    for (index, step) in steps.iter().enumerate() {
+        let label = format!("[{}/{total}] {}", index + 1, step.describe());
+        eprintln!("==> {label}");
+        let started = Instant::now();
+        let result = match step {
+            Step::Sync(_) => sync(syncs.next().expect("one sync per step")),
+            Step::ParallelSync(_) => thread::scope(|scope| {
+                let running: Vec<_> = syncs
+                    .by_ref()
+                    .map(|args| scope.spawn(|| sync(args)))
+                    .collect();
+                // Let every sync finish, then report the first failure
+                let results: Vec<_> = running
+                    .into_iter()
+                    .map(|handle| handle.join().expect("sync thread panicked"))
+                    .collect();
+                results.into_iter().collect()
+            }),
+            Step::Purge(_) | Step::HealthChecks if dry_run => {
+                eprintln!("    skipped in a dry run");
+                continue;
+            }
+            Step::Purge(pullzone) => {
+                purge_pull_zone(api.context("Purging needs an API key")?, *pullzone)
+            }
+            Step::HealthChecks => {
+                health::run_checks(client, &env.health_checks, &Expectation::ok(health_timeout))
+            }
+        };
+        result.with_context(|| format!("Release to {name} failed at {label}"))?;
+        eprintln!("    done in {:?}", started.elapsed());
+    }
+    eprintln!("Released {name}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_connection_budget_between_endpoints_and_syncs() {
+        let endpoints = ["de.storage", "de.storage", "ny.storage"];
+        assert_eq!(budgets(&endpoints, 8, None), vec![4, 4, 8]);
+        assert_eq!(budgets(&endpoints, 8, Some(10)), vec![2, 2, 5]);
+        assert_eq!(budgets(&endpoints, 1, None), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn builds_sync_arguments() {
+        let env: Environment = toml::from_str(
+            r#"
+            wait_replicated = ["DE", "NY"]
+
+            [[sync]]
+            local_path = "public"
+            storage_zone = "example"
+            args = ["--path", "docs/", "--verbose"]
+            "#,
+        )
+        .unwrap();
+        let args = sync_args(&env.sync[0], &env, true).unwrap();
+        assert_eq!(args.local_path, "public");
+        assert_eq!(args.path, vec!["docs/"]);
+        assert!(args.verbose && args.dry_run);
+        assert_eq!(
+            args.wait_replicated,
+            Some(vec!["DE".to_string(), "NY".to_string()])
+        );
+
+        let bad = ZoneSync {
+            local_path: Some("public".to_string()),
+            storage_zone: "example".to_string(),
+            args: vec!["--no-such-flag".to_string()],
+        };
+        assert!(sync_args(&bad, &env, false).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use crate::api::FileMeta;
+use anyhow::Context;
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize)]
+struct CachedListing {
+    created: u64,
+    files: FxHashMap<String, FileMeta>,
+}
+
+/// Identifies one remote listing: the same zone crawled with different skips is a different listing
+pub struct ListingKey<'a> {
+    pub endpoint: &'a str,
+    pub storage_zone: &'a str,
+    pub path: &'a str,
+    pub skip: &'a [String],
+}
+
+/// Stores remote listings on disk so that repeated dry runs don't need to crawl the zone again
+pub struct RemoteCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl RemoteCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        RemoteCache { dir, ttl }
+    }
+
+    /// Listings of a zone share a directory, so they can be forgotten together
+    fn zone_dir(&self, endpoint: &str, storage_zone: &str) -> PathBuf {
+        self.dir.join(endpoint).join(storage_zone)
+    }
+
+    fn entry_path(&self, key: &ListingKey) -> PathBuf {
+        let mut skip = key.skip.to_vec();
+        skip.sort();
+        let mut hasher = Sha256::new();
+        for part in [key.endpoint, key.storage_zone, key.path]
+            .into_iter()
+            .chain(skip.iter().map(|s| s.as_str()))
+        {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let digest = hex::encode(hasher.finalize());
+        self.zone_dir(key.endpoint, key.storage_zone)
+            .join(format!("{}.json", &digest[..16]))
+    }
+
+    /// Returns the cached listing along with its age, if there is one that is younger than the ttl
+    pub fn load(&self, key: &ListingKey) -> Option<(FxHashMap<String, FileMeta>, Duration)> {
+        let content = fs::read(self.entry_path(key)).ok()?;
+        let cached: CachedListing = serde_json::from_slice(&content).ok()?;
+        let age = Duration::from_secs(now().saturating_sub(cached.created));
+        if age < self.ttl {
+            Some((cached.files, age))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(
+        &self,
+        key: &ListingKey,
+        files: &FxHashMap<String, FileMeta>,
+    ) -> anyhow::Result<()> {
+        let dir = self.zone_dir(key.endpoint, key.storage_zone);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Unable to create cache dir {}", dir.display()))?;
+        let cached = CachedListing {
+            created: now(),
+            files: files.clone(),
+        };
+        fs::write(self.entry_path(key), serde_json::to_vec(&cached)?)?;
+        Ok(())
+    }
+
+    /// Forget every listing of the storage zone, for use after it has been changed. Listings of
+    /// other paths or with other skips may include the changed files too.
+    pub fn invalidate(&self, endpoint: &str, storage_zone: &str) {
+        let _ = fs::remove_dir_all(self.zone_dir(endpoint, storage_zone));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("thumper-{name}-{}", std::process::id()))
+    }
+
+    fn listing() -> FxHashMap<String, FileMeta> {
+        let mut files = FxHashMap::default();
+        files.insert(
+            "index.html".to_string(),
+            FileMeta {
+                checksum: Some([7; 32]),
+            },
+        );
+        files
+    }
+
+    #[test]
+    fn reuses_fresh_listing() {
+        let dir = cache_dir("fresh");
+        let cache = RemoteCache::new(dir.clone(), Duration::from_secs(60));
+        let skip = vec!["other/".to_string()];
+        let key = ListingKey {
+            endpoint: "storage.bunnycdn.com",
+            storage_zone: "zone",
+            path: "/",
+            skip: &skip,
+        };
+        cache.store(&key, &listing()).unwrap();
+        let (files, _) = cache.load(&key).unwrap();
+        assert_eq!(files.get("index.html").unwrap().checksum, Some([7; 32]));
+
+        let other = ListingKey { skip: &[], ..key };
+        assert!(cache.load(&other).is_none());
+
+        cache.invalidate("storage.bunnycdn.com", "zone");
+        assert!(cache.load(&key).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalidates_every_listing_of_the_zone() {
+        let dir = cache_dir("invalidate");
+        let cache = RemoteCache::new(dir.clone(), Duration::from_secs(60));
+        let key = ListingKey {
+            endpoint: "storage.bunnycdn.com",
+            storage_zone: "zone",
+            path: "/",
+            skip: &[],
+        };
+        let docs = ListingKey {
+            path: "/docs/",
+            ..key
+        };
+        let other_zone = ListingKey {
+            storage_zone: "zone-staging",
+            ..key
+        };
+        for key in [&key, &docs, &other_zone] {
+            cache.store(key, &listing()).unwrap();
+        }
+        cache.invalidate("storage.bunnycdn.com", "zone");
+        assert!(cache.load(&key).is_none());
+        assert!(cache.load(&docs).is_none());
+        assert!(cache.load(&other_zone).is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_expired_listing() {
+        let dir = cache_dir("expired");
+        let cache = RemoteCache::new(dir.clone(), Duration::ZERO);
+        let key = ListingKey {
+            endpoint: "storage.bunnycdn.com",
+            storage_zone: "zone",
+            path: "/",
+            skip: &[],
+        };
+        cache.store(&key, &listing()).unwrap();
+        assert!(cache.load(&key).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
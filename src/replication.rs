@@ -0,0 +1,166 @@
+use crate::api::StorageZoneClient;
+use crate::inventory::Item;
+use anyhow::anyhow;
+use fxhash::{FxHashMap, FxHashSet};
+use std::collections::BTreeSet;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Parse the comma separated region codes bunny.net reports in ReplicatedZones
+pub fn parse_regions(regions: &str) -> BTreeSet<String> {
+    regions
+        .split(',')
+        .map(|region| region.trim().to_ascii_uppercase())
+        .filter(|region| !region.is_empty())
+        .collect()
+}
+
+/// Directory of a remote path, with a trailing slash, or empty at the zone root
+pub fn parent_dir(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(pos) => &path[..=pos],
+        None => "",
+    }
+}
+
+fn missing_regions(expected: &BTreeSet<String>, replicated: Option<&str>) -> Vec<String> {
+    let replicated = replicated.map(parse_regions).unwrap_or_default();
+    expected.difference(&replicated).cloned().collect()
+}
+
+/// Poll listings until every uploaded file reports the expected regions in ReplicatedZones.
+/// Without explicit regions, expect the regions that other files in the same directories report.
+pub fn wait_for_replication(
+    client: &StorageZoneClient,
+    uploaded: &[String],
+    regions: &[String],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let explicit: BTreeSet<String> = regions.iter().flat_map(|r| parse_regions(r)).collect();
+    let mut pending: FxHashMap<&str, FxHashSet<&str>> = FxHashMap::default();
+    for path in uploaded {
+        pending
+            .entry(parent_dir(path))
+            .or_default()
+            .insert(path.as_str());
+    }
+    let started = Instant::now();
+    let mut seen = BTreeSet::new();
+
+    loop {
+        let mut lagging = vec![];
+        for (dir, files) in pending.iter_mut() {
+            let listing = client.ls_dir(dir)?;
+            for fi in &listing {
+                if let Some(replicated) = &fi.replicated_zones {
+                    seen.extend(parse_regions(replicated));
+                }
+            }
+            let expected = if explicit.is_empty() {
+                &seen
+            } else {
+                &explicit
+            };
+            if expected.is_empty() {
+                continue;
+            }
+            for fi in listing.iter().filter(|fi| !fi.is_directory) {
+                let name = client.remote_name(fi);
+                if files.contains(name.as_str()) {
+                    let missing = missing_regions(expected, fi.replicated_zones.as_deref());
+                    if missing.is_empty() {
+                        files.remove(name.as_str());
+                    } else {
+                        lagging.push(format!("{name} (missing {})", missing.join(",")));
+                    }
+                }
+            }
+        }
+        pending.retain(|_, files| !files.is_empty());
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+        if explicit.is_empty() && seen.is_empty() {
+            return Err(anyhow!(
+                "Unable to tell which regions to wait for, pass them to --wait-replicated"
+            ));
+        }
+        if started.elapsed() > timeout {
+            let remaining: usize = pending.values().map(|files| files.len()).sum();
+            lagging.truncate(10);
+            return Err(anyhow!(
+                "{remaining} files were not replicated within {timeout:?}: {}",
+                lagging.join(", ")
+            ));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Files of an inventory missing some of the `regions`, with the regions they lack. Without
+/// regions, expect every region that any of the files reports.
+pub fn unreplicated(items: &[Item], regions: &[String]) -> Vec<(String, Vec<String>)> {
+    let mut expected: BTreeSet<String> = regions.iter().flat_map(|r| parse_regions(r)).collect();
+    if expected.is_empty() {
+        expected = items
+            .iter()
+            .flat_map(|item| parse_regions(&item.replicated_zones))
+            .collect();
+    }
+    items
+        .iter()
+        .filter_map(|item| {
+            let missing = missing_regions(&expected, Some(&item.replicated_zones));
+            (!missing.is_empty()).then(|| (item.path.clone(), missing))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_missing_regions() {
+        let expected = parse_regions("sg, NY");
+        assert_eq!(
+            missing_regions(&expected, Some("SE,NY,SG")),
+            Vec::<String>::new()
+        );
+        assert_eq!(missing_regions(&expected, Some("SE,NY")), vec!["SG"]);
+        assert_eq!(missing_regions(&expected, None), vec!["NY", "SG"]);
+    }
+
+    #[test]
+    fn groups_by_directory() {
+        assert_eq!(parent_dir("docs/index.html"), "docs/");
+        assert_eq!(parent_dir("index.html"), "");
+    }
+
+    #[test]
+    fn reports_files_missing_regions() {
+        let item = |path: &str, replicated_zones: &str| Item {
+            path: path.to_string(),
+            size: 0,
+            checksum: String::new(),
+            last_changed: String::new(),
+            replicated_zones: replicated_zones.to_string(),
+        };
+        let items = [
+            item("index.html", "SE,NY,SG"),
+            item("style.css", "SE,NY"),
+            item("404.html", ""),
+        ];
+        assert_eq!(
+            unreplicated(&items, &["SE,NY".to_string()]),
+            vec![(
+                "404.html".to_string(),
+                vec!["NY".to_string(), "SE".to_string()]
+            )]
+        );
+        assert_eq!(unreplicated(&items, &[]).len(), 2);
+    }
+}
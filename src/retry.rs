@@ -0,0 +1,255 @@
+use crate::error::ThumperError;
+use std::thread;
+use std::time::Duration;
+
+/// Errors that may go away when the request is sent again, like 5xx responses and connection
+/// resets, as opposed to rejected keys or missing files
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+
+    /// How long bunny.net asked to wait before trying again, if it did
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Transient for ThumperError {
+    fn is_transient(&self) -> bool {
+        self.is_retryable()
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ThumperError::RateLimited(error) => error.retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Errors from outside the storage API, like reading a local file, are worth another try
+impl Transient for anyhow::Error {
+    fn is_transient(&self) -> bool {
+        ThumperError::find(self).is_none_or(ThumperError::is_retryable)
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        ThumperError::find(self)?.retry_after()
+    }
+}
+
+/// How many times a request is attempted again before giving up, and how long to wait in between
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: usize,
+    /// Wait before the first retry, doubled for each one after
+    pub delay: Duration,
+    /// Longest wait between two attempts
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Give up after the first failure
+    pub const NONE: RetryPolicy = RetryPolicy {
+        retries: 0,
+        delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    /// The longest wait after failed attempt `attempt`, before jitter
+    fn ceiling(&self, attempt: usize) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(31) as u32;
+        self.delay
+            .saturating_mul(1 << doublings)
+            .min(self.max_delay)
+    }
+
+    /// How long to wait after failed attempt `attempt`: somewhere between half and all of the
+    /// exponential backoff, so workers that failed together don't retry together
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let ceiling = self.ceiling(attempt);
+        ceiling / 2 + ceiling.mul_f64(rand::random_range(0.0..=0.5))
+    }
+
+    /// The backoff after `err` failed attempt `attempt`, or longer if bunny.net asked for that,
+    /// but never longer than the longest wait
+    fn wait_after(&self, attempt: usize, err: &impl Transient) -> Duration {
+        let backoff = self.backoff(attempt);
+        err.retry_after()
+            .map_or(backoff, |after| after.min(self.max_delay).max(backoff))
+    }
+
+    /// Run `op` until it succeeds, fails with an error that isn't transient or the retry budget
+    /// is spent, calling `on_retry` with the attempt number that failed and its error before
+    /// sleeping.
+    pub fn run<T, E, F, R>(&self, mut op: F, mut on_retry: R) -> Result<T, E>
+    where
+        E: Transient,
+        F: FnMut() -> Result<T, E>,
+        R: FnMut(usize, &E),
+    {
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt <= self.retries && err.is_transient() => {
+                    on_retry(attempt, &err);
+                    thread::sleep(self.wait_after(attempt, &err));
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Like [`Self::run`], waiting on the tokio timer instead of blocking the thread
+    pub async fn run_async<T, F, Fut, R>(&self, mut op: F, mut on_retry: R) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+        R: FnMut(usize, &anyhow::Error),
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt <= self.retries && err.is_transient() => {
+                    on_retry(attempt, &err);
+                    tokio::time::sleep(self.wait_after(attempt, &err)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::HttpError;
+    use anyhow::anyhow;
+    use reqwest::{Method, StatusCode};
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        retries: 2,
+        delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    #[test]
+    fn succeeds_within_budget() {
+        let mut calls = 0;
+        let mut retried = vec![];
+        let result = POLICY.run(
+            || {
+                calls += 1;
+                if calls < 3 {
+                    Err(anyhow!("flaky"))
+                } else {
+                    Ok(calls)
+                }
+            },
+            |attempt, _| retried.push(attempt),
+        );
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(retried, vec![1, 2]);
+    }
+
+    #[test]
+    fn gives_up_when_budget_is_spent() {
+        let mut calls = 0;
+        let result: anyhow::Result<()> = POLICY.run(
+            || {
+                calls += 1;
+                Err(anyhow!("down"))
+            },
+            |_, _| {},
+        );
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn gives_up_on_errors_that_are_not_transient() {
+        let mut calls = 0;
+        let result: Result<(), ThumperError> = POLICY.run(
+            || {
+                calls += 1;
+                Err(ThumperError::NotFound(HttpError {
+                    method: Method::GET,
+                    path: "/docs/missing/".to_string(),
+                    status: StatusCode::NOT_FOUND,
+                    retry_after: None,
+                }))
+            },
+            |_, _| {},
+        );
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_longest_wait() {
+        let policy = RetryPolicy {
+            retries: 5,
+            delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(350),
+        };
+        let ceilings: Vec<_> = (1..=4).map(|attempt| policy.ceiling(attempt)).collect();
+        assert_eq!(
+            ceilings,
+            [100, 200, 350, 350].map(Duration::from_millis).to_vec()
+        );
+        for attempt in 1..=4 {
+            let backoff = policy.backoff(attempt);
+            assert!(backoff >= policy.ceiling(attempt) / 2);
+            assert!(backoff <= policy.ceiling(attempt));
+        }
+    }
+
+    #[test]
+    fn honors_retry_after_up_to_the_longest_wait() {
+        let policy = RetryPolicy {
+            retries: 5,
+            delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+        };
+        let rate_limited = |retry_after| {
+            ThumperError::RateLimited(HttpError {
+                method: Method::PUT,
+                path: "/docs/index.html".to_string(),
+                status: StatusCode::TOO_MANY_REQUESTS,
+                retry_after: Some(retry_after),
+            })
+        };
+        assert_eq!(
+            policy.wait_after(1, &rate_limited(Duration::from_secs(5))),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            policy.wait_after(1, &rate_limited(Duration::from_secs(86400))),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn retries_async_operations() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let calls = std::cell::Cell::new(0);
+        let result = runtime.block_on(POLICY.run_async(
+            || async {
+                calls.set(calls.get() + 1);
+                if calls.get() < 2 {
+                    Err(anyhow!("flaky"))
+                } else {
+                    Ok(calls.get())
+                }
+            },
+            |_, _| {},
+        ));
+        assert_eq!(result.unwrap(), 2);
+    }
+}
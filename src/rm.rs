@@ -0,0 +1,80 @@
+use crate::api::StorageZoneClient;
+use crossbeam::channel::{Sender, unbounded};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// How often to report progress while deleting a subtree
+const PROGRESS_EVERY: usize = 100;
+
+/// Delete the files `list` sends with `concurrency` workers, starting while it is still listing
+pub fn delete_streamed<F>(
+    client: &StorageZoneClient,
+    concurrency: usize,
+    list: F,
+) -> anyhow::Result<usize>
+where
+    F: FnOnce(&Sender<String>) -> anyhow::Result<()>,
+{
+    let (send_work, receive_work) = unbounded::<String>();
+    let deleted = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        let mut workers = Vec::with_capacity(concurrency);
+        for _ in 0..concurrency.max(1) {
+            let receive_work = receive_work.clone();
+            let deleted = &deleted;
+            workers.push(scope.spawn(move || {
+                while let Ok(name) = receive_work.recv() {
+                    client.delete_file(&name)?;
+                    let count = deleted.fetch_add(1, Ordering::Relaxed) + 1;
+                    if count.is_multiple_of(PROGRESS_EVERY) {
+                        eprintln!("Deleted {count} files");
+                    }
+                }
+                Ok::<(), anyhow::Error>(())
+            }));
+        }
+
+        let listed = list(&send_work);
+        // Close the channel so workers stop once the listed files are deleted
+        drop(send_work);
+        for worker in workers {
+            worker.join().expect("delete worker panicked")?;
+        }
+        listed
+    })?;
+    Ok(deleted.into_inner())
+}
+
+/// Delete every file below `path` while it is still being listed, then delete the emptied
+/// directory tree with a single request
+pub fn remove_tree(
+    client: &StorageZoneClient,
+    path: &str,
+    concurrency: usize,
+) -> anyhow::Result<usize> {
+    let deleted = delete_streamed(client, concurrency, |send_work| {
+        client.for_each_file(path, &[], concurrency, |fi| {
+            send_work.send(client.remote_name(&fi))?;
+            Ok(())
+        })
+    })?;
+    client.delete_dir(path)?;
+    Ok(deleted)
+}
+
+pub fn remove(
+    client: &StorageZoneClient,
+    path: &str,
+    recursive: bool,
+    concurrency: usize,
+) -> anyhow::Result<()> {
+    if recursive {
+        let deleted = remove_tree(client, path, concurrency)?;
+        println!("Deleted {path} with {deleted} files");
+    } else {
+        client.delete_file(path)?;
+        println!("Deleted {path}");
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// The changes a sync would make, saved to compare against a later plan
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SavedPlan {
+    pub storage_zone: String,
+    pub paths: Vec<String>,
+    pub put: BTreeSet<String>,
+    pub delete: BTreeSet<String>,
+}
+
+impl SavedPlan {
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let plan = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        serde_json::from_str(&plan).with_context(|| format!("Invalid plan in {}", path.display()))
+    }
+
+    /// Tasks added since `previous` with +, tasks no longer pending with -
+    pub fn changes_since(&self, previous: &SavedPlan) -> Vec<String> {
+        let mut changes = vec![];
+        for (action, now, before) in [
+            ("put", &self.put, &previous.put),
+            ("delete", &self.delete, &previous.delete),
+        ] {
+            changes.extend(
+                now.difference(before)
+                    .map(|name| format!("+ {action} {name}")),
+            );
+            changes.extend(
+                before
+                    .difference(now)
+                    .map(|name| format!("- {action} {name}")),
+            );
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(put: &[&str], delete: &[&str]) -> SavedPlan {
+        SavedPlan {
+            storage_zone: "site".to_string(),
+            paths: vec!["/".to_string()],
+            put: put.iter().map(|name| name.to_string()).collect(),
+            delete: delete.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn lists_added_and_removed_tasks() {
+        let before = plan(&["index.html", "app.js"], &["old.css"]);
+        let after = plan(&["index.html", "app.css"], &["old.css", "app.js"]);
+        assert_eq!(
+            after.changes_since(&before),
+            vec!["+ put app.css", "- put app.js", "+ delete app.js",]
+        );
+        assert!(after.changes_since(&after).is_empty());
+    }
+}
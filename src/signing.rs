@@ -0,0 +1,240 @@
+use crate::api::{ReadOnly, StorageZoneClient};
+use crate::error::ThumperError;
+use crate::history::THUMPER_DIR;
+use crate::planning::PathRules;
+use crate::sync_job::within;
+use anyhow::{Context, anyhow};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const MANIFEST_FILE: &str = ".thumper/deploy-manifest.json";
+pub const SIGNATURE_FILE: &str = ".thumper/deploy-manifest.sig";
+
+/// What the last sync left in the zone, signed so tampering with the zone can be detected
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeployManifest {
+    /// Paths in the zone that were synced
+    pub paths: Vec<String>,
+    /// Prefixes or glob patterns inside the paths that the sync did not manage
+    pub unmanaged: Vec<String>,
+    /// SHA256 of each synced file, by name in the zone
+    pub files: BTreeMap<String, String>,
+}
+
+fn read_hex_key(path: &Path) -> anyhow::Result<[u8; 32]> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Unable to read {}", path.display()))?;
+    parse_hex_key(&content)
+}
+
+fn parse_hex_key(key: &str) -> anyhow::Result<[u8; 32]> {
+    let mut bytes = [0; 32];
+    hex::decode_to_slice(key.trim(), &mut bytes)
+        .map_err(|_| anyhow!("Expected an ed25519 key as 64 hex digits"))?;
+    Ok(bytes)
+}
+
+/// Load a signing key from a file holding its 32 byte secret seed as hex
+pub fn load_signing_key(path: &Path) -> anyhow::Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&read_hex_key(path)?))
+}
+
+/// Replace what `previous` signed below the paths of `ours`, keeping what syncs of other paths
+/// signed
+fn merge(mut previous: DeployManifest, ours: DeployManifest) -> DeployManifest {
+    previous.files.retain(|name, _| !within(&ours.paths, name));
+    previous.files.extend(
+        ours.files
+            .into_iter()
+            .filter(|(name, _)| within(&ours.paths, name)),
+    );
+    for path in ours.paths {
+        if !previous.paths.contains(&path) {
+            previous.paths.push(path);
+        }
+    }
+    for rule in ours.unmanaged {
+        if !previous.unmanaged.contains(&rule) {
+            previous.unmanaged.push(rule);
+        }
+    }
+    previous
+}
+
+/// Upload the manifest and its signature, merged into the one already in the zone when it was
+/// signed by the same key. Call while holding the lock on `.thumper/`.
+pub fn publish(
+    client: &StorageZoneClient,
+    manifest: DeployManifest,
+    key: &SigningKey,
+) -> anyhow::Result<()> {
+    let previous = client.read_bytes(MANIFEST_FILE).ok().and_then(|content| {
+        let signature = client.read_file(SIGNATURE_FILE).ok()?;
+        verified_manifest(&content, &signature, &key.verifying_key()).ok()
+    });
+    let manifest = match previous {
+        Some(previous) => merge(previous, manifest),
+        None => manifest,
+    };
+    let content = serde_json::to_vec_pretty(&manifest)?;
+    let signature = key.sign(&content);
+    client.put_file(MANIFEST_FILE, content, Some("application/json"))?;
+    client.put_file(
+        SIGNATURE_FILE,
+        hex::encode(signature.to_bytes()).into_bytes(),
+        Some("text/plain"),
+    )?;
+    eprintln!(
+        "Signed deploy manifest, verify with public key {}",
+        hex::encode(key.verifying_key().to_bytes())
+    );
+    Ok(())
+}
+
+/// Check the signature of the manifest content, then parse it
+fn verified_manifest(
+    content: &[u8],
+    signature: &str,
+    key: &VerifyingKey,
+) -> anyhow::Result<DeployManifest> {
+    let mut bytes = [0; 64];
+    hex::decode_to_slice(signature.trim(), &mut bytes).context("Malformed signature")?;
+    key.verify(content, &Signature::from_bytes(&bytes))
+        .map_err(|_| anyhow!("The deploy manifest signature does not match the public key"))?;
+    Ok(serde_json::from_slice(content)?)
+}
+
+/// Differences between the manifest and what the zone holds
+fn compare(manifest: &DeployManifest, zone: &BTreeMap<String, String>) -> Vec<String> {
+    let mut problems = vec![];
+    for (name, expected) in &manifest.files {
+        match zone.get(name) {
+            None => problems.push(format!("{name}: missing")),
+            Some(actual) if !actual.eq_ignore_ascii_case(expected) => problems.push(format!(
+                "{name}: checksum {actual} is not the signed {expected}"
+            )),
+            _ => {}
+        }
+    }
+    let unmanaged = PathRules::new(&manifest.unmanaged);
+    for name in zone.keys() {
+        if !unmanaged.matches(name) && !manifest.files.contains_key(name) {
+            problems.push(format!("{name}: not in the signed manifest"));
+        }
+    }
+    problems
+}
+
+/// Verify the signed manifest and that the zone contents match it
+pub fn verify(
+    client: &StorageZoneClient<ReadOnly>,
+    public_key: &str,
+    concurrency: usize,
+) -> anyhow::Result<()> {
+    let key = VerifyingKey::from_bytes(&parse_hex_key(public_key)?)
+        .map_err(|_| anyhow!("Invalid ed25519 public key"))?;
+    let content = client.read_bytes(MANIFEST_FILE)?;
+    let signature = client.read_file(SIGNATURE_FILE)?;
+    let manifest = verified_manifest(&content, &signature, &key)?;
+
+    let mut zone = BTreeMap::new();
+    let skip = [THUMPER_DIR.to_string()];
+    for path in &manifest.paths {
+        client.for_each_file(path, &skip, concurrency, |fi| {
+            let checksum = fi.checksum.clone().unwrap_or_default();
+            zone.insert(client.remote_name(&fi), checksum);
+            Ok(())
+        })?;
+    }
+    let problems = compare(&manifest, &zone);
+    if problems.is_empty() {
+        println!(
+            "Signature valid and {} files match the deploy manifest",
+            manifest.files.len()
+        );
+        Ok(())
+    } else {
+        Err(ThumperError::Integrity(format!(
+            "Zone does not match the signed deploy manifest:\n{}",
+            problems.join("\n")
+        ))
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_tampered_manifests() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let content = br#"{"paths":["/"],"unmanaged":[],"files":{}}"#;
+        let signature = hex::encode(key.sign(content).to_bytes());
+        assert!(verified_manifest(content, &signature, &key.verifying_key()).is_ok());
+        let tampered = br#"{"paths":["/"],"unmanaged":["a"],"files":{}}"#;
+        assert!(verified_manifest(tampered, &signature, &key.verifying_key()).is_err());
+    }
+
+    #[test]
+    fn compares_zone_contents() {
+        let manifest = DeployManifest {
+            paths: vec!["/".to_string()],
+            unmanaged: vec!["uploads/".to_string()],
+            files: BTreeMap::from([
+                ("index.html".to_string(), "aa".to_string()),
+                ("app.js".to_string(), "bb".to_string()),
+                ("gone.css".to_string(), "cc".to_string()),
+            ]),
+        };
+        let zone = BTreeMap::from([
+            ("index.html".to_string(), "AA".to_string()),
+            ("app.js".to_string(), "ff".to_string()),
+            ("uploads/cat.jpg".to_string(), "dd".to_string()),
+            ("evil.js".to_string(), "ee".to_string()),
+        ]);
+        assert_eq!(
+            compare(&manifest, &zone),
+            vec![
+                "app.js: checksum ff is not the signed bb",
+                "gone.css: missing",
+                "evil.js: not in the signed manifest",
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_manifests_of_other_paths() {
+        let files = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(name, checksum)| (name.to_string(), checksum.to_string()))
+                .collect()
+        };
+        let previous = DeployManifest {
+            paths: vec!["/docs/".to_string(), "/blog/".to_string()],
+            unmanaged: vec!["docs/uploads/".to_string()],
+            files: files(&[("docs/a", "aa"), ("docs/gone", "bb"), ("blog/b", "cc")]),
+        };
+        let ours = DeployManifest {
+            paths: vec!["/docs/".to_string(), "/shop/".to_string()],
+            unmanaged: vec!["shop/cart/".to_string()],
+            files: files(&[("docs/a", "dd"), ("shop/c", "ee")]),
+        };
+        assert_eq!(
+            merge(previous, ours),
+            DeployManifest {
+                paths: vec![
+                    "/docs/".to_string(),
+                    "/blog/".to_string(),
+                    "/shop/".to_string()
+                ],
+                unmanaged: vec!["docs/uploads/".to_string(), "shop/cart/".to_string()],
+                files: files(&[("docs/a", "dd"), ("blog/b", "cc"), ("shop/c", "ee")]),
+            }
+        );
+    }
+}
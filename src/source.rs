@@ -0,0 +1,349 @@
+use crate::archive::{ZipSource, open_tar};
+use crate::local_path;
+use anyhow::{Context, anyhow};
+use fxhash::FxHashMap;
+use std::collections::hash_map::Entry;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{fs, io};
+
+/// Where the files to sync come from
+pub trait FileSource: Sync {
+    /// Files below `root`, keyed by their name in the storage zone below `remote_root`
+    fn files_by_remote_name(&self, root: &str, remote_root: &str) -> anyhow::Result<RemoteNames>;
+
+    /// Content of a file returned by `files_by_remote_name`
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Identifies the underlying file when several paths may refer to it, like hard links
+    fn identity(&self, _path: &Path) -> Option<FileId> {
+        None
+    }
+
+    /// Directories below `root` that contain nothing at all
+    fn empty_dirs(&self, _root: &str) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(vec![])
+    }
+
+    /// The file on disk holding exactly what `read` returns, so large files can be hashed and
+    /// uploaded without reading all of them into memory
+    fn on_disk(&self, _path: &Path) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Local files keyed by their name in the storage zone
+#[derive(Debug, Default)]
+pub struct RemoteNames {
+    pub files: FxHashMap<String, PathBuf>,
+    /// Files whose name another file already took, along with that name
+    pub duplicates: Vec<(String, PathBuf)>,
+}
+
+impl RemoteNames {
+    /// Add a file, keeping the one already there if the name is taken
+    pub fn insert(&mut self, remote: String, local: PathBuf) {
+        match self.files.entry(remote) {
+            Entry::Occupied(taken) => self.duplicates.push((taken.key().clone(), local)),
+            Entry::Vacant(free) => {
+                free.insert(local);
+            }
+        }
+    }
+
+    /// The files, after warning about names several files map to, or failing on them if `strict`
+    pub fn unique(self, strict: bool) -> anyhow::Result<FxHashMap<String, PathBuf>> {
+        let described: Vec<_> = self
+            .duplicates
+            .iter()
+            .map(|(remote, dropped)| {
+                format!(
+                    "{} and {} both map to {remote}",
+                    self.files[remote].display(),
+                    dropped.display()
+                )
+            })
+            .collect();
+        if strict && !described.is_empty() {
+            return Err(anyhow!(
+                "Several local files map to the same name in the storage zone:\n  {}",
+                described.join("\n  ")
+            ));
+        }
+        for duplicate in described {
+            eprintln!("WARNING: {duplicate}, syncing the first");
+        }
+        Ok(self.files)
+    }
+}
+
+impl FromIterator<(String, PathBuf)> for RemoteNames {
+    fn from_iter<I: IntoIterator<Item = (String, PathBuf)>>(files: I) -> Self {
+        let mut names = RemoteNames::default();
+        for (remote, local) in files {
+            names.insert(remote, local);
+        }
+        names
+    }
+}
+
+/// Device, inode and size of a file
+pub type FileId = (u64, u64, u64);
+
+/// Files in a local directory
+pub struct Directory;
+
+impl FileSource for Directory {
+    fn files_by_remote_name(&self, root: &str, remote_root: &str) -> anyhow::Result<RemoteNames> {
+        local_path::files_by_remote_name(root, remote_root)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    #[cfg(unix)]
+    fn identity(&self, path: &Path) -> Option<FileId> {
+        use std::os::unix::fs::MetadataExt;
+        let meta = fs::metadata(path).ok()?;
+        Some((meta.dev(), meta.ino(), meta.size()))
+    }
+
+    fn empty_dirs(&self, root: &str) -> anyhow::Result<Vec<PathBuf>> {
+        local_path::empty_dirs(Path::new(root))
+    }
+
+    fn on_disk(&self, path: &Path) -> Option<PathBuf> {
+        Some(path.to_path_buf())
+    }
+}
+
+/// Adds an empty `marker` file, like `.keep`, to each empty directory of another source so
+/// the directory exists in the storage zone too
+pub struct Placeholders {
+    inner: Box<dyn FileSource>,
+    marker: String,
+}
+
+impl Placeholders {
+    pub fn new(inner: Box<dyn FileSource>, marker: String) -> Self {
+        Placeholders { inner, marker }
+    }
+
+    fn is_marker(&self, path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| name == self.marker.as_str())
+    }
+}
+
+impl FileSource for Placeholders {
+    fn files_by_remote_name(&self, root: &str, remote_root: &str) -> anyhow::Result<RemoteNames> {
+        let mut files = self.inner.files_by_remote_name(root, remote_root)?;
+        for dir in self.inner.empty_dirs(root)? {
+            let marker = dir.join(&self.marker);
+            let relative = marker
+                .strip_prefix(root)?
+                .to_str()
+                .context("Invalid utf8")?
+                .to_owned();
+            files.insert(remote_name(remote_root, &relative), marker);
+        }
+        Ok(files)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.inner.read(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound && self.is_marker(path) => Ok(vec![]),
+            result => result,
+        }
+    }
+
+    fn identity(&self, path: &Path) -> Option<FileId> {
+        self.inner.identity(path)
+    }
+
+    fn on_disk(&self, path: &Path) -> Option<PathBuf> {
+        self.inner.on_disk(path)
+    }
+}
+
+/// Digests of files already hashed during this run, so paths to the same file are hashed once
+#[derive(Default)]
+pub struct DigestMemo {
+    digests: Mutex<FxHashMap<FileId, [u8; 32]>>,
+}
+
+impl DigestMemo {
+    pub fn get_or_compute<F>(&self, id: Option<FileId>, compute: F) -> [u8; 32]
+    where
+        F: FnOnce() -> [u8; 32],
+    {
+        let Some(id) = id else {
+            return compute();
+        };
+        if let Some(digest) = self.digests.lock().expect("digest lock poisoned").get(&id) {
+            return *digest;
+        }
+        let digest = compute();
+        self.digests
+            .lock()
+            .expect("digest lock poisoned")
+            .insert(id, digest);
+        digest
+    }
+}
+
+/// Files kept in memory, keyed by their path relative to the synced root. Useful to deploy
+/// generated content without writing it to disk first.
+#[derive(Default)]
+pub struct MemorySource {
+    files: FxHashMap<String, Vec<u8>>,
+}
+
+impl MemorySource {
+    pub fn insert(&mut self, path: &str, content: Vec<u8>) {
+        self.files.insert(path.to_string(), content);
+    }
+}
+
+impl FileSource for MemorySource {
+    fn files_by_remote_name(&self, root: &str, remote_root: &str) -> anyhow::Result<RemoteNames> {
+        Ok(self
+            .files
+            .keys()
+            .filter_map(|name| {
+                let relative = name.strip_prefix(root)?;
+                Some((remote_name(remote_root, relative), PathBuf::from(name)))
+            })
+            .collect())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let name = path.to_str().ok_or(io::ErrorKind::InvalidInput)?;
+        self.files
+            .get(name)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, name.to_string()))
+    }
+}
+
+/// Join the name of a file relative to the synced root onto the remote root
+pub fn remote_name(remote_root: &str, name: &str) -> String {
+    let remote_root = remote_root.trim_start_matches('/').trim_end_matches('/');
+    if remote_root.is_empty() {
+        name.to_string()
+    } else {
+        format!("{remote_root}/{name}")
+    }
+}
+
+/// Open `local_path` as a source, along with the root to sync from within it.
+/// Archives are read in place, their root is the top of the archive.
+pub fn open(local_path: &str) -> anyhow::Result<(Box<dyn FileSource>, String)> {
+    let path = Path::new(local_path);
+    let is_tar = [".tar", ".tar.gz", ".tgz"]
+        .iter()
+        .any(|ext| local_path.ends_with(ext));
+    if path.is_file() && local_path.ends_with(".zip") {
+        Ok((Box::new(ZipSource::open(path)?), String::new()))
+    } else if path.is_file() && is_tar {
+        Ok((Box::new(open_tar(path)?), String::new()))
+    } else {
+        Ok((Box::new(Directory), local_path.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_files_from_memory() {
+        let mut source = MemorySource::default();
+        source.insert("index.html", b"<html>".to_vec());
+        source.insert("docs/guide.html", b"guide".to_vec());
+
+        let docs = source
+            .files_by_remote_name("docs/", "/manual/")
+            .unwrap()
+            .files;
+        assert_eq!(docs.len(), 1);
+        assert_eq!(source.read(&docs["manual/guide.html"]).unwrap(), b"guide");
+        assert!(source.read(Path::new("missing.html")).is_err());
+    }
+
+    #[test]
+    fn reports_files_mapping_to_the_same_name() {
+        let names = || -> RemoteNames {
+            [
+                ("docs/a.html", "site/docs/a.html"),
+                ("docs/b.html", "site/docs/b.html"),
+                ("docs/a.html", "archive/docs/a.html"),
+            ]
+            .into_iter()
+            .map(|(remote, local)| (remote.to_string(), PathBuf::from(local)))
+            .collect()
+        };
+        assert_eq!(
+            names().duplicates,
+            vec![(
+                "docs/a.html".to_string(),
+                PathBuf::from("archive/docs/a.html")
+            )]
+        );
+        let err = names().unique(true).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("site/docs/a.html and archive/docs/a.html both map to docs/a.html")
+        );
+        let files = names().unique(false).unwrap();
+        assert_eq!(files["docs/a.html"], PathBuf::from("site/docs/a.html"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hashes_hard_links_once() {
+        let dir = std::env::temp_dir().join(format!("thumper-links-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (original, link) = (dir.join("logo.png"), dir.join("copy.png"));
+        fs::write(&original, b"png").unwrap();
+        fs::hard_link(&original, &link).unwrap();
+
+        let memo = DigestMemo::default();
+        assert_eq!(
+            memo.get_or_compute(Directory.identity(&original), || [1; 32]),
+            [1; 32]
+        );
+        assert_eq!(
+            memo.get_or_compute(Directory.identity(&link), || [2; 32]),
+            [1; 32]
+        );
+        assert_eq!(memo.get_or_compute(None, || [3; 32]), [3; 32]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn adds_markers_to_empty_directories() {
+        let dir = std::env::temp_dir().join(format!("thumper-keep-{}", std::process::id()));
+        fs::create_dir_all(dir.join("uploads/2024")).unwrap();
+        fs::create_dir_all(dir.join("empty")).unwrap();
+        fs::write(dir.join("index.html"), b"<html>").unwrap();
+        let root = format!("{}/", dir.display());
+
+        let source = Placeholders::new(Box::new(Directory), ".keep".to_string());
+        let files = source.files_by_remote_name(&root, "site").unwrap().files;
+        let mut names: Vec<_> = files.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "site/empty/.keep",
+                "site/index.html",
+                "site/uploads/2024/.keep"
+            ]
+        );
+        assert_eq!(source.read(&files["site/empty/.keep"]).unwrap(), b"");
+        assert!(source.read(&dir.join("missing.html")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+use crate::api::{FileInfo, StorageZoneClient};
+use crate::error::ThumperError;
+use crate::replication::parent_dir;
+use crate::source::FileSource;
+use anyhow::anyhow;
+use fxhash::FxHashMap;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+fn verify(expected: &[u8; 32], listed: Option<&FileInfo>) -> Result<(), String> {
+    let listed = listed.ok_or("missing from the listing")?;
+    let checksum = listed.checksum.as_deref().ok_or("no checksum reported")?;
+    if checksum.eq_ignore_ascii_case(&hex::encode(expected)) {
+        Ok(())
+    } else {
+        Err(format!("checksum {checksum} does not match what was sent"))
+    }
+}
+
+/// List `count` randomly chosen uploaded files again and compare their checksums to the content
+/// that was sent. `sent` maps remote names to the local files they were uploaded from.
+pub fn spot_check(
+    client: &StorageZoneClient,
+    source: &dyn FileSource,
+    sent: &FxHashMap<String, PathBuf>,
+    uploaded: &[String],
+    count: usize,
+) -> anyhow::Result<()> {
+    let picked =
+        rand::seq::index::sample(&mut rand::rng(), uploaded.len(), count.min(uploaded.len()));
+    let mut failures = vec![];
+    for index in picked {
+        let name = uploaded[index].as_str();
+        let local = sent
+            .get(name)
+            .ok_or_else(|| anyhow!("Don't know what was sent to {name}"))?;
+        let expected: [u8; 32] = Sha256::digest(source.read(local)?).into();
+        let listing = client.ls_dir(parent_dir(name))?;
+        let listed = listing
+            .iter()
+            .find(|fi| !fi.is_directory && client.remote_name(fi) == name);
+        if let Err(problem) = verify(&expected, listed) {
+            failures.push(format!("{name}: {problem}"));
+        }
+    }
+    if failures.is_empty() {
+        eprintln!(
+            "Spot check of {} uploaded files passed",
+            count.min(uploaded.len())
+        );
+        Ok(())
+    } else {
+        Err(ThumperError::Integrity(format!("Spot check failed:\n{}", failures.join("\n"))).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed(checksum: Option<&str>) -> FileInfo {
+        FileInfo {
+            path: "/zone/docs/".to_string(),
+            object_name: "index.html".to_string(),
+            checksum: checksum.map(str::to_string),
+            is_directory: false,
+            replicated_zones: None,
+            length: 0,
+            last_changed: String::new(),
+        }
+    }
+
+    #[test]
+    fn compares_checksums_case_insensitively() {
+        let expected: [u8; 32] = Sha256::digest(b"hello").into();
+        let upper = hex::encode_upper(expected);
+        assert!(verify(&expected, Some(&listed(Some(&upper)))).is_ok());
+        assert!(verify(&expected, Some(&listed(Some("00")))).is_err());
+        assert!(verify(&expected, Some(&listed(None))).is_err());
+        assert!(verify(&expected, None).is_err());
+    }
+}
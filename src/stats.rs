@@ -0,0 +1,196 @@
+use crate::bench::format_bytes;
+use crate::bunny_api::BunnyApiClient;
+use crate::cli::StatsAction;
+use anyhow::anyhow;
+use chrono::{DateTime, SecondsFormat, Utc};
+use fxhash::FxHashMap;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The parts of the statistics API response thumper uses
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Statistics {
+    #[serde(default)]
+    pub total_requests_served: u64,
+    /// Percentage of requests served from cache, 0 to 100
+    #[serde(default)]
+    pub cache_hit_rate: f64,
+    /// Bytes served by edge location, keyed like `EU: Frankfurt, DE`
+    #[serde(default)]
+    pub geo_traffic_distribution: FxHashMap<String, u64>,
+}
+
+/// Traffic served to one country
+#[derive(Debug, PartialEq, Serialize)]
+pub struct GeoShare {
+    pub country: String,
+    pub region: String,
+    pub bytes: u64,
+    /// Share of all traffic, between 0 and 1
+    pub share: f64,
+}
+
+pub fn get_statistics(
+    api: &BunnyApiClient,
+    pullzone: u64,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> anyhow::Result<Statistics> {
+    let request = api.request(Method::GET, "statistics").query(&[
+        ("pullZone", pullzone.to_string()),
+        ("dateFrom", from.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        ("dateTo", to.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        ("hourly", "true".to_string()),
+    ]);
+    Ok(api.send(request)?.json()?)
+}
+
+/// Fail unless the cache served at least `min_hit_rate` (0.0 to 1.0) of the requests
+fn check_hit_rate(stats: &Statistics, min_hit_rate: f64) -> anyhow::Result<()> {
+    let hit_rate = stats.cache_hit_rate / 100.0;
+    if hit_rate < min_hit_rate {
+        Err(anyhow!(
+            "Cache hit rate {:.1}% is below {:.1}% over {} requests",
+            hit_rate * 100.0,
+            min_hit_rate * 100.0,
+            stats.total_requests_served
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Sum traffic by country, largest first. Locations look like `EU: Frankfurt, DE`.
+fn geo_breakdown(distribution: &FxHashMap<String, u64>) -> Vec<GeoShare> {
+    let mut by_country: FxHashMap<(String, String), u64> = FxHashMap::default();
+    for (location, bytes) in distribution {
+        let (region, place) = location.split_once(':').unwrap_or(("", location));
+        let country = place.rsplit(',').next().unwrap_or(place).trim();
+        *by_country
+            .entry((country.to_string(), region.trim().to_string()))
+            .or_default() += bytes;
+    }
+    let total = by_country.values().sum::<u64>().max(1) as f64;
+    let mut shares: Vec<_> = by_country
+        .into_iter()
+        .map(|((country, region), bytes)| GeoShare {
+            country,
+            region,
+            bytes,
+            share: bytes as f64 / total,
+        })
+        .collect();
+    shares.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| a.country.cmp(&b.country))
+    });
+    shares
+}
+
+fn geo(api: &BunnyApiClient, pullzone: u64, window: Duration, json: bool) -> anyhow::Result<()> {
+    let to = Utc::now();
+    let from = to - chrono::Duration::from_std(window)?;
+    let stats = get_statistics(api, pullzone, from, to)?;
+    let shares = geo_breakdown(&stats.geo_traffic_distribution);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&shares)?);
+    } else {
+        for share in shares {
+            println!(
+                "{:<4} {:<4} {:>12} {:>6.1}%",
+                share.country,
+                share.region,
+                format_bytes(share.bytes as f64),
+                share.share * 100.0
+            );
+        }
+    }
+    Ok(())
+}
+
+fn gate(
+    api: &BunnyApiClient,
+    pullzone: u64,
+    min_hit_rate: f64,
+    window: Duration,
+) -> anyhow::Result<()> {
+    if !(0.0..=1.0).contains(&min_hit_rate) {
+        return Err(anyhow!("--min-hit-rate must be between 0 and 1"));
+    }
+    let to = Utc::now();
+    let from = to - chrono::Duration::from_std(window)?;
+    let stats = get_statistics(api, pullzone, from, to)?;
+    if stats.total_requests_served == 0 {
+        eprintln!("WARNING: No requests to pull zone {pullzone} in the window, nothing to gate on");
+        return Ok(());
+    }
+    check_hit_rate(&stats, min_hit_rate)?;
+    println!(
+        "Cache hit rate {:.1}% over {} requests",
+        stats.cache_hit_rate, stats.total_requests_served
+    );
+    Ok(())
+}
+
+pub fn run(api: &BunnyApiClient, action: StatsAction) -> anyhow::Result<()> {
+    match action {
+        StatsAction::Gate {
+            pullzone,
+            min_hit_rate,
+            window,
+        } => gate(api, pullzone, min_hit_rate, window),
+        StatsAction::Geo {
+            pullzone,
+            window,
+            json,
+        } => geo(api, pullzone, window, json),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gates_on_hit_rate() {
+        let stats: Statistics = serde_json::from_str(
+            r#"{"TotalBandwidthUsed": 1024, "TotalRequestsServed": 200, "CacheHitRate": 87.5}"#,
+        )
+        .unwrap();
+        assert!(check_hit_rate(&stats, 0.85).is_ok());
+        assert!(check_hit_rate(&stats, 0.875).is_ok());
+        assert!(check_hit_rate(&stats, 0.9).is_err());
+    }
+
+    #[test]
+    fn breaks_down_traffic_by_country() {
+        let distribution: FxHashMap<String, u64> = [
+            ("EU: Frankfurt, DE".to_string(), 100),
+            ("EU: Berlin, DE".to_string(), 50),
+            ("NA: New York, US".to_string(), 50),
+        ]
+        .into_iter()
+        .collect();
+        let shares = geo_breakdown(&distribution);
+        assert_eq!(
+            shares,
+            vec![
+                GeoShare {
+                    country: "DE".to_string(),
+                    region: "EU".to_string(),
+                    bytes: 150,
+                    share: 0.75,
+                },
+                GeoShare {
+                    country: "US".to_string(),
+                    region: "NA".to_string(),
+                    bytes: 50,
+                    share: 0.25,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,491 @@
+use crate::bench::{format_bytes, parse_size};
+use crate::bunny_api::BunnyApiClient;
+use crate::cli::StreamAction;
+use anyhow::{Context, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::Method;
+use reqwest::blocking::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, thread};
+
+const STREAM_BASE: &str = "https://video.bunnycdn.com";
+const TUS_ENDPOINT: &str = "https://video.bunnycdn.com/tusupload";
+/// How many times a failing chunk is retried from the offset the server reports
+const CHUNK_ATTEMPTS: usize = 4;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct VideoLibrary {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub video_count: u64,
+    #[serde(default)]
+    pub storage_usage: u64,
+    api_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Video {
+    pub guid: String,
+    pub title: String,
+    #[serde(default)]
+    pub status: u8,
+    #[serde(default)]
+    pub encode_progress: u32,
+    #[serde(default)]
+    pub length: u64,
+    #[serde(default)]
+    pub storage_size: u64,
+    #[serde(default)]
+    pub date_uploaded: Option<String>,
+}
+
+impl Video {
+    pub fn status_name(&self) -> &'static str {
+        match self.status {
+            0 => "created",
+            1 => "uploaded",
+            2 => "processing",
+            3 => "transcoding",
+            4 => "finished",
+            5 => "error",
+            6 => "upload-failed",
+            _ => "unknown",
+        }
+    }
+}
+
+/// The Stream API pages with its own camelCase envelope
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VideoPage {
+    total_items: u64,
+    items: Vec<Video>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateVideo<'a> {
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collection_id: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatedVideo {
+    guid: String,
+}
+
+/// Written next to the uploaded file so an interrupted upload can continue where it left off
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct UploadState {
+    library_id: u64,
+    video_id: String,
+    upload_url: String,
+    size: u64,
+}
+
+fn state_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".thumper-upload.json");
+    PathBuf::from(name)
+}
+
+fn load_state(file: &Path, library_id: u64, size: u64) -> Option<UploadState> {
+    let content = fs::read(state_path(file)).ok()?;
+    let state: UploadState = serde_json::from_slice(&content).ok()?;
+    (state.library_id == library_id && state.size == size).then_some(state)
+}
+
+/// The TUS endpoint is authenticated with a short lived signature instead of the library key
+fn signature(library_id: u64, library_key: &str, expires: u64, video_id: &str) -> String {
+    let digest = Sha256::digest(format!("{library_id}{library_key}{expires}{video_id}"));
+    hex::encode(digest)
+}
+
+fn upload_metadata(title: &str, file_type: &str) -> String {
+    format!(
+        "filetype {},title {}",
+        STANDARD.encode(file_type),
+        STANDARD.encode(title)
+    )
+}
+
+struct TusUpload<'a> {
+    api: &'a BunnyApiClient,
+    library_id: u64,
+    library_key: &'a str,
+    video_id: &'a str,
+}
+
+impl TusUpload<'_> {
+    fn signed(&self, request: RequestBuilder) -> RequestBuilder {
+        let expires = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+            + 3600;
+        request
+            .header(
+                "AuthorizationSignature",
+                signature(self.library_id, self.library_key, expires, self.video_id),
+            )
+            .header("AuthorizationExpire", expires.to_string())
+            .header("VideoId", self.video_id)
+            .header("LibraryId", self.library_id.to_string())
+            .header("Tus-Resumable", "1.0.0")
+    }
+
+    fn create(&self, size: u64, metadata: String) -> anyhow::Result<String> {
+        let request = self
+            .signed(self.api.request(Method::POST, TUS_ENDPOINT))
+            .header("Upload-Length", size.to_string())
+            .header("Upload-Metadata", metadata);
+        let response = self.api.send(request)?;
+        let location = response
+            .headers()
+            .get("Location")
+            .and_then(|l| l.to_str().ok())
+            .context("TUS upload was created without a Location")?;
+        if location.starts_with("https://") {
+            Ok(location.to_string())
+        } else {
+            Ok(format!(
+                "{STREAM_BASE}/{}",
+                location.trim_start_matches('/')
+            ))
+        }
+    }
+
+    fn offset(&self, upload_url: &str) -> anyhow::Result<u64> {
+        let response = self
+            .api
+            .send(self.signed(self.api.request(Method::HEAD, upload_url)))?;
+        upload_offset(&response)
+    }
+
+    fn patch(&self, upload_url: &str, offset: u64, chunk: Vec<u8>) -> anyhow::Result<u64> {
+        let request = self
+            .signed(self.api.request(Method::PATCH, upload_url))
+            .header("Upload-Offset", offset.to_string())
+            .header("Content-Type", "application/offset+octet-stream")
+            .body(chunk);
+        upload_offset(&self.api.send(request)?)
+    }
+}
+
+fn upload_offset(response: &reqwest::blocking::Response) -> anyhow::Result<u64> {
+    response
+        .headers()
+        .get("Upload-Offset")
+        .and_then(|o| o.to_str().ok())
+        .and_then(|o| o.parse().ok())
+        .context("Response is missing Upload-Offset")
+}
+
+pub fn list_libraries(account: &BunnyApiClient) -> anyhow::Result<Vec<VideoLibrary>> {
+    Ok(account.get_all("videolibrary")?)
+}
+
+pub fn list_videos(stream: &BunnyApiClient, library_id: u64) -> anyhow::Result<Vec<Video>> {
+    let mut videos = vec![];
+    for page in 1.. {
+        let response: VideoPage = stream
+            .send(
+                stream
+                    .request(Method::GET, &format!("library/{library_id}/videos"))
+                    .query(&[("page", page), ("itemsPerPage", 100)]),
+            )?
+            .json()?;
+        let empty = response.items.is_empty();
+        videos.extend(response.items);
+        if empty || videos.len() as u64 >= response.total_items {
+            break;
+        }
+    }
+    Ok(videos)
+}
+
+pub fn get_video(stream: &BunnyApiClient, library_id: u64, video: &str) -> anyhow::Result<Video> {
+    Ok(stream.get(&format!("library/{library_id}/videos/{video}"))?)
+}
+
+pub fn delete_video(stream: &BunnyApiClient, library_id: u64, video: &str) -> anyhow::Result<()> {
+    Ok(stream.delete(&format!("library/{library_id}/videos/{video}"))?)
+}
+
+fn playback_urls(library_id: u64, video: &str) -> [String; 2] {
+    [
+        format!("https://iframe.mediadelivery.net/embed/{library_id}/{video}"),
+        format!("https://iframe.mediadelivery.net/play/{library_id}/{video}"),
+    ]
+}
+
+fn describe_video(video: &Video) -> String {
+    let progress = if video.status == 4 {
+        String::new()
+    } else {
+        format!(" {}%", video.encode_progress)
+    };
+    format!(
+        "{}\t{}\t{}{progress}\t{}s\t{}",
+        video.guid,
+        video.title,
+        video.status_name(),
+        video.length,
+        format_bytes(video.storage_size as f64)
+    )
+}
+
+fn library_key(
+    account: Option<&BunnyApiClient>,
+    library_key: Option<String>,
+    library_id: u64,
+) -> anyhow::Result<String> {
+    match (library_key, account) {
+        (Some(key), _) => Ok(key),
+        (None, Some(account)) => {
+            let library: VideoLibrary = account.get(&format!("videolibrary/{library_id}"))?;
+            Ok(library.api_key)
+        }
+        (None, None) => Err(anyhow!(
+            "No library key provided with --library-key or THUMPER_STREAM_KEY, and no API key to look it up with"
+        )),
+    }
+}
+
+pub fn upload(
+    stream: &BunnyApiClient,
+    library_id: u64,
+    library_key: &str,
+    file: &Path,
+    title: Option<String>,
+    collection: Option<String>,
+    chunk_size: usize,
+) -> anyhow::Result<String> {
+    let size = fs::metadata(file)
+        .with_context(|| format!("Unable to read {}", file.display()))?
+        .len();
+    let title = title.unwrap_or_else(|| {
+        file.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+
+    let state = match load_state(file, library_id, size) {
+        Some(state) => {
+            eprintln!("Resuming upload of video {}", state.video_id);
+            state
+        }
+        None => {
+            let created: CreatedVideo = stream
+                .post(
+                    &format!("library/{library_id}/videos"),
+                    &CreateVideo {
+                        title: &title,
+                        collection_id: collection.as_deref(),
+                    },
+                )?
+                .json()?;
+            let tus = TusUpload {
+                api: stream,
+                library_id,
+                library_key,
+                video_id: &created.guid,
+            };
+            let file_type = mime_guess::from_path(file)
+                .first_raw()
+                .unwrap_or("video/mp4");
+            let upload_url = tus.create(size, upload_metadata(&title, file_type))?;
+            let state = UploadState {
+                library_id,
+                video_id: created.guid,
+                upload_url,
+                size,
+            };
+            fs::write(state_path(file), serde_json::to_vec(&state)?)?;
+            state
+        }
+    };
+
+    let tus = TusUpload {
+        api: stream,
+        library_id,
+        library_key,
+        video_id: &state.video_id,
+    };
+    let mut reader = File::open(file)?;
+    let mut offset = tus.offset(&state.upload_url)?;
+    let mut failures = 0;
+    while offset < size {
+        let mut chunk = vec![0; chunk_size.min((size - offset) as usize)];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut chunk)?;
+        match tus.patch(&state.upload_url, offset, chunk) {
+            Ok(new_offset) => {
+                offset = new_offset;
+                failures = 0;
+                eprint!(
+                    "\r{} / {} ({:.0}%)",
+                    format_bytes(offset as f64),
+                    format_bytes(size as f64),
+                    100.0 * offset as f64 / size.max(1) as f64
+                );
+            }
+            Err(err) if failures + 1 < CHUNK_ATTEMPTS => {
+                failures += 1;
+                eprintln!("\nWARNING: chunk at {offset} failed, resuming: {err}");
+                thread::sleep(std::time::Duration::from_secs(failures as u64));
+                offset = tus.offset(&state.upload_url)?;
+            }
+            Err(err) => {
+                return Err(err.context(format!(
+                    "Upload interrupted, run the same command again to resume from {offset}"
+                )));
+            }
+        }
+    }
+    eprintln!();
+    let _ = fs::remove_file(state_path(file));
+    Ok(state.video_id)
+}
+
+fn stream_client(
+    account: Option<&BunnyApiClient>,
+    library_key_arg: Option<String>,
+    library_id: u64,
+    connect: impl Fn(String) -> BunnyApiClient,
+) -> anyhow::Result<BunnyApiClient> {
+    let key = library_key(account, library_key_arg, library_id)?;
+    Ok(connect(key).with_base_url(STREAM_BASE))
+}
+
+/// Carry out `action`, with `connect` making clients for the library keys
+pub fn run(
+    account: Option<&BunnyApiClient>,
+    library_key_arg: Option<String>,
+    connect: impl Fn(String) -> BunnyApiClient,
+    action: StreamAction,
+) -> anyhow::Result<()> {
+    match action {
+        StreamAction::List { library: None } => {
+            let account = account.context(
+                "Listing libraries needs an API key provided with --api-key or THUMPER_API_KEY",
+            )?;
+            for library in list_libraries(account)? {
+                println!(
+                    "{}\t{}\t{} videos\t{}",
+                    library.id,
+                    library.name,
+                    library.video_count,
+                    format_bytes(library.storage_usage as f64)
+                );
+            }
+        }
+        StreamAction::List {
+            library: Some(library),
+        } => {
+            let stream = stream_client(account, library_key_arg, library, &connect)?;
+            for video in list_videos(&stream, library)? {
+                println!("{}", describe_video(&video));
+            }
+        }
+        StreamAction::Info { library, video } => {
+            let stream = stream_client(account, library_key_arg, library, &connect)?;
+            let video = get_video(&stream, library, &video)?;
+            println!("{}", describe_video(&video));
+            if let Some(uploaded) = &video.date_uploaded {
+                println!("uploaded {uploaded}");
+            }
+            for url in playback_urls(library, &video.guid) {
+                println!("{url}");
+            }
+        }
+        StreamAction::Delete { library, video } => {
+            let stream = stream_client(account, library_key_arg, library, &connect)?;
+            delete_video(&stream, library, &video)?;
+            println!("Deleted video {video} from library {library}");
+        }
+        StreamAction::Upload {
+            library,
+            file,
+            title,
+            collection,
+            chunk_size,
+        } => {
+            let chunk_size = parse_size(&chunk_size)?;
+            if chunk_size == 0 {
+                return Err(anyhow!("--chunk-size must be larger than 0"));
+            }
+            let key = library_key(account, library_key_arg, library)?;
+            let stream = connect(key.clone()).with_base_url(STREAM_BASE);
+            let video_id = upload(&stream, library, &key, &file, title, collection, chunk_size)?;
+            println!("Uploaded {} as video {video_id}", file.display());
+            for url in playback_urls(library, &video_id) {
+                println!("{url}");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_videos() {
+        let page: VideoPage = serde_json::from_str(
+            r#"{"totalItems": 1, "currentPage": 1, "itemsPerPage": 100, "items": [
+                {"videoLibraryId": 1, "guid": "abc-def", "title": "intro", "status": 3,
+                 "encodeProgress": 40, "length": 61, "storageSize": 2048,
+                 "dateUploaded": "2025-04-15T16:52:33.824"}
+            ]}"#,
+        )
+        .unwrap();
+        let video = &page.items[0];
+        assert_eq!(video.status_name(), "transcoding");
+        assert_eq!(
+            describe_video(video),
+            "abc-def\tintro\ttranscoding 40%\t61s\t2.0 KiB"
+        );
+    }
+
+    #[test]
+    fn signs_uploads() {
+        let expected = hex::encode(Sha256::digest("42secret1700000000abc-def"));
+        assert_eq!(signature(42, "secret", 1700000000, "abc-def"), expected);
+    }
+
+    #[test]
+    fn encodes_metadata() {
+        assert_eq!(
+            upload_metadata("intro", "video/mp4"),
+            "filetype dmlkZW8vbXA0,title aW50cm8="
+        );
+    }
+
+    #[test]
+    fn resumes_only_matching_uploads() {
+        let file = std::env::temp_dir().join(format!("thumper-video-{}.mp4", std::process::id()));
+        let state = UploadState {
+            library_id: 1,
+            video_id: "abc".to_string(),
+            upload_url: "https://video.bunnycdn.com/tusupload/xyz".to_string(),
+            size: 100,
+        };
+        fs::write(state_path(&file), serde_json::to_vec(&state).unwrap()).unwrap();
+        assert_eq!(load_state(&file, 1, 100), Some(state));
+        assert_eq!(load_state(&file, 1, 101), None);
+        assert_eq!(load_state(&file, 2, 100), None);
+        fs::remove_file(state_path(&file)).unwrap();
+    }
+}
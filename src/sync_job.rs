@@ -0,0 +1,181 @@
+use crate::api::{DEFAULT_ENDPOINT, StorageZoneClient};
+
+/// A local directory synced to a remote subtree of the storage zone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncScope {
+    pub path: String,
+    pub local_path: String,
+}
+
+/// A storage zone and the local directories to sync into it
+pub struct SyncJob {
+    pub client: StorageZoneClient,
+    pub scopes: Vec<SyncScope>,
+}
+
+pub fn normalize_path(mut path: String) -> String {
+    if path.ends_with("/") {
+        path
+    } else {
+        path.push('/');
+        path
+    }
+}
+
+/// Whether `name` in the zone lies below one of the synced `paths`
+pub fn within(paths: &[String], name: &str) -> bool {
+    paths
+        .iter()
+        .any(|path| name.starts_with(path.trim_start_matches('/')))
+}
+
+/// Like [`normalize_path`], but an empty root is the top of an archive and stays empty
+pub fn normalize_root(local_path: String) -> String {
+    if local_path.is_empty() {
+        local_path
+    } else {
+        normalize_path(local_path)
+    }
+}
+
+/// A single path syncs `local_path` itself, several paths sync the matching subdirectories of it
+fn sync_scopes(local_path: String, paths: Vec<String>) -> Vec<SyncScope> {
+    let local_path = normalize_root(local_path);
+    if let [path] = paths.as_slice() {
+        return vec![SyncScope {
+            path: normalize_path(path.clone()),
+            local_path,
+        }];
+    }
+    paths
+        .into_iter()
+        .map(|path| {
+            let path = normalize_path(path);
+            SyncScope {
+                local_path: normalize_path(format!("{local_path}{}", path.trim_start_matches('/'))),
+                path,
+            }
+        })
+        .collect()
+}
+
+impl SyncJob {
+    /// Sync into `storage_zone`, authenticating with its password
+    pub fn builder(storage_zone: &str, access_key: &str) -> SyncJobBuilder {
+        SyncJobBuilder {
+            storage_zone: storage_zone.to_string(),
+            access_key: access_key.to_string(),
+            key_origin: "--access-key",
+            fallback_keys: vec![],
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            local_path: ".".to_string(),
+            paths: vec![],
+        }
+    }
+}
+
+/// Builds a [`SyncJob`], syncing the current directory to the top of the zone by default
+pub struct SyncJobBuilder {
+    storage_zone: String,
+    access_key: String,
+    key_origin: &'static str,
+    fallback_keys: Vec<String>,
+    endpoint: String,
+    local_path: String,
+    paths: Vec<String>,
+}
+
+impl SyncJobBuilder {
+    /// Storage API endpoint of the zone's primary region, like ny.storage.bunnycdn.com
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    /// Where the access key came from, like a flag or environment variable, for error messages
+    pub fn key_origin(mut self, origin: &'static str) -> Self {
+        self.key_origin = origin;
+        self
+    }
+
+    /// Keys to try in order when bunny.net rejects the access key, like during key rotation
+    pub fn fallback_keys(mut self, keys: Vec<String>) -> Self {
+        self.fallback_keys = keys;
+        self
+    }
+
+    /// Local directory to sync from, or the root within an archive
+    pub fn local_path(mut self, local_path: &str) -> Self {
+        self.local_path = local_path.to_string();
+        self
+    }
+
+    /// Sync into this directory of the zone. Call several times to sync only those subtrees,
+    /// each from the matching subdirectory of the local path.
+    pub fn path(mut self, path: &str) -> Self {
+        self.paths.push(path.to_string());
+        self
+    }
+
+    pub fn build(self) -> SyncJob {
+        let paths = if self.paths.is_empty() {
+            vec!["/".to_string()]
+        } else {
+            self.paths
+        };
+        let client = StorageZoneClient::new(self.access_key, self.endpoint, self.storage_zone)
+            .with_key_origin(self.key_origin)
+            .with_fallback_keys(self.fallback_keys);
+        SyncJob {
+            client,
+            scopes: sync_scopes(self.local_path, paths),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_scopes_for_each_path() {
+        let job = SyncJob::builder("docs", "secret")
+            .local_path("public")
+            .build();
+        assert_eq!(
+            job.scopes,
+            vec![SyncScope {
+                path: "/".to_string(),
+                local_path: "public/".to_string(),
+            }]
+        );
+        let job = SyncJob::builder("docs", "secret")
+            .local_path("public")
+            .path("/en")
+            .path("/de/")
+            .build();
+        assert_eq!(
+            job.scopes,
+            vec![
+                SyncScope {
+                    path: "/en/".to_string(),
+                    local_path: "public/en/".to_string(),
+                },
+                SyncScope {
+                    path: "/de/".to_string(),
+                    local_path: "public/de/".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_names_below_paths() {
+        let paths = ["/en/".to_string(), "de/".to_string()];
+        assert!(within(&paths, "en/index.html"));
+        assert!(within(&paths, "de/a/b.css"));
+        assert!(!within(&paths, "fr/index.html"));
+        assert!(!within(&paths, "english.html"));
+        assert!(within(&["/".to_string()], "anything"));
+    }
+}
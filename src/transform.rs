@@ -0,0 +1,141 @@
+use crate::planning::{glob, matches_glob};
+use crate::source::{FileId, FileSource, RemoteNames};
+use anyhow::anyhow;
+use globset::GlobMatcher;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Run `command` through the shell with `input` on stdin, returning what it writes to stdout
+pub fn run_command(command: &str, input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut child = if cfg!(windows) {
+        Command::new("cmd")
+            .args(["/C", command])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?
+    } else {
+        Command::new("sh")
+            .args(["-c", command])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?
+    };
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let output = thread::scope(|scope| {
+        // Feed stdin while reading stdout, so large files can't fill both pipes and deadlock
+        let writer = scope.spawn(move || stdin.write_all(input));
+        let output = child.wait_with_output()?;
+        match writer.join().expect("stdin writer panicked") {
+            // Commands may exit without reading all of their input
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(err),
+            _ => Ok(output),
+        }
+    })?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(io::Error::other(format!(
+            "{command} failed: {}",
+            output.status
+        )))
+    }
+}
+
+/// Pipes files matching a pattern through an external command, so the command's output is
+/// what gets compared against and uploaded to the storage zone
+pub struct Transforms {
+    inner: Box<dyn FileSource>,
+    rules: Vec<(GlobMatcher, String)>,
+}
+
+impl Transforms {
+    /// Rules map glob patterns to commands. When several patterns match, the longest applies.
+    pub fn new(inner: Box<dyn FileSource>, rules: &toml::Table) -> anyhow::Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|(pattern, command)| {
+                let command = command
+                    .as_str()
+                    .ok_or_else(|| anyhow!("The transform for {pattern} must be a command"))?;
+                Ok((glob(pattern)?, command.to_string()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Transforms { inner, rules })
+    }
+
+    fn command_for(&self, path: &Path) -> Option<&str> {
+        let path = path.to_str()?;
+        self.rules
+            .iter()
+            .filter(|(pattern, _)| matches_glob(pattern, path))
+            .max_by_key(|(pattern, _)| pattern.glob().glob().len())
+            .map(|(_, command)| command.as_str())
+    }
+}
+
+impl FileSource for Transforms {
+    fn files_by_remote_name(&self, root: &str, remote_root: &str) -> anyhow::Result<RemoteNames> {
+        self.inner.files_by_remote_name(root, remote_root)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let content = self.inner.read(path)?;
+        match self.command_for(path) {
+            Some(command) => run_command(command, &content),
+            None => Ok(content),
+        }
+    }
+
+    fn identity(&self, path: &Path) -> Option<FileId> {
+        // Links to one file under names matching different rules transform differently
+        match self.command_for(path) {
+            Some(_) => None,
+            None => self.inner.identity(path),
+        }
+    }
+
+    fn empty_dirs(&self, root: &str) -> anyhow::Result<Vec<PathBuf>> {
+        self.inner.empty_dirs(root)
+    }
+
+    fn on_disk(&self, path: &Path) -> Option<PathBuf> {
+        match self.command_for(path) {
+            Some(_) => None,
+            None => self.inner.on_disk(path),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::source::MemorySource;
+
+    #[test]
+    fn transforms_matching_files() {
+        let mut source = MemorySource::default();
+        source.insert("index.html", b"<p>hello</p>".to_vec());
+        source.insert("style.css", b"p {}".to_vec());
+        source.insert("app.min.js", b"min".to_vec());
+        let rules: toml::Table = toml::from_str(
+            "\"*.html\" = \"tr a-z A-Z\"\n\"*.js\" = \"exit 1\"\n\"*.min.js\" = \"cat\"",
+        )
+        .unwrap();
+        let source = Transforms::new(Box::new(source), &rules).unwrap();
+
+        assert_eq!(
+            source.read(Path::new("index.html")).unwrap(),
+            b"<P>HELLO</P>"
+        );
+        assert_eq!(source.read(Path::new("style.css")).unwrap(), b"p {}");
+        assert_eq!(source.read(Path::new("app.min.js")).unwrap(), b"min");
+    }
+
+    #[test]
+    fn fails_when_the_command_fails() {
+        assert!(run_command("exit 3", b"").is_err());
+        assert_eq!(run_command("cat", b"same").unwrap(), b"same");
+    }
+}
@@ -0,0 +1,183 @@
+use crate::api::StorageZoneClient;
+use crate::bench::format_bytes;
+use crate::error::ThumperError;
+use crate::history::THUMPER_DIR;
+use crate::local_history::history_path;
+use anyhow::Context;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// How much a storage zone held at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: String,
+    pub storage_zone: String,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Where the usage time series is kept
+pub enum UsageStore {
+    /// A file on this machine, shared by all zones
+    Local(PathBuf),
+    /// usage.jsonl in the thumper directory of the zone itself
+    Remote(Box<StorageZoneClient>),
+}
+
+/// THUMPER_USAGE, or usage.jsonl next to the local sync history
+pub fn local_usage_path() -> Option<PathBuf> {
+    match env::var("THUMPER_USAGE") {
+        Ok(path) => Some(PathBuf::from(path)),
+        Err(_) => Some(history_path()?.with_file_name("usage.jsonl")),
+    }
+}
+
+fn remote_usage_file() -> String {
+    format!("{THUMPER_DIR}usage.jsonl")
+}
+
+impl UsageStore {
+    fn read(&self) -> anyhow::Result<String> {
+        match self {
+            UsageStore::Local(path) if !path.exists() => Ok(String::new()),
+            UsageStore::Local(path) => fs::read_to_string(path)
+                .with_context(|| format!("Unable to read {}", path.display())),
+            UsageStore::Remote(client) => match client.read_file(&remote_usage_file()) {
+                Ok(series) => Ok(series),
+                Err(ThumperError::NotFound(_)) => Ok(String::new()),
+                Err(err) => Err(err.into()),
+            },
+        }
+    }
+
+    fn write(&self, series: String) -> anyhow::Result<()> {
+        match self {
+            UsageStore::Local(path) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(path, series)
+                    .with_context(|| format!("Unable to write {}", path.display()))
+            }
+            UsageStore::Remote(client) => Ok(client.put_file(
+                &remote_usage_file(),
+                series.into_bytes(),
+                Some("application/x-ndjson"),
+            )?),
+        }
+    }
+
+    /// Every recorded measurement of `storage_zone`, oldest first, optionally adding `record`
+    pub fn records(
+        &self,
+        storage_zone: &str,
+        record: Option<&UsageRecord>,
+    ) -> anyhow::Result<Vec<UsageRecord>> {
+        let mut series = self.read()?;
+        if let Some(record) = record {
+            if !series.is_empty() && !series.ends_with('\n') {
+                series.push('\n');
+            }
+            series.push_str(&serde_json::to_string(record)?);
+            series.push('\n');
+            self.write(series.clone())?;
+        }
+        Ok(parse(&series)
+            .into_iter()
+            .filter(|record| record.storage_zone == storage_zone)
+            .collect())
+    }
+}
+
+/// Parse the time series, skipping lines that don't parse
+fn parse(series: &str) -> Vec<UsageRecord> {
+    series
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Like format_bytes, with a sign
+fn format_change(bytes: i128) -> String {
+    let sign = if bytes < 0 { "-" } else { "+" };
+    format!("{sign}{}", format_bytes(bytes.unsigned_abs() as f64))
+}
+
+/// One line per measurement with the change since the one before, then the growth rate
+pub fn report(records: &[UsageRecord]) -> Vec<String> {
+    let mut lines = vec![];
+    let mut previous: Option<&UsageRecord> = None;
+    for record in records {
+        let mut line = format!(
+            "{}  {} files  {}",
+            record.timestamp,
+            record.files,
+            format_bytes(record.bytes as f64)
+        );
+        if let Some(previous) = previous {
+            line.push_str(&format!(
+                "  ({:+} files, {})",
+                record.files as i128 - previous.files as i128,
+                format_change(record.bytes as i128 - previous.bytes as i128)
+            ));
+        }
+        lines.push(line);
+        previous = Some(record);
+    }
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return lines;
+    };
+    let elapsed = DateTime::parse_from_rfc3339(&last.timestamp)
+        .and_then(|last| Ok(last - DateTime::parse_from_rfc3339(&first.timestamp)?));
+    if let Ok(elapsed) = elapsed
+        && elapsed.num_seconds() > 0
+    {
+        let days = elapsed.num_seconds() as f64 / 86400.0;
+        let grown = last.bytes as i128 - first.bytes as i128;
+        lines.push(format!(
+            "{:+} files and {} over {days:.1} days, about {} a day",
+            last.files as i128 - first.files as i128,
+            format_change(grown),
+            format_change((grown as f64 / days) as i128)
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: &str, files: u64, bytes: u64) -> UsageRecord {
+        UsageRecord {
+            timestamp: timestamp.to_string(),
+            storage_zone: "docs".to_string(),
+            files,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn reports_growth_between_measurements() {
+        let series = [
+            record("2026-10-01T00:00:00+00:00", 1000, 10 * 1024 * 1024),
+            record("2026-10-11T00:00:00+00:00", 1200, 30 * 1024 * 1024),
+        ]
+        .iter()
+        .map(|record| serde_json::to_string(record).unwrap() + "\n")
+        .collect::<String>();
+        let records = parse(&format!("not json\n{series}"));
+        assert_eq!(
+            report(&records),
+            vec![
+                "2026-10-01T00:00:00+00:00  1000 files  10.0 MiB",
+                "2026-10-11T00:00:00+00:00  1200 files  30.0 MiB  (+200 files, +20.0 MiB)",
+                "+200 files and +20.0 MiB over 10.0 days, about +2.0 MiB a day",
+            ]
+        );
+        assert_eq!(report(&records[..1]).len(), 1);
+    }
+}
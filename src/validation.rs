@@ -0,0 +1,185 @@
+use crate::planning::is_glob;
+use anyhow::anyhow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A problem with the sync arguments, found before talking to the storage zone
+#[derive(Debug, PartialEq)]
+pub enum Finding {
+    Warning(String),
+    Error(String),
+}
+
+/// A remote path as a prefix of remote names: no leading /, a trailing / unless it is the root
+fn scope_prefix(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    if path.is_empty() || path.ends_with('/') {
+        path.to_string()
+    } else {
+        format!("{path}/")
+    }
+}
+
+fn overlapping_paths(paths: &[String], findings: &mut Vec<Finding>) {
+    if paths.len() < 2 {
+        return;
+    }
+    for (i, path) in paths.iter().enumerate() {
+        for other in &paths[i + 1..] {
+            let (a, b) = (scope_prefix(path), scope_prefix(other));
+            if a.starts_with(&b) || b.starts_with(&a) {
+                findings.push(Finding::Error(format!(
+                    "--path {path} and --path {other} overlap"
+                )));
+            }
+        }
+    }
+}
+
+/// Paths are always synced as directories, so `docs/index.html` would become a directory
+fn file_like_paths(paths: &[String], findings: &mut Vec<Finding>) {
+    for path in paths.iter().filter(|path| !path.ends_with('/')) {
+        let name = path.rsplit('/').next().unwrap_or_default();
+        if name
+            .rsplit_once('.')
+            .is_some_and(|(stem, _)| !stem.is_empty())
+        {
+            findings.push(Finding::Warning(format!(
+                "--path {path} looks like a file, but is synced as the directory {}",
+                scope_prefix(path)
+            )));
+        }
+    }
+}
+
+/// Prefixes that can't match anything below the synced paths have no effect
+fn stray_prefixes(kind: &str, prefixes: &[String], paths: &[String], findings: &mut Vec<Finding>) {
+    for prefix in prefixes.iter().filter(|prefix| !is_glob(prefix)) {
+        let relative = prefix.trim_start_matches('/');
+        let inside = paths
+            .iter()
+            .map(|path| scope_prefix(path))
+            .any(|path| relative.starts_with(path.as_str()) || path.starts_with(relative));
+        if !inside {
+            findings.push(Finding::Warning(format!(
+                "--{kind} {prefix} is outside every --path and has no effect"
+            )));
+        }
+    }
+}
+
+/// Symlinks below `dir` leading back to `root` or one of its parents would be walked forever
+fn symlink_loops(root: &Path, dir: &Path, findings: &mut Vec<Finding>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if path.is_symlink() {
+            let target = fs::canonicalize(&path)?;
+            if root.starts_with(&target) {
+                findings.push(Finding::Error(format!(
+                    "{} links back to {}, so the local path contains itself",
+                    path.display(),
+                    target.display()
+                )));
+            }
+        } else {
+            symlink_loops(root, &path, findings)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn validate(
+    local_path: &str,
+    paths: &[String],
+    ignore: &[String],
+    protect: &[String],
+) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = vec![];
+    overlapping_paths(paths, &mut findings);
+    file_like_paths(paths, &mut findings);
+    stray_prefixes("ignore", ignore, paths, &mut findings);
+    stray_prefixes("protect", protect, paths, &mut findings);
+    let local = Path::new(local_path);
+    if local.is_dir() {
+        let root: PathBuf = fs::canonicalize(local)?;
+        symlink_loops(&root, local, &mut findings)?;
+    }
+    Ok(findings)
+}
+
+/// Print the warnings and fail on the errors
+pub fn check(findings: Vec<Finding>) -> anyhow::Result<()> {
+    let mut errors = vec![];
+    for finding in findings {
+        match finding {
+            Finding::Warning(warning) => eprintln!("WARNING: {warning}"),
+            Finding::Error(error) => errors.push(error),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid sync arguments:\n  {}",
+            errors.join("\n  ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_incoherent_paths_and_prefixes() {
+        let findings = validate(
+            "src",
+            &strings(&["docs/", "/docs/api", "blog/index.html"]),
+            &strings(&["docs/tmp/", "assets/"]),
+            &strings(&["blog/index.html/uploads/"]),
+        )
+        .unwrap();
+        assert_eq!(
+            findings,
+            vec![
+                Finding::Error("--path docs/ and --path /docs/api overlap".to_string()),
+                Finding::Warning(
+                    "--path blog/index.html looks like a file, but is synced as the directory blog/index.html/"
+                        .to_string()
+                ),
+                Finding::Warning(
+                    "--ignore assets/ is outside every --path and has no effect".to_string()
+                ),
+            ]
+        );
+        assert!(
+            validate("src", &strings(&["/"]), &strings(&["assets/"]), &[])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_local_paths_containing_themselves() {
+        let dir = std::env::temp_dir().join(format!("thumper-loop-{}", std::process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("docs/site")).unwrap();
+        let findings = validate(dir.to_str().unwrap(), &strings(&["/"]), &[], &[]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert!(
+            check(findings)
+                .unwrap_err()
+                .to_string()
+                .contains("so the local path contains itself")
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use crate::api::StorageZoneClient;
+use crate::error::ThumperError;
+
+/// Name of the `index`-th previous version of `path`, 1 being the most recent
+pub fn version_name(path: &str, index: usize) -> String {
+    format!("{path}.~{index}~")
+}
+
+/// Whether `name` is a previous version kept by `--keep-versions`
+pub fn is_version(name: &str) -> bool {
+    name.strip_suffix('~')
+        .and_then(|name| name.rsplit_once(".~"))
+        .is_some_and(|(_, index)| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Contents of `path`, or None if it isn't there
+fn read_if_present(client: &StorageZoneClient, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    match client.read_bytes(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(ThumperError::NotFound(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Copy the current remote `path` to its first version, shifting older versions up and dropping
+/// the one past `keep`. The storage API can't copy, so every version is downloaded and uploaded.
+pub fn keep_previous(client: &StorageZoneClient, path: &str, keep: usize) -> anyhow::Result<()> {
+    for index in (1..keep).rev() {
+        if let Some(content) = read_if_present(client, &version_name(path, index))? {
+            client.put_file(&version_name(path, index + 1), content, None)?;
+        }
+    }
+    if let Some(content) = read_if_present(client, path)? {
+        client.put_file(&version_name(path, 1), content, None)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_versions() {
+        assert_eq!(version_name("docs/index.html", 2), "docs/index.html.~2~");
+        assert!(is_version("docs/index.html.~2~"));
+        assert!(is_version("docs/index.html.~12~"));
+        assert!(!is_version("docs/index.html"));
+        assert!(!is_version("docs/backup~"));
+        assert!(!is_version("docs/index.html.~~"));
+        assert!(!is_version("docs/index.html.~a~"));
+    }
+}
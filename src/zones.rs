@@ -0,0 +1,149 @@
+use crate::api::{DEFAULT_ENDPOINT, StorageZoneClient};
+use crate::bunny_api::BunnyApiClient;
+use crate::cli::ZonesAction;
+use crate::usage::{UsageRecord, UsageStore, local_usage_path, report};
+use anyhow::{Context, anyhow};
+use reqwest::Method;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StorageZone {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub read_only_password: Option<String>,
+    /// Storage API endpoint of the zone's primary region
+    #[serde(default)]
+    pub storage_hostname: Option<String>,
+    #[serde(default)]
+    pub storage_used: u64,
+    #[serde(default)]
+    pub files_stored: u64,
+}
+
+/// A client for the storage API of the zone, using the password the account API returned
+pub fn storage_client(zone: StorageZone) -> anyhow::Result<StorageZoneClient> {
+    let password = zone.password.with_context(|| {
+        format!(
+            "bunny.net did not return the password of storage zone {}",
+            zone.id
+        )
+    })?;
+    let endpoint = zone
+        .storage_hostname
+        .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+    Ok(StorageZoneClient::new(password, endpoint, zone.name))
+}
+
+pub fn list_zones(api: &BunnyApiClient) -> anyhow::Result<Vec<StorageZone>> {
+    Ok(api.get("storagezone")?)
+}
+
+/// Look up a storage zone by numeric ID or name
+pub fn get_zone(api: &BunnyApiClient, zone: &str) -> anyhow::Result<StorageZone> {
+    let id = match zone.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => {
+            list_zones(api)?
+                .into_iter()
+                .find(|z| z.name == zone)
+                .ok_or_else(|| anyhow!("No storage zone named {zone}"))?
+                .id
+        }
+    };
+    Ok(api.get(&format!("storagezone/{id}"))?)
+}
+
+/// Generate a new password for the zone, or its read-only password, and return it
+pub fn reset_password(
+    api: &BunnyApiClient,
+    zone: &StorageZone,
+    read_only: bool,
+) -> anyhow::Result<String> {
+    let request = if read_only {
+        api.request(Method::POST, "storagezone/resetReadOnlyPassword")
+            .query(&[("id", zone.id)])
+    } else {
+        api.request(
+            Method::POST,
+            &format!("storagezone/{}/resetPassword", zone.id),
+        )
+    };
+    api.send(request)?;
+    let zone = get_zone(api, &zone.id.to_string())?;
+    let password = if read_only {
+        zone.read_only_password
+    } else {
+        zone.password
+    };
+    password.ok_or_else(|| anyhow!("The API did not return the new password of {}", zone.name))
+}
+
+pub fn run(api: &BunnyApiClient, action: ZonesAction) -> anyhow::Result<()> {
+    match action {
+        ZonesAction::ResetPassword { zone, read_only } => {
+            let zone = get_zone(api, &zone)?;
+            let password = reset_password(api, &zone, read_only)?;
+            eprintln!(
+                "Reset the {}password of storage zone {} ({})",
+                if read_only { "read-only " } else { "" },
+                zone.name,
+                zone.id
+            );
+            // Only the password on stdout, so it can be piped into a secret store
+            println!("{password}");
+        }
+        ZonesAction::Usage {
+            zone,
+            record,
+            remote,
+            file,
+        } => {
+            let zone = get_zone(api, &zone)?;
+            let measured = UsageRecord {
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                storage_zone: zone.name.clone(),
+                files: zone.files_stored,
+                bytes: zone.storage_used,
+            };
+            let name = zone.name.clone();
+            let store = if remote {
+                UsageStore::Remote(Box::new(
+                    storage_client(zone)?.with_key_origin("the account API"),
+                ))
+            } else {
+                let path = file
+                    .or_else(local_usage_path)
+                    .context("Unable to find a directory for the usage time series")?;
+                UsageStore::Local(path)
+            };
+            let mut records = store.records(&name, record.then_some(&measured))?;
+            if !record {
+                records.push(measured);
+            }
+            for line in report(&records) {
+                println!("{line}");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_storage_zone() {
+        let zone: StorageZone = serde_json::from_str(
+            r#"{"Id": 42, "Name": "docs", "Password": "secret", "ReadOnlyPassword": "peek", "Region": "DE"}"#,
+        )
+        .unwrap();
+        assert_eq!(zone.id, 42);
+        assert_eq!(zone.password.as_deref(), Some("secret"));
+        assert_eq!(zone.read_only_password.as_deref(), Some("peek"));
+    }
+}